@@ -0,0 +1,509 @@
+//! Derive macros for the `ToTerm` and `FromTerm` traits of ei-sys.
+//!
+//! The shape of the term is chosen by the `#[term(...)]` attribute on the type.
+//!
+//! * A struct with named fields is a map from the names of its fields, as atoms, to their values.
+//! * With `#[term(record = "person")]`, a struct is a record, that is the tuple
+//!   `{person, Field1, Field2, ...}` with the fields in declaration order.
+//! * With `#[term(elixir_struct = "Elixir.User")]`, a struct with named fields is an Elixir
+//!   struct, that is a map with the atom keys of the fields and the key `__struct__` mapped to the
+//!   given module.
+//! * A tuple struct is a tuple of its fields, except a struct with a single field, which is the
+//!   term of that field.
+//! * A unit struct is an atom, its name in snake case.
+//! * An enum is an atom for each of its unit variants and a tuple tagged with the name of the
+//!   variant for the others, such as `{variant, Field1, Field2}`. The names of variants are
+//!   converted to snake case.
+//! * With `#[term(atom)]`, an enum must only have unit variants, which are atoms.
+//!
+//! The name of a field or a variant can be changed with `#[term(rename = "...")]`.
+
+extern crate proc_macro;
+
+use proc_macro2::{Span, TokenStream};
+use quote::{format_ident, quote};
+use syn::{
+  parse_macro_input, parse_quote, spanned::Spanned, Data, DeriveInput, Fields, Generics, LitStr,
+};
+
+#[proc_macro_derive(ToTerm, attributes(term))]
+pub fn derive_to_term(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+  let input = parse_macro_input!(input as DeriveInput);
+  expand_to_term(&input)
+    .unwrap_or_else(syn::Error::into_compile_error)
+    .into()
+}
+
+#[proc_macro_derive(FromTerm, attributes(term))]
+pub fn derive_from_term(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+  let input = parse_macro_input!(input as DeriveInput);
+  expand_from_term(&input)
+    .unwrap_or_else(syn::Error::into_compile_error)
+    .into()
+}
+
+enum Shape {
+  Default,
+  Record(String),
+  ElixirStruct(String),
+  Atom,
+}
+
+fn container_shape(input: &DeriveInput) -> syn::Result<Shape> {
+  let mut shape = Shape::Default;
+  for attr in input.attrs.iter().filter(|a| a.path().is_ident("term")) {
+    attr.parse_nested_meta(|meta| {
+      if meta.path.is_ident("record") {
+        shape = Shape::Record(meta.value()?.parse::<LitStr>()?.value());
+      } else if meta.path.is_ident("elixir_struct") {
+        shape = Shape::ElixirStruct(meta.value()?.parse::<LitStr>()?.value());
+      } else if meta.path.is_ident("atom") {
+        shape = Shape::Atom;
+      } else {
+        return Err(meta.error("unsupported term attribute"));
+      }
+      Ok(())
+    })?;
+  }
+  Ok(shape)
+}
+
+/// Rejects the attributes that only apply to structs.
+fn check_enum_shape(shape: &Shape, name: &syn::Ident) -> syn::Result<()> {
+  match shape {
+    Shape::Record(_) => Err(syn::Error::new(
+      name.span(),
+      "#[term(record)] only applies to structs",
+    )),
+    Shape::ElixirStruct(_) => Err(syn::Error::new(
+      name.span(),
+      "#[term(elixir_struct)] only applies to structs with named fields",
+    )),
+    Shape::Default | Shape::Atom => Ok(()),
+  }
+}
+
+fn renamed(attrs: &[syn::Attribute], default: String) -> syn::Result<String> {
+  let mut name = default;
+  for attr in attrs.iter().filter(|a| a.path().is_ident("term")) {
+    attr.parse_nested_meta(|meta| {
+      if meta.path.is_ident("rename") {
+        name = meta.value()?.parse::<LitStr>()?.value();
+        Ok(())
+      } else {
+        Err(meta.error("unsupported term attribute"))
+      }
+    })?;
+  }
+  Ok(name)
+}
+
+/// Converts a name in camel case to snake case, treating a run of capitals as one word, so that
+/// `HTTPServer` becomes `http_server`.
+fn snake_case(name: &str) -> String {
+  let chars: Vec<char> = name.chars().collect();
+  let mut snake = String::new();
+  for (i, &c) in chars.iter().enumerate() {
+    if c.is_uppercase() && i > 0 {
+      let previous = chars[i - 1];
+      let next_is_lower = chars.get(i + 1).is_some_and(|c| c.is_lowercase());
+      if (!previous.is_uppercase() && previous != '_') || (previous.is_uppercase() && next_is_lower)
+      {
+        snake.push('_');
+      }
+    }
+    snake.extend(c.to_lowercase());
+  }
+  snake
+}
+
+fn add_bounds(generics: &Generics, bound: TokenStream) -> Generics {
+  let mut generics = generics.clone();
+  for param in generics.type_params_mut() {
+    param.bounds.push(parse_quote!(#bound));
+  }
+  generics
+}
+
+/// Returns the names of the fields as atoms, or their indices for tuple structs.
+fn field_names(fields: &Fields) -> syn::Result<Vec<String>> {
+  fields
+    .iter()
+    .enumerate()
+    .map(|(i, field)| {
+      let default = match &field.ident {
+        Some(ident) => ident.to_string(),
+        None => i.to_string(),
+      };
+      renamed(&field.attrs, default)
+    })
+    .collect()
+}
+
+fn expand_to_term(input: &DeriveInput) -> syn::Result<TokenStream> {
+  let shape = container_shape(input)?;
+  let name = &input.ident;
+  let generics = add_bounds(&input.generics, quote!(::ei_sys::ToTerm));
+  let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+
+  let body = match &input.data {
+    Data::Struct(data) => {
+      let bindings: Vec<_> = (0..data.fields.len())
+        .map(|i| format_ident!("field{}", i))
+        .collect();
+      let pattern = destructure(quote!(#name), &data.fields, &bindings);
+      let encode = encode_fields(&shape, &name.to_string(), &data.fields, &bindings)?;
+      quote! {
+        let #pattern = self;
+        #encode
+      }
+    }
+    Data::Enum(data) => {
+      check_enum_shape(&shape, name)?;
+      let mut arms = Vec::new();
+      for variant in &data.variants {
+        let ident = &variant.ident;
+        let tag = renamed(&variant.attrs, snake_case(&ident.to_string()))?;
+        let bindings: Vec<_> = (0..variant.fields.len())
+          .map(|i| format_ident!("field{}", i))
+          .collect();
+        let pattern = destructure(quote!(#name::#ident), &variant.fields, &bindings);
+        let arity = variant.fields.len();
+        let encode = match (&shape, &variant.fields) {
+          (_, Fields::Unit) => quote!(encoder.atom(#tag)?;),
+          (Shape::Atom, _) => {
+            return Err(syn::Error::new(
+              variant.span(),
+              "enums with #[term(atom)] must only have unit variants",
+            ))
+          }
+          _ => quote! {
+            encoder.tuple(#arity + 1, |encoder| {
+              encoder.atom(#tag)?;
+              #(::ei_sys::ToTerm::to_term(#bindings, encoder)?;)*
+              Ok(())
+            })?;
+          },
+        };
+        arms.push(quote!(#pattern => { #encode }));
+      }
+      quote! {
+        match self {
+          #(#arms)*
+        }
+      }
+    }
+    Data::Union(data) => {
+      return Err(syn::Error::new(
+        data.union_token.span(),
+        "ToTerm cannot be derived for unions",
+      ))
+    }
+  };
+
+  Ok(quote! {
+    impl #impl_generics ::ei_sys::ToTerm for #name #ty_generics #where_clause {
+      fn to_term(&self, encoder: &mut ::ei_sys::Encoder) -> ::core::result::Result<(), ::ei_sys::Error> {
+        #body
+        Ok(())
+      }
+    }
+  })
+}
+
+fn destructure(path: TokenStream, fields: &Fields, bindings: &[syn::Ident]) -> TokenStream {
+  match fields {
+    Fields::Named(named) => {
+      let idents = named.named.iter().map(|f| &f.ident);
+      quote!(#path { #(#idents: #bindings),* })
+    }
+    Fields::Unnamed(_) => quote!(#path(#(#bindings),*)),
+    Fields::Unit => quote!(#path),
+  }
+}
+
+fn encode_fields(
+  shape: &Shape,
+  type_name: &str,
+  fields: &Fields,
+  bindings: &[syn::Ident],
+) -> syn::Result<TokenStream> {
+  let arity = fields.len();
+  let names = field_names(fields)?;
+  let encode = match (shape, fields) {
+    (Shape::Atom, _) => {
+      return Err(syn::Error::new(
+        Span::call_site(),
+        "#[term(atom)] only applies to enums",
+      ))
+    }
+    (Shape::Record(record), _) => quote! {
+      encoder.tuple(#arity + 1, |encoder| {
+        encoder.atom(#record)?;
+        #(::ei_sys::ToTerm::to_term(#bindings, encoder)?;)*
+        Ok(())
+      })?;
+    },
+    (Shape::ElixirStruct(module), Fields::Named(_)) => quote! {
+      encoder.map_with(#arity + 1, |encoder| {
+        encoder.atom("__struct__")?.atom(#module)?;
+        #(
+          encoder.atom(#names)?;
+          ::ei_sys::ToTerm::to_term(#bindings, encoder)?;
+        )*
+        Ok(())
+      })?;
+    },
+    (Shape::ElixirStruct(_), _) => {
+      return Err(syn::Error::new(
+        Span::call_site(),
+        "#[term(elixir_struct)] only applies to structs with named fields",
+      ))
+    }
+    (Shape::Default, Fields::Named(_)) => quote! {
+      encoder.map_with(#arity, |encoder| {
+        #(
+          encoder.atom(#names)?;
+          ::ei_sys::ToTerm::to_term(#bindings, encoder)?;
+        )*
+        Ok(())
+      })?;
+    },
+    (Shape::Default, Fields::Unnamed(_)) if arity == 1 => quote! {
+      #(::ei_sys::ToTerm::to_term(#bindings, encoder)?;)*
+    },
+    (Shape::Default, Fields::Unnamed(_)) => quote! {
+      encoder.tuple(#arity, |encoder| {
+        #(::ei_sys::ToTerm::to_term(#bindings, encoder)?;)*
+        Ok(())
+      })?;
+    },
+    (Shape::Default, Fields::Unit) => {
+      let atom = snake_case(type_name);
+      quote!(encoder.atom(#atom)?;)
+    }
+  };
+  Ok(encode)
+}
+
+fn expand_from_term(input: &DeriveInput) -> syn::Result<TokenStream> {
+  let shape = container_shape(input)?;
+  let name = &input.ident;
+  let generics = add_bounds(&input.generics, quote!(::ei_sys::FromTerm));
+  let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+
+  let body = match &input.data {
+    Data::Struct(data) => decode_struct(&shape, name, &data.fields)?,
+    Data::Enum(data) => {
+      check_enum_shape(&shape, name)?;
+      let mut atoms = Vec::new();
+      let mut tuples = Vec::new();
+      for variant in &data.variants {
+        let ident = &variant.ident;
+        let tag = renamed(&variant.attrs, snake_case(&ident.to_string()))?;
+        match (&shape, &variant.fields) {
+          (_, Fields::Unit) => atoms.push(quote!(#tag => Ok(#name::#ident),)),
+          (Shape::Atom, _) => {
+            return Err(syn::Error::new(
+              variant.span(),
+              "enums with #[term(atom)] must only have unit variants",
+            ))
+          }
+          (_, fields) => {
+            let arity = fields.len() + 1;
+            let construct = construct(quote!(#name::#ident), fields);
+            tuples.push(quote! {
+              #tag if arity == #arity => Ok(#construct),
+            });
+          }
+        }
+      }
+      quote! {
+        let mut atom = [0; ::ei_sys::MAXATOMLEN_UTF8];
+        if decoder.peek_type()?.0.is_atom() {
+          return match decoder.decode_atom(&mut atom)? {
+            #(#atoms)*
+            _ => Err(::ei_sys::Error::Decode),
+          };
+        }
+        let arity = decoder.decode_tuple_header()?;
+        match decoder.decode_atom(&mut atom)? {
+          #(#tuples)*
+          _ => Err(::ei_sys::Error::Decode),
+        }
+      }
+    }
+    Data::Union(data) => {
+      return Err(syn::Error::new(
+        data.union_token.span(),
+        "FromTerm cannot be derived for unions",
+      ))
+    }
+  };
+
+  Ok(quote! {
+    impl #impl_generics ::ei_sys::FromTerm for #name #ty_generics #where_clause {
+      fn from_term(decoder: &mut ::ei_sys::Decoder) -> ::core::result::Result<Self, ::ei_sys::Error> {
+        #body
+      }
+    }
+  })
+}
+
+/// Returns an expression that builds `path` by decoding its fields in order.
+fn construct(path: TokenStream, fields: &Fields) -> TokenStream {
+  let decode = quote!(::ei_sys::FromTerm::from_term(decoder)?);
+  match fields {
+    Fields::Named(named) => {
+      let idents = named.named.iter().map(|f| &f.ident);
+      quote!(#path { #(#idents: #decode),* })
+    }
+    Fields::Unnamed(unnamed) => {
+      let decodes = unnamed.unnamed.iter().map(|_| &decode);
+      quote!(#path(#(#decodes),*))
+    }
+    Fields::Unit => path,
+  }
+}
+
+fn decode_struct(shape: &Shape, name: &syn::Ident, fields: &Fields) -> syn::Result<TokenStream> {
+  let arity = fields.len();
+  let decode = match (shape, fields) {
+    (Shape::Atom, _) => {
+      return Err(syn::Error::new(
+        name.span(),
+        "#[term(atom)] only applies to enums",
+      ))
+    }
+    (Shape::Record(record), _) => {
+      let construct = construct(quote!(#name), fields);
+      quote! {
+        let mut atom = [0; ::ei_sys::MAXATOMLEN_UTF8];
+        if decoder.decode_tuple_header()? != #arity + 1 || decoder.decode_atom(&mut atom)? != #record {
+          return Err(::ei_sys::Error::Decode);
+        }
+        Ok(#construct)
+      }
+    }
+    (Shape::ElixirStruct(module), Fields::Named(_)) => decode_map(name, fields, Some(module))?,
+    (Shape::ElixirStruct(_), _) => {
+      return Err(syn::Error::new(
+        name.span(),
+        "#[term(elixir_struct)] only applies to structs with named fields",
+      ))
+    }
+    (Shape::Default, Fields::Named(_)) => decode_map(name, fields, None)?,
+    (Shape::Default, Fields::Unnamed(_)) if arity == 1 => {
+      let construct = construct(quote!(#name), fields);
+      quote!(Ok(#construct))
+    }
+    (Shape::Default, Fields::Unnamed(_)) => {
+      let construct = construct(quote!(#name), fields);
+      quote! {
+        if decoder.decode_tuple_header()? != #arity {
+          return Err(::ei_sys::Error::Decode);
+        }
+        Ok(#construct)
+      }
+    }
+    (Shape::Default, Fields::Unit) => {
+      let atom = snake_case(&name.to_string());
+      quote! {
+        let mut atom = [0; ::ei_sys::MAXATOMLEN_UTF8];
+        if decoder.decode_atom(&mut atom)? != #atom {
+          return Err(::ei_sys::Error::Decode);
+        }
+        Ok(#name)
+      }
+    }
+  };
+  Ok(decode)
+}
+
+fn decode_map(
+  name: &syn::Ident,
+  fields: &Fields,
+  module: Option<&String>,
+) -> syn::Result<TokenStream> {
+  let names = field_names(fields)?;
+  let idents: Vec<_> = fields.iter().map(|f| f.ident.clone().unwrap()).collect();
+  let slots: Vec<_> = (0..fields.len())
+    .map(|i| format_ident!("slot{}", i))
+    .collect();
+  let (check_struct, struct_arm) = match module {
+    Some(module) => (
+      quote! {
+        if !seen_struct {
+          return Err(::ei_sys::Error::Decode);
+        }
+      },
+      quote! {
+        "__struct__" => {
+          if decoder.decode_atom(&mut atom)? != #module {
+            return Err(::ei_sys::Error::Decode);
+          }
+          seen_struct = true;
+        }
+      },
+    ),
+    None => (quote!(), quote!()),
+  };
+  Ok(quote! {
+    let mut atom = [0; ::ei_sys::MAXATOMLEN_UTF8];
+    let mut seen_struct = false;
+    #(let mut #slots = None;)*
+    for _ in 0..decoder.decode_map_header()? {
+      // Keys that are not atoms are not fields, and are skipped with their values.
+      if !decoder.peek_type()?.0.is_atom() {
+        decoder.skip()?;
+        decoder.skip()?;
+        continue;
+      }
+      match decoder.decode_atom(&mut atom)? {
+        #struct_arm
+        #(#names => #slots = Some(::ei_sys::FromTerm::from_term(decoder)?),)*
+        _ => decoder.skip()?,
+      }
+    }
+    let _ = seen_struct;
+    #check_struct
+    Ok(#name {
+      #(#idents: #slots.ok_or(::ei_sys::Error::Decode)?,)*
+    })
+  })
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{expand_from_term, expand_to_term, snake_case};
+  use syn::parse_quote;
+
+  #[test]
+  fn snake_case_splits_words() {
+    assert_eq!(snake_case("A"), "a");
+    assert_eq!(snake_case("Ok"), "ok");
+    assert_eq!(snake_case("NotFound"), "not_found");
+    assert_eq!(snake_case("HTTPServer"), "http_server");
+    assert_eq!(snake_case("IOError"), "io_error");
+    assert_eq!(snake_case("UseTLS"), "use_tls");
+    assert_eq!(snake_case("Http2Server"), "http2_server");
+    assert_eq!(snake_case("Already_Snake"), "already_snake");
+  }
+  #[test]
+  fn struct_shapes_are_rejected_on_enums() {
+    let inputs: [syn::DeriveInput; 2] = [
+      parse_quote! {
+        #[term(record = "shape")]
+        enum Shape { Circle(u32) }
+      },
+      parse_quote! {
+        #[term(elixir_struct = "Elixir.Shape")]
+        enum Shape { Circle(u32) }
+      },
+    ];
+    for input in &inputs {
+      assert!(expand_to_term(input).is_err());
+      assert!(expand_from_term(input).is_err());
+    }
+  }
+}
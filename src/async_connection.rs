@@ -0,0 +1,211 @@
+//! Connections driven by the tokio runtime.
+
+use crate::{
+  error::{io_error, lock},
+  CNode, Connection, Error, FromTerm, Listener, PeerInfo, Received, RpcError, RpcReply, ToTerm,
+  XBuff,
+};
+use core::time::Duration;
+use std::{
+  panic,
+  string::String,
+  sync::{Arc, Mutex},
+};
+use tokio::{io::unix::AsyncFd, task};
+
+/// A [`Connection`] whose operations wait for the socket to be ready instead of blocking the
+/// thread.
+///
+/// Ticks from the other node are answered while receiving and never returned.
+///
+/// Sending waits until the socket is writable, but ei then writes the whole message at once, so a
+/// message larger than the send buffer of the socket blocks the thread until it is written.
+///
+/// [`Connection`]: struct.Connection.html
+pub struct AsyncConnection {
+  inner: AsyncFd<Connection>,
+}
+
+impl AsyncConnection {
+  /// Registers `connection` with the reactor of the current tokio runtime.
+  ///
+  /// # Panics
+  ///
+  /// Panics if called outside of a tokio runtime.
+  pub fn new(connection: Connection) -> Result<Self, Error> {
+    Ok(Self {
+      inner: AsyncFd::new(connection).map_err(io_error)?,
+    })
+  }
+
+  /// Connects to the node named `node_name`, like [`CNode::connect`], on the blocking threads of
+  /// the runtime.
+  ///
+  /// [`CNode::connect`]: struct.CNode.html#method.connect
+  pub async fn connect(node: &CNode, node_name: &str) -> Result<Self, Error> {
+    let node = node.clone();
+    let node_name = String::from(node_name);
+    let connection = blocking(move || node.connect(&node_name)).await?;
+    Self::new(connection)
+  }
+
+  /// Returns the underlying connection.
+  #[inline]
+  pub fn get_ref(&self) -> &Connection {
+    self.inner.get_ref()
+  }
+
+  /// Returns the underlying connection, to call its blocking methods.
+  #[inline]
+  pub fn get_mut(&mut self) -> &mut Connection {
+    self.inner.get_mut()
+  }
+
+  /// Deregisters the connection from the runtime and returns it.
+  #[inline]
+  pub fn into_inner(self) -> Connection {
+    self.inner.into_inner()
+  }
+
+  /// Sends the message in `buf` to the process `to`.
+  pub async fn send(&mut self, to: &crate::erlang_pid, buf: &XBuff) -> Result<(), Error> {
+    let mut guard = self.inner.writable_mut().await.map_err(io_error)?;
+    guard.get_inner_mut().send(to, buf)
+  }
+
+  /// Sends the message in `buf` to the process registered as `name` on the other node.
+  pub async fn reg_send(&mut self, name: &str, buf: &XBuff) -> Result<(), Error> {
+    let mut guard = self.inner.writable_mut().await.map_err(io_error)?;
+    guard.get_inner_mut().reg_send(name, buf)
+  }
+
+  /// Waits for the next message from the other node, answering ticks meanwhile.
+  ///
+  /// If ticks are enabled with [`Connection::set_net_ticktime`], [`Connection::heartbeat`] is
+  /// called while waiting.
+  ///
+  /// [`Connection::set_net_ticktime`]: struct.Connection.html#method.set_net_ticktime
+  /// [`Connection::heartbeat`]: struct.Connection.html#method.heartbeat
+  pub async fn receive(&mut self) -> Result<(crate::erlang_msg, XBuff), Error> {
+    loop {
+      self.inner.get_mut().heartbeat()?;
+      let ticktime = self.inner.get_ref().net_ticktime();
+      let readable = self.inner.readable_mut();
+      let mut guard = match ticktime {
+        Some(ticktime) => match tokio::time::timeout(ticktime / 4, readable).await {
+          Ok(guard) => guard,
+          Err(_) => continue,
+        },
+        None => readable.await,
+      }
+      .map_err(io_error)?;
+      match guard.get_inner_mut().try_receive() {
+        Ok(Received::Tick) => continue,
+        Ok(Received::Message { msg, payload }) => return Ok((msg, payload)),
+        Err(Error::WouldBlock) => guard.clear_ready(),
+        Err(error) => return Err(error),
+      }
+    }
+  }
+
+  /// Calls `module:function` with `args` on the other node and decodes its result, like
+  /// [`Connection::rpc`].
+  ///
+  /// Replies to other calls made with [`Connection::rpc_to`] and received meanwhile are kept for
+  /// [`Connection::rpc_from`]. Other messages are discarded.
+  ///
+  /// [`Connection::rpc`]: struct.Connection.html#method.rpc
+  /// [`Connection::rpc_to`]: struct.Connection.html#method.rpc_to
+  /// [`Connection::rpc_from`]: struct.Connection.html#method.rpc_from
+  pub async fn rpc<A: ToTerm, R: FromTerm>(
+    &mut self,
+    module: &str,
+    function: &str,
+    args: A,
+    timeout: Duration,
+  ) -> Result<R, RpcError> {
+    let id = {
+      let mut guard = self.inner.writable_mut().await.map_err(io_error)?;
+      guard.get_inner_mut().rpc_to(module, function, args)?
+    };
+    let reply = async {
+      loop {
+        let (msg, payload) = self.receive().await?;
+        match RpcReply::new(&msg, payload) {
+          Some(reply) if reply.id() == id => return reply.decode(),
+          Some(reply) => self.inner.get_mut().replies.push_back(reply),
+          None => {}
+        }
+      }
+    };
+    tokio::time::timeout(timeout, reply)
+      .await
+      .unwrap_or(Err(RpcError::Timeout))
+  }
+}
+
+/// A [`Listener`] that accepts connections as [`AsyncConnection`]s.
+///
+/// A connection accepted after the future of [`accept`] was dropped is kept for the next call.
+///
+/// [`Listener`]: struct.Listener.html
+/// [`AsyncConnection`]: struct.AsyncConnection.html
+/// [`accept`]: #method.accept
+pub struct AsyncListener {
+  inner: Arc<Mutex<Listener>>,
+  accepted: Arc<Mutex<Option<(Connection, PeerInfo)>>>,
+}
+
+impl AsyncListener {
+  /// How long a blocking thread waits for a connection at a time, which bounds how long it keeps
+  /// accepting after the future of `accept` was dropped.
+  const ACCEPT_INTERVAL: Duration = Duration::from_secs(1);
+
+  /// Wraps `listener`.
+  pub fn new(listener: Listener) -> Self {
+    Self {
+      inner: Arc::new(Mutex::new(listener)),
+      accepted: Arc::new(Mutex::new(None)),
+    }
+  }
+
+  /// Waits for another node to connect, on the blocking threads of the runtime, since ei performs
+  /// the handshake of the connection as it accepts it.
+  pub async fn accept(&self) -> Result<(AsyncConnection, PeerInfo), Error> {
+    loop {
+      let listener = Arc::clone(&self.inner);
+      let accepted = Arc::clone(&self.accepted);
+      // The blocking thread outlives this future if it is dropped, so the connection is handed
+      // over through `accepted` rather than returned, to keep it for the next call.
+      let result = blocking(move || {
+        let mut listener = lock(&listener);
+        if lock(&accepted).is_some() {
+          return Ok(());
+        }
+        let connection = listener.accept_timeout(Self::ACCEPT_INTERVAL)?;
+        *lock(&accepted) = Some(connection);
+        Ok(())
+      });
+      match result.await {
+        Ok(()) | Err(Error::Timeout) => {}
+        Err(error) => return Err(error),
+      }
+      // Another call may have taken the connection first.
+      if let Some((connection, peer)) = lock(&self.accepted).take() {
+        return Ok((AsyncConnection::new(connection)?, peer));
+      }
+    }
+  }
+}
+
+/// Runs `f` on the blocking threads of the runtime, forwarding its panics.
+async fn blocking<T, F>(f: F) -> T
+where
+  T: Send + 'static,
+  F: FnOnce() -> T + Send + 'static,
+{
+  match task::spawn_blocking(f).await {
+    Ok(value) => value,
+    Err(error) => panic::resume_unwind(error.into_panic()),
+  }
+}
@@ -0,0 +1,27 @@
+//! Conversions between Rust strings and the fixed-size C strings embedded in ei structures.
+
+use crate::Error;
+use core::{slice, str};
+use libc::c_char;
+
+/// Returns the string stored in `chars`, up to the first null character.
+pub(crate) fn to_str(chars: &[c_char]) -> Result<&str, Error> {
+  let bytes = unsafe { slice::from_raw_parts(chars.as_ptr() as *const u8, chars.len()) };
+  let len = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+  str::from_utf8(&bytes[..len]).map_err(|_| Error::Decode)
+}
+
+/// Copies `s` to `chars`, followed by a null character.
+///
+/// Fails if `s` does not fit or contains a null character.
+pub(crate) fn copy(s: &str, chars: &mut [c_char]) -> Result<(), Error> {
+  if s.len() >= chars.len() || s.bytes().any(|b| b == 0) {
+    return Err(Error::Encode);
+  }
+
+  for (dst, &src) in chars.iter_mut().zip(s.as_bytes()) {
+    *dst = src as c_char;
+  }
+  chars[s.len()] = 0;
+  Ok(())
+}
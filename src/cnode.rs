@@ -0,0 +1,238 @@
+//! The identity of this node, as seen by the other nodes of the cluster.
+
+use crate::{
+  c_str, Error, NodeName, EI_MAXALIVELEN, EI_MAXHOSTNAMELEN, EI_MAX_COOKIE_SIZE, MAXNODELEN,
+};
+use core::{
+  ffi::CStr,
+  mem,
+  net::Ipv4Addr,
+  str,
+  sync::atomic::{AtomicU32, Ordering},
+};
+use libc::{c_char, c_short};
+
+/// An initialized [`ei_cnode`], the identity of a C node.
+///
+/// A node is created with a [`CNodeBuilder`]:
+///
+/// ```no_run
+/// let node = ei_sys::CNode::builder("rust", "secret").build()?;
+/// println!("running as {}", node.node_name());
+/// # Ok::<(), ei_sys::Error>(())
+/// ```
+///
+/// [`ei_cnode`]: struct.ei_cnode.html
+/// [`CNodeBuilder`]: struct.CNodeBuilder.html
+#[derive(Clone)]
+pub struct CNode {
+  raw: crate::ei_cnode,
+}
+
+impl CNode {
+  /// Returns a builder for a node named `alive_name`, that authenticates with `cookie`.
+  pub fn builder<'a>(alive_name: &'a str, cookie: &'a str) -> CNodeBuilder<'a> {
+    CNodeBuilder {
+      alive_name,
+      cookie,
+      host_name: None,
+      ip: None,
+      creation: 0,
+    }
+  }
+
+  /// Returns a builder for a node named `name`, that authenticates with `cookie`.
+  pub fn named<'a>(name: &'a NodeName, cookie: &'a str) -> CNodeBuilder<'a> {
+    let mut builder = Self::builder(name.alive(), cookie);
+    builder.host_name(name.host());
+    builder
+  }
+
+  /// Returns the full name of the node, `alive@host`.
+  ///
+  /// # See Also
+  ///
+  /// [The official entry for this function in the Erlang documentation.](http://erlang.org/doc/man/ei_connect.html#ei_thisnodename)
+  pub fn node_name(&self) -> &str {
+    unsafe { Self::str(crate::ei_thisnodename(&self.raw)) }
+  }
+
+  /// Returns the host part of the name of the node.
+  ///
+  /// # See Also
+  ///
+  /// [The official entry for this function in the Erlang documentation.](http://erlang.org/doc/man/ei_connect.html#ei_thishostname)
+  pub fn host_name(&self) -> &str {
+    unsafe { Self::str(crate::ei_thishostname(&self.raw)) }
+  }
+
+  /// Returns the alive part of the name of the node.
+  ///
+  /// # See Also
+  ///
+  /// [The official entry for this function in the Erlang documentation.](http://erlang.org/doc/man/ei_connect.html#ei_thisalivename)
+  pub fn alive_name(&self) -> &str {
+    unsafe { Self::str(crate::ei_thisalivename(&self.raw)) }
+  }
+
+  /// Returns the creation of the node, which distinguishes it from previous incarnations with the
+  /// same name.
+  pub fn creation(&self) -> c_short {
+    self.raw.creation
+  }
+
+  /// Returns the pid of the node, used as the sender of the messages it sends.
+  ///
+  /// # See Also
+  ///
+  /// [The official entry for this function in the Erlang documentation.](http://erlang.org/doc/man/ei_connect.html#ei_self)
+  pub fn pid(&self) -> &crate::erlang_pid {
+    &self.raw.self_
+  }
+
+  /// Returns a new reference, distinct from the others made by the nodes of this process.
+  pub fn make_ref(&self) -> crate::erlang_ref {
+    static COUNTER: AtomicU32 = AtomicU32::new(0);
+    let count = COUNTER.fetch_add(1, Ordering::Relaxed);
+    crate::erlang_ref {
+      node: self.raw.self_.node,
+      len: 3,
+      // The first number is 18 bits in older reference encodings.
+      n: [count & 0x3ffff, count >> 18, 0],
+      creation: self.raw.self_.creation,
+    }
+  }
+
+  #[inline]
+  pub(crate) fn pid_mut(&mut self) -> &mut crate::erlang_pid {
+    &mut self.raw.self_
+  }
+
+  /// Returns a pointer to the raw node, to pass to the functions of ei.
+  pub fn as_ptr(&self) -> *const crate::ei_cnode {
+    &self.raw
+  }
+
+  /// Returns a mutable pointer to the raw node, to pass to the functions of ei.
+  pub fn as_mut_ptr(&mut self) -> *mut crate::ei_cnode {
+    &mut self.raw
+  }
+
+  /// # Safety
+  ///
+  /// `chars` must point to one of the names of the node, which [`build`] checked are valid UTF-8.
+  ///
+  /// [`build`]: struct.CNodeBuilder.html#method.build
+  unsafe fn str<'a>(chars: *const c_char) -> &'a str {
+    str::from_utf8_unchecked(CStr::from_ptr(chars).to_bytes())
+  }
+}
+
+/// A builder for a [`CNode`].
+///
+/// Without a host name, the node is initialized by [`ei_connect_init`], which uses the name of the
+/// local host. Otherwise, it is initialized by [`ei_connect_xinit`].
+///
+/// [`CNode`]: struct.CNode.html
+/// [`ei_connect_init`]: fn.ei_connect_init.html
+/// [`ei_connect_xinit`]: fn.ei_connect_xinit.html
+#[derive(Clone, Debug)]
+pub struct CNodeBuilder<'a> {
+  alive_name: &'a str,
+  cookie: &'a str,
+  host_name: Option<&'a str>,
+  ip: Option<Ipv4Addr>,
+  creation: c_short,
+}
+
+impl<'a> CNodeBuilder<'a> {
+  /// Sets the host part of the name of the node.
+  pub fn host_name(&mut self, host_name: &'a str) -> &mut Self {
+    self.host_name = Some(host_name);
+    self
+  }
+
+  /// Sets the address of the node. It is only meaningful with a host name and defaults to
+  /// `0.0.0.0`.
+  pub fn ip(&mut self, ip: Ipv4Addr) -> &mut Self {
+    self.ip = Some(ip);
+    self
+  }
+
+  /// Sets the creation of the node, which defaults to `0`.
+  pub fn creation(&mut self, creation: c_short) -> &mut Self {
+    self.creation = creation;
+    self
+  }
+
+  /// Initializes the node.
+  ///
+  /// Fails with [`Error::InvalidArgument`] if a name or the cookie is empty, too long or contains
+  /// a null character, if an address is given without a host name, or if ei rejects them.
+  ///
+  /// [`Error::InvalidArgument`]: enum.Error.html#variant.InvalidArgument
+  pub fn build(&self) -> Result<CNode, Error> {
+    let mut alive_name = [0; EI_MAXALIVELEN + 1];
+    let mut cookie = [0; EI_MAX_COOKIE_SIZE + 1];
+    copy_non_empty(self.alive_name, &mut alive_name)?;
+    copy_non_empty(self.cookie, &mut cookie)?;
+
+    let mut raw: crate::ei_cnode = unsafe { mem::zeroed() };
+    let code = match self.host_name {
+      None if self.ip.is_some() => return Err(Error::InvalidArgument),
+      None => unsafe {
+        crate::ei_connect_init(
+          &mut raw,
+          alive_name.as_ptr(),
+          cookie.as_ptr(),
+          self.creation,
+        )
+      },
+      Some(host) => {
+        let mut host_name = [0; EI_MAXHOSTNAMELEN + 1];
+        let mut node_name = [0; MAXNODELEN + 1];
+        copy_non_empty(host, &mut host_name)?;
+        // The parts were checked above, so `alive@host` fits with its null character.
+        let node_bytes = self
+          .alive_name
+          .bytes()
+          .chain(Some(b'@'))
+          .chain(host.bytes());
+        for (dst, src) in node_name.iter_mut().zip(node_bytes) {
+          *dst = src as c_char;
+        }
+
+        let ip = self.ip.unwrap_or(Ipv4Addr::UNSPECIFIED);
+        let mut addr = libc::in_addr {
+          s_addr: u32::from(ip).to_be(),
+        };
+        unsafe {
+          crate::ei_connect_xinit(
+            &mut raw,
+            host_name.as_ptr(),
+            alive_name.as_ptr(),
+            node_name.as_ptr(),
+            &mut addr as *mut libc::in_addr as *mut in_addr::in_addr,
+            cookie.as_ptr(),
+            self.creation,
+          )
+        }
+      }
+    };
+    if code < 0 {
+      return Err(Error::InvalidArgument);
+    }
+
+    // The names are only valid UTF-8 by construction if ei did not pick the host name itself.
+    c_str::to_str(&raw.thishostname).map_err(|_| Error::InvalidArgument)?;
+    c_str::to_str(&raw.thisnodename).map_err(|_| Error::InvalidArgument)?;
+    Ok(CNode { raw })
+  }
+}
+
+fn copy_non_empty(s: &str, chars: &mut [c_char]) -> Result<(), Error> {
+  if s.is_empty() {
+    return Err(Error::InvalidArgument);
+  }
+  c_str::copy(s, chars).map_err(|_| Error::InvalidArgument)
+}
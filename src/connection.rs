@@ -0,0 +1,800 @@
+//! Connections to other nodes.
+
+use crate::{
+  c_str,
+  error::{last_error, os_error, Error},
+  CNode, ToTerm, XBuff, MAXATOMLEN_UTF8, MAXNODELEN,
+};
+#[cfg(all(feature = "std", unix))]
+use crate::{decode::term_end, Decoder};
+#[cfg(feature = "alloc")]
+use alloc::collections::VecDeque;
+use core::{convert::TryFrom, mem, net::SocketAddrV4, time::Duration};
+use libc::{c_char, c_int, c_long, c_uint};
+#[cfg(feature = "std")]
+use std::time::Instant;
+#[cfg(all(feature = "std", unix))]
+use std::vec::Vec;
+
+/// A connection to another node, closed when dropped.
+///
+/// A connection is opened with [`CNode::connect`] and keeps a copy of the node it was opened from,
+/// which is needed to send messages to registered names.
+///
+/// [`CNode::connect`]: struct.CNode.html#method.connect
+pub struct Connection {
+  node: CNode,
+  fd: c_int,
+  calls: u32,
+  peer: [c_char; MAXNODELEN + 1],
+  /// Replies to calls made with `rpc_to` that `rpc` received while waiting for its own.
+  #[cfg(feature = "alloc")]
+  pub(crate) replies: VecDeque<crate::RpcReply>,
+  #[cfg(feature = "std")]
+  ticks: Option<Ticks>,
+  /// What was received so far of the next frame, with its length prefix.
+  #[cfg(all(feature = "std", unix))]
+  incoming: Vec<u8>,
+}
+
+/// The most that is read from the socket at once.
+#[cfg(all(feature = "std", unix))]
+const READ_CHUNK: usize = 64 * 1024;
+
+/// When the connection last sent and received anything, to keep it alive.
+#[cfg(feature = "std")]
+struct Ticks {
+  ticktime: Duration,
+  sent: Instant,
+  received: Instant,
+}
+
+/// What [`Connection::receive`] got from the other node.
+///
+/// [`Connection::receive`]: struct.Connection.html#method.receive
+// Ticks are rare enough that boxing the header of every message is not worth it.
+#[allow(clippy::large_enum_variant)]
+pub enum Received {
+  /// The other node checked that this node is alive.
+  Tick,
+  /// A message or a control message, such as a link or an exit signal.
+  Message {
+    /// The header of the message, with its type, sender and recipient.
+    msg: crate::erlang_msg,
+    /// The payload of the message, if any, starting with the version byte.
+    payload: XBuff,
+  },
+}
+
+impl CNode {
+  /// Connects to the node named `node_name`, of the form `alive@host`.
+  ///
+  /// # See Also
+  ///
+  /// [The official entry for this function in the Erlang documentation.](http://erlang.org/doc/man/ei_connect.html#ei_connect)
+  pub fn connect(&self, node_name: &str) -> Result<Connection, Error> {
+    self.connect_with(node_name, 0)
+  }
+
+  /// Connects to the node named `node_name`, or fails with [`Error::Timeout`] after `timeout`.
+  ///
+  /// [`Error::Timeout`]: enum.Error.html#variant.Timeout
+  ///
+  /// # See Also
+  ///
+  /// [The official entry for this function in the Erlang documentation.](http://erlang.org/doc/man/ei_connect.html#ei_connect_tmo)
+  pub fn connect_timeout(&self, node_name: &str, timeout: Duration) -> Result<Connection, Error> {
+    self.connect_with(node_name, millis(timeout))
+  }
+
+  /// Connects to the node listening on `addr`, without asking EPMD for its port, such as one
+  /// found with [`Epmd::port_please`].
+  ///
+  /// ei does not tell the name of the other node, so [`Connection::peer_name`] is empty, and a
+  /// [`Dispatcher`] takes the processes of every node to be on the other end of the connection.
+  ///
+  /// [`Epmd::port_please`]: struct.Epmd.html#method.port_please
+  /// [`Connection::peer_name`]: struct.Connection.html#method.peer_name
+  /// [`Dispatcher`]: struct.Dispatcher.html
+  ///
+  /// # See Also
+  ///
+  /// [The official entry for this function in the Erlang documentation.](http://erlang.org/doc/man/ei_connect.html#ei_xconnect_host_port)
+  pub fn connect_addr(&self, addr: SocketAddrV4) -> Result<Connection, Error> {
+    self.connect_addr_with(addr, 0)
+  }
+
+  /// Connects to the node listening on `addr`, or fails with [`Error::Timeout`] after `timeout`.
+  ///
+  /// [`Error::Timeout`]: enum.Error.html#variant.Timeout
+  ///
+  /// # See Also
+  ///
+  /// [The official entry for this function in the Erlang documentation.](http://erlang.org/doc/man/ei_connect.html#ei_xconnect_host_port_tmo)
+  pub fn connect_addr_timeout(
+    &self,
+    addr: SocketAddrV4,
+    timeout: Duration,
+  ) -> Result<Connection, Error> {
+    self.connect_addr_with(addr, millis(timeout))
+  }
+
+  fn connect_addr_with(&self, addr: SocketAddrV4, ms: c_uint) -> Result<Connection, Error> {
+    let mut node = self.clone();
+    let mut ip = libc::in_addr {
+      s_addr: u32::from(*addr.ip()).to_be(),
+    };
+    let fd = unsafe {
+      crate::ei_xconnect_host_port_tmo(
+        node.as_mut_ptr(),
+        &mut ip as *mut libc::in_addr as *mut in_addr::in_addr,
+        c_int::from(addr.port()),
+        ms,
+      )
+    };
+    if fd < 0 {
+      return Err(last_error());
+    }
+    Ok(unsafe { Connection::from_raw_fd(node, fd) })
+  }
+
+  fn connect_with(&self, node_name: &str, ms: c_uint) -> Result<Connection, Error> {
+    let mut name = [0; MAXNODELEN + 1];
+    c_str::copy(node_name, &mut name).map_err(|_| Error::InvalidArgument)?;
+    let mut node = self.clone();
+    let fd = unsafe { crate::ei_connect_tmo(node.as_mut_ptr(), name.as_mut_ptr(), ms) };
+    if fd < 0 {
+      return Err(last_error());
+    }
+    let mut connection = unsafe { Connection::from_raw_fd(node, fd) };
+    connection.peer = name;
+    Ok(connection)
+  }
+}
+
+impl Connection {
+  /// Wraps `fd`, which will be closed when the connection is dropped.
+  ///
+  /// # Safety
+  ///
+  /// `fd` must be a connection to another node that was established by `node`, such as one
+  /// returned by [`ei_connect`] or [`ei_accept`], and must not be closed elsewhere.
+  ///
+  /// [`ei_connect`]: fn.ei_connect.html
+  /// [`ei_accept`]: fn.ei_accept.html
+  pub unsafe fn from_raw_fd(node: CNode, fd: c_int) -> Self {
+    Self {
+      node,
+      fd,
+      calls: 0,
+      peer: [0; MAXNODELEN + 1],
+      #[cfg(feature = "alloc")]
+      replies: VecDeque::new(),
+      #[cfg(feature = "std")]
+      ticks: None,
+      #[cfg(all(feature = "std", unix))]
+      incoming: Vec::new(),
+    }
+  }
+
+  /// Returns the file descriptor of the connection.
+  #[inline]
+  pub fn fd(&self) -> c_int {
+    self.fd
+  }
+
+  /// Returns the node this connection was opened from.
+  #[inline]
+  pub fn node(&self) -> &CNode {
+    &self.node
+  }
+
+  /// Returns the name of the other node, or an empty string if the connection was made with
+  /// [`from_raw_fd`] or [`CNode::connect_addr`].
+  ///
+  /// [`from_raw_fd`]: #method.from_raw_fd
+  /// [`CNode::connect_addr`]: struct.CNode.html#method.connect_addr
+  pub fn peer_name(&self) -> &str {
+    // Only ever set from a string.
+    c_str::to_str(&self.peer).unwrap_or_default()
+  }
+
+  #[cfg(feature = "std")]
+  #[inline]
+  pub(crate) fn set_peer(&mut self, peer: &[c_char; MAXNODELEN + 1]) {
+    self.peer = *peer;
+  }
+
+  #[inline]
+  pub(crate) fn node_mut(&mut self) -> &mut CNode {
+    &mut self.node
+  }
+
+  /// Returns a number that was not returned recently, to tell apart concurrent calls.
+  pub(crate) fn next_call(&mut self) -> u32 {
+    self.calls = self.calls.wrapping_add(1);
+    self.calls
+  }
+
+  /// Keeps the connection alive on its own, like the `net_ticktime` of Erlang nodes, or stops
+  /// doing so if `ticktime` is `None`, which is the default.
+  ///
+  /// Erlang nodes drop a connection when they receive nothing on it for `net_ticktime`, 60 seconds
+  /// by default, and send ticks a quarter as often to prevent it. Once enabled:
+  ///
+  /// - on Unix, [`receive`] and [`receive_timeout`] do not return ticks, call [`heartbeat`] while
+  ///   they wait, and fail with [`Error::NetTickTimeout`] if the other node sends nothing for
+  ///   `ticktime`, not even part of a message. Elsewhere, they return ticks as usual;
+  /// - [`heartbeat`] should be called regularly by connections that only send.
+  ///
+  /// `ticktime` should match the `net_ticktime` of the other node.
+  ///
+  /// [`receive`]: #method.receive
+  /// [`receive_timeout`]: #method.receive_timeout
+  /// [`heartbeat`]: #method.heartbeat
+  /// [`Error::NetTickTimeout`]: enum.Error.html#variant.NetTickTimeout
+  #[cfg(feature = "std")]
+  pub fn set_net_ticktime(&mut self, ticktime: Option<Duration>) {
+    let now = Instant::now();
+    self.ticks = ticktime.map(|ticktime| Ticks {
+      ticktime,
+      sent: now,
+      received: now,
+    });
+  }
+
+  /// Returns the tick time set with [`set_net_ticktime`], if any.
+  ///
+  /// [`set_net_ticktime`]: #method.set_net_ticktime
+  #[cfg(feature = "std")]
+  #[inline]
+  pub fn net_ticktime(&self) -> Option<Duration> {
+    self.ticks.as_ref().map(|ticks| ticks.ticktime)
+  }
+
+  /// Sends a tick to the other node if nothing was sent to it for a quarter of the tick time, and
+  /// fails with [`Error::NetTickTimeout`] if nothing was received from it for the tick time.
+  ///
+  /// Data waiting in the socket counts as received, so that connections that only send are not
+  /// dropped. Does nothing unless enabled with [`set_net_ticktime`].
+  ///
+  /// [`Error::NetTickTimeout`]: enum.Error.html#variant.NetTickTimeout
+  /// [`set_net_ticktime`]: #method.set_net_ticktime
+  #[cfg(feature = "std")]
+  pub fn heartbeat(&mut self) -> Result<(), Error> {
+    #[cfg(unix)]
+    if self.ticks.is_some() && self.has_pending() {
+      self.received();
+    }
+    let ticks = match &self.ticks {
+      Some(ticks) => ticks,
+      None => return Ok(()),
+    };
+    if ticks.received.elapsed() >= ticks.ticktime {
+      return Err(Error::NetTickTimeout);
+    }
+    if ticks.sent.elapsed() >= ticks.ticktime / 4 {
+      // A tick is an empty frame.
+      write_all(self.fd, &[0; 4])?;
+      self.sent();
+    }
+    Ok(())
+  }
+
+  /// Notes that something was sent to the other node.
+  #[inline]
+  pub(crate) fn sent(&mut self) {
+    #[cfg(feature = "std")]
+    if let Some(ticks) = &mut self.ticks {
+      ticks.sent = Instant::now();
+    }
+  }
+
+  /// Notes that something was received from the other node.
+  #[inline]
+  pub(crate) fn received(&mut self) {
+    #[cfg(feature = "std")]
+    if let Some(ticks) = &mut self.ticks {
+      ticks.received = Instant::now();
+    }
+  }
+
+  /// Sends the message in `buf` to the process `to`.
+  ///
+  /// # See Also
+  ///
+  /// [The official entry for this function in the Erlang documentation.](http://erlang.org/doc/man/ei_connect.html#ei_send)
+  pub fn send(&mut self, to: &crate::erlang_pid, buf: &XBuff) -> Result<(), Error> {
+    self.send_with(to, buf, 0)
+  }
+
+  /// Sends the message in `buf` to the process `to`, or fails with [`Error::Timeout`] after
+  /// `timeout`.
+  ///
+  /// [`Error::Timeout`]: enum.Error.html#variant.Timeout
+  ///
+  /// # See Also
+  ///
+  /// [The official entry for this function in the Erlang documentation.](http://erlang.org/doc/man/ei_connect.html#ei_send_tmo)
+  pub fn send_timeout(
+    &mut self,
+    to: &crate::erlang_pid,
+    buf: &XBuff,
+    timeout: Duration,
+  ) -> Result<(), Error> {
+    self.send_with(to, buf, millis(timeout))
+  }
+
+  fn send_with(&mut self, to: &crate::erlang_pid, buf: &XBuff, ms: c_uint) -> Result<(), Error> {
+    let mut to = to.clone();
+    let (ptr, len) = raw_parts(buf)?;
+    let code = unsafe { crate::ei_send_tmo(self.fd, &mut to, ptr, len, ms) };
+    if code < 0 {
+      return Err(last_error());
+    }
+    self.sent();
+    Ok(())
+  }
+
+  /// Sends the message in `buf` to the process registered as `name` on the other node.
+  ///
+  /// # See Also
+  ///
+  /// [The official entry for this function in the Erlang documentation.](http://erlang.org/doc/man/ei_connect.html#ei_reg_send)
+  pub fn reg_send(&mut self, name: &str, buf: &XBuff) -> Result<(), Error> {
+    self.reg_send_with(name, buf, 0)
+  }
+
+  /// Sends the message in `buf` to the process registered as `name` on the other node, or fails
+  /// with [`Error::Timeout`] after `timeout`.
+  ///
+  /// [`Error::Timeout`]: enum.Error.html#variant.Timeout
+  ///
+  /// # See Also
+  ///
+  /// [The official entry for this function in the Erlang documentation.](http://erlang.org/doc/man/ei_connect.html#ei_reg_send_tmo)
+  pub fn reg_send_timeout(
+    &mut self,
+    name: &str,
+    buf: &XBuff,
+    timeout: Duration,
+  ) -> Result<(), Error> {
+    self.reg_send_with(name, buf, millis(timeout))
+  }
+
+  fn reg_send_with(&mut self, name: &str, buf: &XBuff, ms: c_uint) -> Result<(), Error> {
+    let mut server_name = [0; MAXATOMLEN_UTF8];
+    c_str::copy(name, &mut server_name).map_err(|_| Error::InvalidArgument)?;
+    let (ptr, len) = raw_parts(buf)?;
+    let code = unsafe {
+      crate::ei_reg_send_tmo(
+        self.node.as_mut_ptr(),
+        self.fd,
+        server_name.as_mut_ptr(),
+        ptr,
+        len,
+        ms,
+      )
+    };
+    if code < 0 {
+      return Err(last_error());
+    }
+    self.sent();
+    Ok(())
+  }
+
+  /// Links the local process `from` to the process `to` on the other node, so that `to` is sent an
+  /// exit signal when `from` exits, and conversely.
+  pub fn link(&mut self, from: &crate::erlang_pid, to: &crate::erlang_pid) -> Result<(), Error> {
+    let mut control = XBuff::with_version()?;
+    control.encoder().tuple(3, |e| {
+      e.long(c_long::from(crate::ERL_LINK))?.pid(from)?.pid(to)?;
+      Ok(())
+    })?;
+    self.send_control(&control)
+  }
+
+  /// Removes the link between the local process `from` and the process `to` on the other node.
+  pub fn unlink(&mut self, from: &crate::erlang_pid, to: &crate::erlang_pid) -> Result<(), Error> {
+    let mut control = XBuff::with_version()?;
+    control.encoder().tuple(3, |e| {
+      e.long(c_long::from(crate::ERL_UNLINK))?
+        .pid(from)?
+        .pid(to)?;
+      Ok(())
+    })?;
+    self.send_control(&control)
+  }
+
+  /// Sends the exit signal of the local process `from`, which exited with `reason`, to the process
+  /// `to` on the other node it is linked to.
+  pub fn exit<R: ToTerm>(
+    &mut self,
+    from: &crate::erlang_pid,
+    to: &crate::erlang_pid,
+    reason: R,
+  ) -> Result<(), Error> {
+    let mut control = XBuff::with_version()?;
+    control.encoder().tuple(4, |e| {
+      e.long(c_long::from(crate::ERL_EXIT))?.pid(from)?.pid(to)?;
+      reason.to_term(e)
+    })?;
+    self.send_control(&control)
+  }
+
+  /// Sends a control message without payload, which ei has no function for.
+  ///
+  /// Like ei, the message is framed by its length and passed through, without atom cache.
+  fn send_control(&mut self, control: &XBuff) -> Result<(), Error> {
+    let len = u32::try_from(control.byte_len() + 1).map_err(|_| Error::InvalidArgument)?;
+    let len = len.to_be_bytes();
+    let header = [len[0], len[1], len[2], len[3], crate::ERL_PASS_THROUGH];
+    write_all(self.fd, &header)?;
+    write_all(self.fd, control.as_bytes())?;
+    self.sent();
+    Ok(())
+  }
+
+  /// Waits for the next message or tick from the other node.
+  ///
+  /// # See Also
+  ///
+  /// [The official entry for this function in the Erlang documentation.](http://erlang.org/doc/man/ei_connect.html#ei_xreceive_msg)
+  pub fn receive(&mut self) -> Result<Received, Error> {
+    #[cfg(all(feature = "std", unix))]
+    return self.receive_within(None);
+    #[cfg(not(all(feature = "std", unix)))]
+    return self.receive_with(0);
+  }
+
+  /// Waits for the next message or tick from the other node, or fails with [`Error::Timeout`]
+  /// after `timeout`.
+  ///
+  /// On Unix, what was received of a message when the timeout expires is kept for the next call.
+  ///
+  /// [`Error::Timeout`]: enum.Error.html#variant.Timeout
+  ///
+  /// # See Also
+  ///
+  /// [The official entry for this function in the Erlang documentation.](http://erlang.org/doc/man/ei_connect.html#ei_xreceive_msg_tmo)
+  pub fn receive_timeout(&mut self, timeout: Duration) -> Result<Received, Error> {
+    #[cfg(all(feature = "std", unix))]
+    return self.receive_within(Some(Deadline::new(timeout)));
+    #[cfg(not(all(feature = "std", unix)))]
+    return self.receive_with(millis(timeout));
+  }
+
+  /// Waits for the next frame from the other node, reading it as it arrives.
+  ///
+  /// ei loses its place in the stream if it times out in the middle of a message, so frames are
+  /// read here and only decoded once whole. With ticks enabled, waits in slices of a quarter of
+  /// the tick time, calling [`heartbeat`] between them, and skips ticks.
+  ///
+  /// [`heartbeat`]: #method.heartbeat
+  #[cfg(all(feature = "std", unix))]
+  fn receive_within(&mut self, deadline: Option<Deadline>) -> Result<Received, Error> {
+    loop {
+      self.heartbeat()?;
+      let ticktime = self.net_ticktime();
+      if self.read_available()? {
+        match self.take_frame() {
+          Ok(Received::Tick) if ticktime.is_some() => continue,
+          received => return received,
+        }
+      }
+      let mut wait = ticktime.map(|ticktime| ticktime / 4);
+      if let Some(deadline) = &deadline {
+        let remaining = deadline.remaining().ok_or(Error::Timeout)?;
+        wait = Some(wait.map_or(remaining, |wait| wait.min(remaining)));
+      }
+      self.wait_readable(wait)?;
+    }
+  }
+
+  /// Waits up to `timeout`, or forever if it is `None`, for data from the other node, or for the
+  /// connection to be closed.
+  #[cfg(all(feature = "std", unix))]
+  fn wait_readable(&self, timeout: Option<Duration>) -> Result<(), Error> {
+    let mut fd = libc::pollfd {
+      fd: self.fd,
+      events: libc::POLLIN,
+      revents: 0,
+    };
+    let ms = match timeout {
+      Some(timeout) => c_int::try_from(millis(timeout)).unwrap_or(c_int::MAX),
+      None => -1,
+    };
+    if unsafe { libc::poll(&mut fd, 1, ms) } < 0 {
+      match os_error() {
+        Error::Io(libc::EINTR) => {}
+        error => return Err(error),
+      }
+    }
+    Ok(())
+  }
+
+  /// Returns `true` if any data from the other node is waiting in the socket.
+  #[cfg(all(feature = "std", unix))]
+  fn has_pending(&self) -> bool {
+    let mut byte = 0u8;
+    let peeked = unsafe {
+      libc::recv(
+        self.fd,
+        &mut byte as *mut u8 as *mut _,
+        1,
+        libc::MSG_PEEK | libc::MSG_DONTWAIT,
+      )
+    };
+    peeked > 0
+  }
+
+  /// Reads what the socket holds of the next frame, without blocking, and returns `true` once the
+  /// frame is whole.
+  #[cfg(all(feature = "std", unix))]
+  fn read_available(&mut self) -> Result<bool, Error> {
+    loop {
+      let received = self.incoming.len() as u64;
+      let missing = match self.incoming.get(..4) {
+        Some(len) => 4 + u64::from(u32::from_be_bytes([len[0], len[1], len[2], len[3]])) - received,
+        None => 4 - received,
+      };
+      if missing == 0 {
+        return Ok(true);
+      }
+
+      // The buffer grows with what is received, rather than with the length the frame claims.
+      let start = self.incoming.len();
+      self
+        .incoming
+        .resize(start + missing.min(READ_CHUNK as u64) as usize, 0);
+      let read = unsafe {
+        libc::recv(
+          self.fd,
+          self.incoming[start..].as_mut_ptr() as *mut _,
+          self.incoming.len() - start,
+          libc::MSG_DONTWAIT,
+        )
+      };
+      self.incoming.truncate(start + read.max(0) as usize);
+      match read {
+        // Like ei, which fails with `EIO` when the other node closes the connection.
+        0 => return Err(Error::Io(libc::EIO)),
+        n if n > 0 => self.received(),
+        _ => match os_error() {
+          Error::Io(libc::EINTR) => {}
+          Error::Io(libc::EAGAIN) => return Ok(false),
+          error => return Err(error),
+        },
+      }
+    }
+  }
+
+  /// Decodes the whole frame read by [`read_available`], and answers it if it is a tick, as ei
+  /// does.
+  ///
+  /// [`read_available`]: #method.read_available
+  #[cfg(all(feature = "std", unix))]
+  fn take_frame(&mut self) -> Result<Received, Error> {
+    let decoded = match self.incoming.get(4..) {
+      Some([]) => None,
+      Some(body) => Some(decode_frame(body)),
+      None => return Err(Error::WouldBlock),
+    };
+    self.incoming.clear();
+    self.incoming.shrink_to(READ_CHUNK);
+    match decoded {
+      None => {
+        // Failing to answer is not a problem, as the next tick is answered anyway.
+        if write_all(self.fd, &[0; 4]).is_ok() {
+          self.sent();
+        }
+        Ok(Received::Tick)
+      }
+      Some(decoded) => {
+        let (msg, payload) = decoded?;
+        Ok(Received::Message { msg, payload })
+      }
+    }
+  }
+
+  /// Receives the next message or tick from the other node if the socket holds the rest of it,
+  /// or fails with [`Error::WouldBlock`] otherwise.
+  ///
+  /// What the socket holds of a message is kept for the next call, so that messages larger than
+  /// the buffer of the socket are received over several calls.
+  ///
+  /// This is meant for event loops that wait for the connection to be readable, which should
+  /// call [`heartbeat`] themselves if ticks are enabled. Ticks are returned either way.
+  ///
+  /// [`Error::WouldBlock`]: enum.Error.html#variant.WouldBlock
+  /// [`heartbeat`]: #method.heartbeat
+  #[cfg(all(feature = "std", unix))]
+  pub fn try_receive(&mut self) -> Result<Received, Error> {
+    if !self.read_available()? {
+      return Err(Error::WouldBlock);
+    }
+    self.take_frame()
+  }
+
+  #[cfg(not(all(feature = "std", unix)))]
+  fn receive_with(&mut self, ms: c_uint) -> Result<Received, Error> {
+    let mut msg: crate::erlang_msg = unsafe { mem::zeroed() };
+    let mut payload = XBuff::new()?;
+    let received =
+      match unsafe { crate::ei_xreceive_msg_tmo(self.fd, &mut msg, payload.as_mut_ptr(), ms) } {
+        crate::ERL_TICK => Received::Tick,
+        crate::ERL_MSG => Received::Message { msg, payload },
+        _ => return Err(last_error()),
+      };
+    self.received();
+    Ok(received)
+  }
+}
+
+#[cfg(all(feature = "std", unix))]
+impl std::os::unix::io::AsRawFd for Connection {
+  #[inline]
+  fn as_raw_fd(&self) -> c_int {
+    self.fd
+  }
+}
+
+#[cfg(all(feature = "mio", unix))]
+impl mio::event::Source for Connection {
+  fn register(
+    &mut self,
+    registry: &mio::Registry,
+    token: mio::Token,
+    interests: mio::Interest,
+  ) -> std::io::Result<()> {
+    mio::unix::SourceFd(&self.fd).register(registry, token, interests)
+  }
+
+  fn reregister(
+    &mut self,
+    registry: &mio::Registry,
+    token: mio::Token,
+    interests: mio::Interest,
+  ) -> std::io::Result<()> {
+    mio::unix::SourceFd(&self.fd).reregister(registry, token, interests)
+  }
+
+  fn deregister(&mut self, registry: &mio::Registry) -> std::io::Result<()> {
+    mio::unix::SourceFd(&self.fd).deregister(registry)
+  }
+}
+
+impl Drop for Connection {
+  fn drop(&mut self) {
+    unsafe { libc::close(self.fd) };
+  }
+}
+
+/// How much is left of a timeout spread over several waits, such as when ticks interrupt them.
+///
+/// Without the `std` feature there is no clock, so every wait gets the full timeout.
+pub(crate) struct Deadline {
+  timeout: Duration,
+  #[cfg(feature = "std")]
+  start: std::time::Instant,
+}
+
+impl Deadline {
+  pub(crate) fn new(timeout: Duration) -> Self {
+    Self {
+      timeout,
+      #[cfg(feature = "std")]
+      start: std::time::Instant::now(),
+    }
+  }
+
+  /// Returns the time left, or `None` once the deadline has passed.
+  pub(crate) fn remaining(&self) -> Option<Duration> {
+    #[cfg(feature = "std")]
+    return self
+      .timeout
+      .checked_sub(self.start.elapsed())
+      .filter(|left| !left.is_zero());
+    #[cfg(not(feature = "std"))]
+    return Some(self.timeout);
+  }
+}
+
+/// Converts `timeout` to the milliseconds expected by the `_tmo` functions of ei.
+///
+/// Since ei waits forever on a timeout of `0`, shorter timeouts are rounded up to one millisecond
+/// and longer ones are capped.
+pub(crate) fn millis(timeout: Duration) -> c_uint {
+  c_uint::try_from(timeout.as_millis())
+    .unwrap_or(c_uint::MAX)
+    .max(1)
+}
+
+fn write_all(fd: c_int, mut bytes: &[u8]) -> Result<(), Error> {
+  while !bytes.is_empty() {
+    let written = unsafe { libc::write(fd, bytes.as_ptr() as *const _, bytes.len() as _) };
+    if written < 0 {
+      let error = os_error();
+      if error == Error::Io(libc::EINTR) {
+        continue;
+      }
+      return Err(error);
+    }
+    bytes = &bytes[written as usize..];
+  }
+  Ok(())
+}
+
+/// Decodes the body of a frame, which holds a control message and the message it carries, if
+/// any, as ei does.
+///
+/// Like with ei, the payload is what follows the fields of the control message that are decoded
+/// into the header, such as the message of a `SEND` or the reason of an `EXIT`.
+#[cfg(all(feature = "std", unix))]
+fn decode_frame(body: &[u8]) -> Result<(crate::erlang_msg, XBuff), Error> {
+  let control = match body {
+    [crate::ERL_PASS_THROUGH, control @ ..] => control,
+    _ => return Err(Error::Decode),
+  };
+  if control.first() != Some(&crate::VERSION_MAGIC) {
+    return Err(Error::Decode);
+  }
+  let mut decoder = Decoder::new(&control[..term_end(control, 1)?])?;
+  decoder.decode_tuple_header()?;
+  let mut msg: crate::erlang_msg = unsafe { mem::zeroed() };
+  msg.msgtype = decoder.decode_long()?;
+  match u8::try_from(msg.msgtype).map_err(|_| Error::Decode)? {
+    // {SEND, Cookie, To} and {SEND_TT, Cookie, To, Token}
+    crate::ERL_SEND | crate::ERL_SEND_TT => {
+      decode_atom(&mut decoder, &mut msg.cookie)?;
+      msg.to = decoder.decode_pid()?;
+    }
+    // {REG_SEND, From, Cookie, ToName} and {REG_SEND_TT, From, Cookie, ToName, Token}
+    crate::ERL_REG_SEND | crate::ERL_REG_SEND_TT => {
+      msg.from = decoder.decode_pid()?;
+      decode_atom(&mut decoder, &mut msg.cookie)?;
+      decode_atom(&mut decoder, &mut msg.toname)?;
+    }
+    // {LINK, From, To}, {EXIT, From, To, Reason}, {EXIT_TT, From, To, Token, Reason}...
+    crate::ERL_LINK
+    | crate::ERL_UNLINK
+    | crate::ERL_GROUP_LEADER
+    | crate::ERL_EXIT
+    | crate::ERL_EXIT2
+    | crate::ERL_EXIT_TT
+    | crate::ERL_EXIT2_TT => {
+      msg.from = decoder.decode_pid()?;
+      msg.to = decoder.decode_pid()?;
+    }
+    crate::ERL_NODE_LINK => {}
+    _ => return Err(Error::Decode),
+  }
+  if matches!(
+    msg.msgtype as u8,
+    crate::ERL_SEND_TT | crate::ERL_REG_SEND_TT | crate::ERL_EXIT_TT | crate::ERL_EXIT2_TT
+  ) {
+    msg.token = decoder.decode_trace()?;
+  }
+
+  let mut payload = XBuff::new()?;
+  payload.extend_from_slice(&control[decoder.position()..])?;
+  Ok((msg, payload))
+}
+
+/// Decodes an atom into a field of an [`erlang_msg`].
+///
+/// [`erlang_msg`]: struct.erlang_msg.html
+#[cfg(all(feature = "std", unix))]
+fn decode_atom(decoder: &mut Decoder, field: &mut [c_char; MAXATOMLEN_UTF8]) -> Result<(), Error> {
+  let mut buf = [0; MAXATOMLEN_UTF8];
+  let atom = decoder.decode_atom(&mut buf)?;
+  c_str::copy(atom, field).map_err(|_| Error::Decode)
+}
+
+/// Returns the pointer and length of `buf`, as expected by the sending functions of ei, which
+/// do not write to the buffer despite their signature.
+fn raw_parts(buf: &XBuff) -> Result<(*mut c_char, c_int), Error> {
+  let bytes = buf.as_bytes();
+  let len = c_int::try_from(bytes.len()).map_err(|_| Error::InvalidArgument)?;
+  Ok((bytes.as_ptr() as *mut c_char, len))
+}
@@ -0,0 +1,281 @@
+//! Conversions between Rust values and terms.
+
+#[cfg(feature = "alloc")]
+use crate::ExtTag;
+use crate::{Decoder, Encoder, Error};
+#[cfg(feature = "alloc")]
+use alloc::{string::String, vec::Vec};
+use core::convert::TryFrom;
+
+/// A value that can be encoded as a term.
+///
+/// With the `derive` feature, this trait can be derived for structs and enums. Refer to the
+/// documentation of the `ei-sys-derive` crate for the supported shapes.
+pub trait ToTerm {
+  /// Encodes the value as exactly one term.
+  fn to_term(&self, encoder: &mut Encoder) -> Result<(), Error>;
+}
+
+/// A value that can be decoded from a term.
+///
+/// With the `derive` feature, this trait can be derived for structs and enums. Refer to the
+/// documentation of the `ei-sys-derive` crate for the supported shapes.
+pub trait FromTerm: Sized {
+  /// Decodes the value from exactly one term.
+  fn from_term(decoder: &mut Decoder) -> Result<Self, Error>;
+}
+
+impl<T: ToTerm + ?Sized> ToTerm for &T {
+  #[inline]
+  fn to_term(&self, encoder: &mut Encoder) -> Result<(), Error> {
+    (**self).to_term(encoder)
+  }
+}
+
+impl ToTerm for bool {
+  fn to_term(&self, encoder: &mut Encoder) -> Result<(), Error> {
+    encoder.boolean(*self)?;
+    Ok(())
+  }
+}
+
+impl FromTerm for bool {
+  fn from_term(decoder: &mut Decoder) -> Result<Self, Error> {
+    decoder.decode_boolean()
+  }
+}
+
+macro_rules! signed {
+  ($($t:ty)*) => {
+    $(
+      impl ToTerm for $t {
+        fn to_term(&self, encoder: &mut Encoder) -> Result<(), Error> {
+          encoder.longlong((*self).into())?;
+          Ok(())
+        }
+      }
+
+      impl FromTerm for $t {
+        fn from_term(decoder: &mut Decoder) -> Result<Self, Error> {
+          <$t>::try_from(decoder.decode_longlong()?).map_err(|_| Error::Decode)
+        }
+      }
+    )*
+  };
+}
+
+macro_rules! unsigned {
+  ($($t:ty)*) => {
+    $(
+      impl ToTerm for $t {
+        fn to_term(&self, encoder: &mut Encoder) -> Result<(), Error> {
+          encoder.ulonglong((*self).into())?;
+          Ok(())
+        }
+      }
+
+      impl FromTerm for $t {
+        fn from_term(decoder: &mut Decoder) -> Result<Self, Error> {
+          <$t>::try_from(decoder.decode_ulonglong()?).map_err(|_| Error::Decode)
+        }
+      }
+    )*
+  };
+}
+
+signed!(i8 i16 i32 i64);
+unsigned!(u8 u16 u32 u64);
+
+impl ToTerm for f64 {
+  fn to_term(&self, encoder: &mut Encoder) -> Result<(), Error> {
+    encoder.double(*self)?;
+    Ok(())
+  }
+}
+
+impl FromTerm for f64 {
+  fn from_term(decoder: &mut Decoder) -> Result<Self, Error> {
+    decoder.decode_double()
+  }
+}
+
+/// Strings are encoded as binaries.
+impl ToTerm for str {
+  fn to_term(&self, encoder: &mut Encoder) -> Result<(), Error> {
+    encoder.binary(self.as_bytes())?;
+    Ok(())
+  }
+}
+
+/// `None` is encoded as the atom `undefined`.
+impl<T: ToTerm> ToTerm for Option<T> {
+  fn to_term(&self, encoder: &mut Encoder) -> Result<(), Error> {
+    match self {
+      Some(value) => value.to_term(encoder),
+      None => {
+        encoder.atom("undefined")?;
+        Ok(())
+      }
+    }
+  }
+}
+
+impl<T: FromTerm> FromTerm for Option<T> {
+  fn from_term(decoder: &mut Decoder) -> Result<Self, Error> {
+    let mut buf = [0; crate::MAXATOMLEN_UTF8];
+    if decoder.clone().decode_atom(&mut buf) == Ok("undefined") {
+      decoder.skip()?;
+      return Ok(None);
+    }
+    T::from_term(decoder).map(Some)
+  }
+}
+
+impl ToTerm for crate::erlang_pid {
+  fn to_term(&self, encoder: &mut Encoder) -> Result<(), Error> {
+    encoder.pid(self)?;
+    Ok(())
+  }
+}
+
+impl FromTerm for crate::erlang_pid {
+  fn from_term(decoder: &mut Decoder) -> Result<Self, Error> {
+    decoder.decode_pid()
+  }
+}
+
+impl ToTerm for crate::erlang_ref {
+  fn to_term(&self, encoder: &mut Encoder) -> Result<(), Error> {
+    encoder.reference(self)?;
+    Ok(())
+  }
+}
+
+impl FromTerm for crate::erlang_ref {
+  fn from_term(decoder: &mut Decoder) -> Result<Self, Error> {
+    decoder.decode_ref()
+  }
+}
+
+/// Slices are encoded as proper lists.
+impl<T: ToTerm> ToTerm for [T] {
+  fn to_term(&self, encoder: &mut Encoder) -> Result<(), Error> {
+    encoder.list(self, |e, element| element.to_term(e))?;
+    Ok(())
+  }
+}
+
+macro_rules! tuples {
+  ($($len:expr => ($($t:ident $i:tt)*))*) => {
+    $(
+      impl<$($t: ToTerm),*> ToTerm for ($($t,)*) {
+        fn to_term(&self, encoder: &mut Encoder) -> Result<(), Error> {
+          encoder.tuple($len, |_e| {
+            $(self.$i.to_term(_e)?;)*
+            Ok(())
+          })?;
+          Ok(())
+        }
+      }
+
+      impl<$($t: FromTerm),*> FromTerm for ($($t,)*) {
+        fn from_term(decoder: &mut Decoder) -> Result<Self, Error> {
+          if decoder.decode_tuple_header()? != $len {
+            return Err(Error::Decode);
+          }
+          Ok(($($t::from_term(decoder)?,)*))
+        }
+      }
+    )*
+  };
+}
+
+tuples! {
+  0 => ()
+  1 => (A 0)
+  2 => (A 0 B 1)
+  3 => (A 0 B 1 C 2)
+  4 => (A 0 B 1 C 2 D 3)
+  5 => (A 0 B 1 C 2 D 3 E 4)
+  6 => (A 0 B 1 C 2 D 3 E 4 F 5)
+  7 => (A 0 B 1 C 2 D 3 E 4 F 5 G 6)
+  8 => (A 0 B 1 C 2 D 3 E 4 F 5 G 6 H 7)
+}
+
+#[cfg(feature = "alloc")]
+impl ToTerm for String {
+  #[inline]
+  fn to_term(&self, encoder: &mut Encoder) -> Result<(), Error> {
+    self.as_str().to_term(encoder)
+  }
+}
+
+/// Strings are decoded from binaries, or from lists of bytes, and must be valid UTF-8.
+#[cfg(feature = "alloc")]
+impl FromTerm for String {
+  fn from_term(decoder: &mut Decoder) -> Result<Self, Error> {
+    let bytes = match decoder.peek_type()? {
+      (ExtTag::Binary, _) => decoder.decode_binary()?.to_vec(),
+      (ExtTag::String, size) | (ExtTag::Nil, size) => {
+        let mut buf = alloc::vec![0; size + 1];
+        let len = decoder.decode_string(&mut buf)?.len();
+        buf.truncate(len);
+        buf
+      }
+      _ => return Err(Error::Decode),
+    };
+    String::from_utf8(bytes).map_err(|_| Error::Decode)
+  }
+}
+
+#[cfg(feature = "alloc")]
+impl<T: ToTerm> ToTerm for Vec<T> {
+  #[inline]
+  fn to_term(&self, encoder: &mut Encoder) -> Result<(), Error> {
+    self.as_slice().to_term(encoder)
+  }
+}
+
+/// Vectors are decoded from proper lists, including lists of bytes encoded as strings.
+#[cfg(feature = "alloc")]
+impl<T: FromTerm> FromTerm for Vec<T> {
+  fn from_term(decoder: &mut Decoder) -> Result<Self, Error> {
+    if let (ExtTag::String, size) = decoder.peek_type()? {
+      // Each byte of the string is re-encoded as a small integer, which T decodes.
+      let mut buf = alloc::vec![0; size + 1];
+      let bytes = decoder.decode_string(&mut buf)?;
+      let mut elements = Vec::with_capacity(bytes.len());
+      for &byte in bytes {
+        let encoded = [crate::SMALL_INTEGER_EXT, byte];
+        elements.push(T::from_term(&mut Decoder::new(&encoded)?)?);
+      }
+      return Ok(elements);
+    }
+
+    let arity = decoder.decode_list_header()?;
+    let mut elements = Vec::with_capacity(arity);
+    for _ in 0..arity {
+      elements.push(T::from_term(decoder)?);
+    }
+    if arity > 0 && decoder.decode_list_header()? != 0 {
+      return Err(Error::Decode);
+    }
+    Ok(elements)
+  }
+}
+
+#[cfg(feature = "alloc")]
+impl ToTerm for crate::Term {
+  #[inline]
+  fn to_term(&self, encoder: &mut Encoder) -> Result<(), Error> {
+    self.encode(encoder)
+  }
+}
+
+#[cfg(feature = "alloc")]
+impl FromTerm for crate::Term {
+  #[inline]
+  fn from_term(decoder: &mut Decoder) -> Result<Self, Error> {
+    crate::Term::decode(decoder)
+  }
+}
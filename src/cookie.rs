@@ -0,0 +1,196 @@
+//! Finding the cookie of a node, like Erlang does.
+
+use crate::{error::io_error, Error, EI_MAX_COOKIE_SIZE};
+use std::{
+  env, fs, io,
+  path::{Path, PathBuf},
+  string::String,
+};
+
+/// The environment variable [`CookieLookup`] reads by default.
+///
+/// [`CookieLookup`]: struct.CookieLookup.html
+pub const COOKIE_ENV_VAR: &str = "ERLANG_COOKIE";
+
+/// A cookie, checked to be usable with [`CNode::builder`].
+///
+/// [`CNode::builder`]: struct.CNode.html#method.builder
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cookie {
+  value: String,
+  source: CookieSource,
+}
+
+/// Where a [`Cookie`] was found.
+///
+/// [`Cookie`]: struct.Cookie.html
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CookieSource {
+  /// The value given to [`CookieLookup::explicit`].
+  ///
+  /// [`CookieLookup::explicit`]: struct.CookieLookup.html#method.explicit
+  Explicit,
+  /// The environment variable of that name.
+  Env(String),
+  /// The file at that path.
+  File(PathBuf),
+}
+
+/// Looks for a cookie in the following places, in order, and takes the first one found:
+///
+/// 1. the value given to [`explicit`], if any;
+/// 2. the environment variable [`COOKIE_ENV_VAR`], or the one given to [`env_var`];
+/// 3. the file `$HOME/.erlang.cookie`;
+/// 4. the file `$XDG_CONFIG_HOME/erlang/.erlang.cookie`, where `XDG_CONFIG_HOME` defaults to
+///    `$HOME/.config`.
+///
+/// Like Erlang, the first line of a file is the cookie, and files that other users can access are
+/// refused, on Unix.
+///
+/// [`explicit`]: #method.explicit
+/// [`COOKIE_ENV_VAR`]: constant.COOKIE_ENV_VAR.html
+/// [`env_var`]: #method.env_var
+#[derive(Clone, Debug)]
+pub struct CookieLookup<'a> {
+  explicit: Option<&'a str>,
+  env_var: Option<&'a str>,
+  check_permissions: bool,
+}
+
+impl Cookie {
+  /// Validates `value` as a cookie.
+  ///
+  /// Fails with [`Error::InvalidArgument`] if it is empty, longer than [`EI_MAX_COOKIE_SIZE`]
+  /// bytes or contains a null character.
+  ///
+  /// [`Error::InvalidArgument`]: enum.Error.html#variant.InvalidArgument
+  /// [`EI_MAX_COOKIE_SIZE`]: constant.EI_MAX_COOKIE_SIZE.html
+  pub fn new(value: &str, source: CookieSource) -> Result<Self, Error> {
+    if value.is_empty() || value.len() > EI_MAX_COOKIE_SIZE || value.contains('\0') {
+      return Err(Error::InvalidArgument);
+    }
+    Ok(Self {
+      value: value.into(),
+      source,
+    })
+  }
+
+  /// Finds a cookie with the default [`CookieLookup`].
+  ///
+  /// [`CookieLookup`]: struct.CookieLookup.html
+  pub fn find() -> Result<Self, Error> {
+    CookieLookup::new().find()
+  }
+
+  /// Returns the cookie.
+  #[inline]
+  pub fn as_str(&self) -> &str {
+    &self.value
+  }
+
+  /// Returns where the cookie was found.
+  #[inline]
+  pub fn source(&self) -> &CookieSource {
+    &self.source
+  }
+}
+
+impl<'a> CookieLookup<'a> {
+  /// Creates a lookup of the default places.
+  pub fn new() -> Self {
+    Self {
+      explicit: None,
+      env_var: Some(COOKIE_ENV_VAR),
+      check_permissions: true,
+    }
+  }
+
+  /// Sets a cookie that takes precedence over the other places, such as one given on the command
+  /// line. `None` is ignored.
+  pub fn explicit(&mut self, cookie: Option<&'a str>) -> &mut Self {
+    self.explicit = cookie;
+    self
+  }
+
+  /// Sets the environment variable to read the cookie from, or skips this step with `None`.
+  pub fn env_var(&mut self, name: Option<&'a str>) -> &mut Self {
+    self.env_var = name;
+    self
+  }
+
+  /// Sets whether files that other users can access are refused, `true` by default.
+  pub fn check_permissions(&mut self, check: bool) -> &mut Self {
+    self.check_permissions = check;
+    self
+  }
+
+  /// Returns the first cookie found.
+  ///
+  /// Fails with `Error::Io(ENOENT)` if there is none, with `Error::Io(EACCES)` if a file was found
+  /// but other users can access it, and with [`Error::InvalidArgument`] if the first cookie found
+  /// is invalid.
+  ///
+  /// [`Error::InvalidArgument`]: enum.Error.html#variant.InvalidArgument
+  pub fn find(&self) -> Result<Cookie, Error> {
+    if let Some(cookie) = self.explicit {
+      return Cookie::new(cookie, CookieSource::Explicit);
+    }
+    if let Some(name) = self.env_var {
+      if let Some(cookie) = env::var_os(name) {
+        let cookie = cookie.to_str().ok_or(Error::InvalidArgument)?;
+        return Cookie::new(cookie, CookieSource::Env(name.into()));
+      }
+    }
+
+    let home = home_dir();
+    let config = env::var_os("XDG_CONFIG_HOME")
+      .filter(|dir| !dir.is_empty())
+      .map(PathBuf::from)
+      .or_else(|| home.as_ref().map(|home| home.join(".config")));
+    let files = [
+      home.map(|home| home.join(".erlang.cookie")),
+      config.map(|config| config.join("erlang").join(".erlang.cookie")),
+    ];
+    for path in files.iter().flatten() {
+      if let Some(cookie) = self.read(path)? {
+        return Cookie::new(&cookie, CookieSource::File(path.clone()));
+      }
+    }
+    Err(Error::Io(libc::ENOENT))
+  }
+
+  /// Reads the first line of the file at `path`, or returns `None` if there is no such file.
+  fn read(&self, path: &Path) -> Result<Option<String>, Error> {
+    let contents = match fs::read_to_string(path) {
+      Ok(contents) => contents,
+      Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(None),
+      Err(error) if error.kind() == io::ErrorKind::InvalidData => {
+        return Err(Error::InvalidArgument)
+      }
+      Err(error) => return Err(io_error(error)),
+    };
+    #[cfg(unix)]
+    if self.check_permissions {
+      use std::os::unix::fs::PermissionsExt;
+      let mode = fs::metadata(path).map_err(io_error)?.permissions().mode();
+      if mode & 0o077 != 0 {
+        return Err(Error::Io(libc::EACCES));
+      }
+    }
+    let line = contents.lines().next().unwrap_or_default();
+    Ok(Some(line.trim_end().into()))
+  }
+}
+
+impl Default for CookieLookup<'_> {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+fn home_dir() -> Option<PathBuf> {
+  let home = env::var_os("HOME");
+  #[cfg(windows)]
+  let home = home.or_else(|| env::var_os("USERPROFILE"));
+  home.filter(|home| !home.is_empty()).map(PathBuf::from)
+}
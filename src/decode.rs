@@ -0,0 +1,440 @@
+//! A safe decoder of terms over the `ei_decode_*` functions.
+
+use crate::{
+  error::{check, Error},
+  ExtTag, XBuff,
+};
+use core::{convert::TryFrom, mem, ptr};
+use libc::{c_char, c_int, c_long, c_longlong, c_ulong, c_ulonglong};
+
+/// Decodes terms from a slice of bytes.
+///
+/// The decoder validates its input when it is created, so that the `ei_decode_*` functions never
+/// read past the end of the slice, even when they are given a truncated or malformed message.
+#[derive(Debug, Clone)]
+pub struct Decoder<'a> {
+  bytes: &'a [u8],
+  index: usize,
+}
+
+impl<'a> Decoder<'a> {
+  /// Creates a decoder over `bytes`, which must be a sequence of complete terms, optionally
+  /// preceded by the version magic byte.
+  ///
+  /// # Errors
+  ///
+  /// Returns [`Error::Decode`] if the last term is truncated, or if any term is malformed. Funs
+  /// nested more than 64 deep in the free variables of each other are also rejected.
+  ///
+  /// [`Error::Decode`]: enum.Error.html#variant.Decode
+  pub fn new(bytes: &'a [u8]) -> Result<Self, Error> {
+    if c_int::try_from(bytes.len()).is_err() {
+      return Err(Error::Decode);
+    }
+
+    let start = match bytes.first() {
+      Some(&crate::VERSION_MAGIC) => 1,
+      _ => 0,
+    };
+    let mut index = start;
+    while index < bytes.len() {
+      index = term_end(bytes, index)?;
+    }
+    Ok(Decoder {
+      bytes,
+      index: start,
+    })
+  }
+
+  /// Creates a decoder over `bytes`, which were already validated.
+  #[inline]
+  pub(crate) fn new_unchecked(bytes: &'a [u8]) -> Self {
+    Decoder { bytes, index: 0 }
+  }
+
+  /// Creates a decoder over the terms of `buf`.
+  #[inline]
+  pub fn from_x_buff(buf: &'a XBuff) -> Result<Self, Error> {
+    Self::new(buf.as_bytes())
+  }
+
+  /// Returns the position of the decoder in its input.
+  #[inline]
+  pub fn position(&self) -> usize {
+    self.index
+  }
+
+  /// Returns `true` if all the terms of the input were decoded.
+  #[inline]
+  pub fn is_empty(&self) -> bool {
+    self.index >= self.bytes.len()
+  }
+
+  /// Returns the bytes that remain to be decoded.
+  #[inline]
+  pub fn remaining(&self) -> &'a [u8] {
+    &self.bytes[self.index..]
+  }
+
+  fn call<F>(&mut self, f: F) -> Result<(), Error>
+  where
+    F: FnOnce(*const c_char, *mut c_int) -> c_int,
+  {
+    if self.is_empty() {
+      return Err(Error::Decode);
+    }
+
+    let mut index = self.index as c_int;
+    check(
+      f(self.bytes.as_ptr() as *const c_char, &mut index),
+      Error::Decode,
+    )?;
+    self.index = index as usize;
+    Ok(())
+  }
+
+  /// Returns the tag and size of the next term, as reported by [`ei_get_type`], without decoding
+  /// it.
+  ///
+  /// [`ei_get_type`]: fn.ei_get_type.html
+  pub fn peek_type(&self) -> Result<(ExtTag, usize), Error> {
+    if self.is_empty() {
+      return Err(Error::Decode);
+    }
+
+    let index = self.index as c_int;
+    let mut type_ = 0;
+    let mut size = 0;
+    let code = unsafe {
+      crate::ei_get_type(
+        self.bytes.as_ptr() as *const c_char,
+        &index,
+        &mut type_,
+        &mut size,
+      )
+    };
+    check(code, Error::Decode)?;
+    let tag = ExtTag::try_from(type_ as u8).map_err(|_| Error::Decode)?;
+    Ok((tag, size as usize))
+  }
+
+  /// Skips the next term.
+  pub fn skip(&mut self) -> Result<(), Error> {
+    self.call(|buf, index| unsafe { crate::ei_skip_term(buf, index) })
+  }
+
+  /// Decodes an integer that fits in a `c_long`.
+  pub fn decode_long(&mut self) -> Result<c_long, Error> {
+    let mut value = 0;
+    self.call(|buf, index| unsafe { crate::ei_decode_long(buf, index, &mut value) })?;
+    Ok(value)
+  }
+
+  /// Decodes a non-negative integer that fits in a `c_ulong`.
+  pub fn decode_ulong(&mut self) -> Result<c_ulong, Error> {
+    let mut value = 0;
+    self.call(|buf, index| unsafe { crate::ei_decode_ulong(buf, index, &mut value) })?;
+    Ok(value)
+  }
+
+  /// Decodes an integer that fits in a `c_longlong`.
+  pub fn decode_longlong(&mut self) -> Result<c_longlong, Error> {
+    let mut value = 0;
+    self.call(|buf, index| unsafe { crate::ei_decode_longlong(buf, index, &mut value) })?;
+    Ok(value)
+  }
+
+  /// Decodes a non-negative integer that fits in a `c_ulonglong`.
+  pub fn decode_ulonglong(&mut self) -> Result<c_ulonglong, Error> {
+    let mut value = 0;
+    self.call(|buf, index| unsafe { crate::ei_decode_ulonglong(buf, index, &mut value) })?;
+    Ok(value)
+  }
+
+  /// Decodes a float.
+  pub fn decode_double(&mut self) -> Result<f64, Error> {
+    let mut value = 0.0;
+    self.call(|buf, index| unsafe { crate::ei_decode_double(buf, index, &mut value) })?;
+    Ok(value)
+  }
+
+  /// Decodes the atoms `true` or `false`.
+  pub fn decode_boolean(&mut self) -> Result<bool, Error> {
+    let mut value = 0;
+    self.call(|buf, index| unsafe { crate::ei_decode_boolean(buf, index, &mut value) })?;
+    Ok(value != 0)
+  }
+
+  /// Decodes an atom into `buf`, converting it to UTF-8, and returns its name.
+  ///
+  /// A buffer of [`MAXATOMLEN_UTF8`] bytes is large enough to hold any atom.
+  ///
+  /// [`MAXATOMLEN_UTF8`]: constant.MAXATOMLEN_UTF8.html
+  pub fn decode_atom<'b>(&mut self, buf: &'b mut [u8]) -> Result<&'b str, Error> {
+    let destlen = c_int::try_from(buf.len()).unwrap_or(c_int::MAX);
+    self.call(|bytes, index| unsafe {
+      crate::ei_decode_atom_as(
+        bytes,
+        index,
+        buf.as_mut_ptr() as *mut c_char,
+        destlen,
+        crate::ERLANG_UTF8,
+        ptr::null_mut(),
+        ptr::null_mut(),
+      )
+    })?;
+    let len = buf.iter().position(|&b| b == 0).unwrap_or(buf.len());
+    core::str::from_utf8(&buf[..len]).map_err(|_| Error::Decode)
+  }
+
+  /// Decodes a list of bytes into `buf`, and returns the bytes.
+  ///
+  /// The buffer must be one byte longer than the list, which is the size reported by
+  /// [`peek_type`].
+  ///
+  /// # Errors
+  ///
+  /// Returns [`Error::BufferTooSmall`] if the list does not fit in `buf`.
+  ///
+  /// [`peek_type`]: #method.peek_type
+  /// [`Error::BufferTooSmall`]: enum.Error.html#variant.BufferTooSmall
+  pub fn decode_string<'b>(&mut self, buf: &'b mut [u8]) -> Result<&'b [u8], Error> {
+    let (_, size) = self.peek_type()?;
+    if size >= buf.len() {
+      return Err(Error::BufferTooSmall);
+    }
+
+    self.call(|bytes, index| unsafe {
+      crate::ei_decode_string(bytes, index, buf.as_mut_ptr() as *mut c_char)
+    })?;
+    Ok(&buf[..size])
+  }
+
+  /// Decodes a binary, borrowing its bytes from the input.
+  pub fn decode_binary(&mut self) -> Result<&'a [u8], Error> {
+    let start = self.index + 5;
+    let mut len: c_long = 0;
+    self.call(|buf, index| unsafe {
+      crate::ei_decode_binary(buf, index, ptr::null_mut(), &mut len)
+    })?;
+    Ok(&self.bytes[start..start + len as usize])
+  }
+
+  /// Decodes a process identifier.
+  pub fn decode_pid(&mut self) -> Result<crate::erlang_pid, Error> {
+    let mut pid: crate::erlang_pid = unsafe { mem::zeroed() };
+    self.call(|buf, index| unsafe { crate::ei_decode_pid(buf, index, &mut pid) })?;
+    Ok(pid)
+  }
+
+  /// Decodes the trace token of a control message.
+  #[cfg(all(feature = "std", unix))]
+  pub(crate) fn decode_trace(&mut self) -> Result<crate::erlang_trace, Error> {
+    let mut trace: crate::erlang_trace = unsafe { mem::zeroed() };
+    self.call(|buf, index| unsafe { crate::ei_decode_trace(buf, index, &mut trace) })?;
+    Ok(trace)
+  }
+
+  /// Decodes a port identifier.
+  pub fn decode_port(&mut self) -> Result<crate::erlang_port, Error> {
+    let mut port: crate::erlang_port = unsafe { mem::zeroed() };
+    self.call(|buf, index| unsafe { crate::ei_decode_port(buf, index, &mut port) })?;
+    Ok(port)
+  }
+
+  /// Decodes a reference.
+  pub fn decode_ref(&mut self) -> Result<crate::erlang_ref, Error> {
+    let mut reference: crate::erlang_ref = unsafe { mem::zeroed() };
+    self.call(|buf, index| unsafe { crate::ei_decode_ref(buf, index, &mut reference) })?;
+    Ok(reference)
+  }
+
+  /// Decodes the header of a tuple and returns its arity. The elements follow.
+  pub fn decode_tuple_header(&mut self) -> Result<usize, Error> {
+    let mut arity = 0;
+    self.call(|buf, index| unsafe { crate::ei_decode_tuple_header(buf, index, &mut arity) })?;
+    Ok(arity as usize)
+  }
+
+  /// Decodes the header of a list and returns its arity. The elements follow, and then the tail,
+  /// unless the list is empty.
+  pub fn decode_list_header(&mut self) -> Result<usize, Error> {
+    let mut arity = 0;
+    self.call(|buf, index| unsafe { crate::ei_decode_list_header(buf, index, &mut arity) })?;
+    Ok(arity as usize)
+  }
+
+  /// Decodes the header of a map and returns its arity. The keys and values follow, alternating.
+  pub fn decode_map_header(&mut self) -> Result<usize, Error> {
+    let mut arity = 0;
+    self.call(|buf, index| unsafe { crate::ei_decode_map_header(buf, index, &mut arity) })?;
+    Ok(arity as usize)
+  }
+}
+
+fn read_u8(bytes: &[u8], index: usize) -> Result<usize, Error> {
+  bytes.get(index).map(|&b| b as usize).ok_or(Error::Decode)
+}
+
+fn read_u16(bytes: &[u8], index: usize) -> Result<usize, Error> {
+  match bytes.get(index..index + 2) {
+    Some(b) => Ok(u16::from_be_bytes([b[0], b[1]]) as usize),
+    None => Err(Error::Decode),
+  }
+}
+
+fn read_u32(bytes: &[u8], index: usize) -> Result<usize, Error> {
+  match bytes.get(index..index + 4) {
+    Some(b) => Ok(u32::from_be_bytes([b[0], b[1], b[2], b[3]]) as usize),
+    None => Err(Error::Decode),
+  }
+}
+
+fn skip(bytes: &[u8], index: usize, len: usize) -> Result<usize, Error> {
+  match index.checked_add(len) {
+    Some(end) if end <= bytes.len() => Ok(end),
+    _ => Err(Error::Decode),
+  }
+}
+
+/// Returns the end of the atom starting at `index`, as found in the node of pids, ports and
+/// references.
+fn atom_end(bytes: &[u8], index: usize) -> Result<usize, Error> {
+  match bytes.get(index).copied() {
+    Some(crate::ATOM_EXT) | Some(crate::ATOM_UTF8_EXT) => {
+      skip(bytes, index + 3, read_u16(bytes, index + 1)?)
+    }
+    Some(crate::SMALL_ATOM_EXT) | Some(crate::SMALL_ATOM_UTF8_EXT) => {
+      skip(bytes, index + 2, read_u8(bytes, index + 1)?)
+    }
+    _ => Err(Error::Decode),
+  }
+}
+
+/// Returns the end of the integer starting at `index`, as found in the fields of funs.
+fn integer_end(bytes: &[u8], index: usize) -> Result<usize, Error> {
+  match bytes.get(index).copied() {
+    Some(crate::SMALL_INTEGER_EXT) => skip(bytes, index + 1, 1),
+    Some(crate::INTEGER_EXT) => skip(bytes, index + 1, 4),
+    Some(crate::SMALL_BIG_EXT) => skip(bytes, index + 3, read_u8(bytes, index + 1)?),
+    Some(crate::LARGE_BIG_EXT) => skip(bytes, index + 6, read_u32(bytes, index + 1)?),
+    _ => Err(Error::Decode),
+  }
+}
+
+/// Returns the end of the pid starting at `index`, as found in the fields of funs.
+fn pid_end(bytes: &[u8], index: usize) -> Result<usize, Error> {
+  match bytes.get(index).copied() {
+    Some(crate::PID_EXT) => skip(bytes, atom_end(bytes, index + 1)?, 9),
+    Some(crate::NEW_PID_EXT) => skip(bytes, atom_end(bytes, index + 1)?, 12),
+    _ => Err(Error::Decode),
+  }
+}
+
+/// How deep funs may be nested in the free variables of each other.
+const MAX_FUN_DEPTH: usize = 64;
+
+/// Returns the end of the term starting at `index` in `bytes`, checking that it is complete.
+///
+/// This only validates the structure of the term, so that the `ei_decode_*` functions do not read
+/// out of bounds. The contents, such as the encoding of atoms, are validated by the functions
+/// themselves.
+///
+/// Funs carry their own size, which must match their fields exactly. Funs nested more than
+/// `MAX_FUN_DEPTH` deep in the free variables of each other are rejected.
+pub(crate) fn term_end(bytes: &[u8], mut index: usize) -> Result<usize, Error> {
+  // Compound terms are walked iteratively, by counting the terms that remain to be skipped, so
+  // deeply nested input does not overflow the stack.
+  let mut pending: usize = 1;
+  // The end of each fun whose free variables are being walked, with the number of terms that
+  // remain to be skipped once they are.
+  let mut funs = [(0, 0); MAX_FUN_DEPTH];
+  let mut depth = 0;
+  loop {
+    while depth > 0 && funs[depth - 1].1 == pending {
+      if index != funs[depth - 1].0 {
+        return Err(Error::Decode);
+      }
+      depth -= 1;
+    }
+    if pending == 0 {
+      break;
+    }
+
+    pending -= 1;
+    let tag = read_u8(bytes, index)? as u8;
+    index += 1;
+    let (end, elements) = match tag {
+      crate::SMALL_INTEGER_EXT => (skip(bytes, index, 1)?, 0),
+      crate::INTEGER_EXT => (skip(bytes, index, 4)?, 0),
+      crate::FLOAT_EXT => (skip(bytes, index, 31)?, 0),
+      crate::NEW_FLOAT_EXT => (skip(bytes, index, 8)?, 0),
+      crate::ATOM_EXT
+      | crate::ATOM_UTF8_EXT
+      | crate::SMALL_ATOM_EXT
+      | crate::SMALL_ATOM_UTF8_EXT => (atom_end(bytes, index - 1)?, 0),
+      crate::REFERENCE_EXT => (skip(bytes, atom_end(bytes, index)?, 5)?, 0),
+      crate::NEW_REFERENCE_EXT | crate::NEWER_REFERENCE_EXT => {
+        let len = read_u16(bytes, index)?;
+        let creation = if tag == crate::NEW_REFERENCE_EXT {
+          1
+        } else {
+          4
+        };
+        (
+          skip(bytes, atom_end(bytes, index + 2)?, creation + 4 * len)?,
+          0,
+        )
+      }
+      crate::PORT_EXT => (skip(bytes, atom_end(bytes, index)?, 5)?, 0),
+      crate::NEW_PORT_EXT => (skip(bytes, atom_end(bytes, index)?, 8)?, 0),
+      crate::PID_EXT | crate::NEW_PID_EXT => (pid_end(bytes, index - 1)?, 0),
+      crate::SMALL_TUPLE_EXT => (index + 1, read_u8(bytes, index)?),
+      crate::LARGE_TUPLE_EXT => (index + 4, read_u32(bytes, index)?),
+      crate::NIL_EXT => (index, 0),
+      crate::STRING_EXT => (skip(bytes, index + 2, read_u16(bytes, index)?)?, 0),
+      crate::LIST_EXT => {
+        let tail = read_u32(bytes, index)?.checked_add(1);
+        (index + 4, tail.ok_or(Error::Decode)?)
+      }
+      crate::BINARY_EXT => (skip(bytes, index + 4, read_u32(bytes, index)?)?, 0),
+      crate::BIT_BINARY_EXT => (skip(bytes, index + 5, read_u32(bytes, index)?)?, 0),
+      crate::SMALL_BIG_EXT => (skip(bytes, index + 2, read_u8(bytes, index)?)?, 0),
+      crate::LARGE_BIG_EXT => (skip(bytes, index + 5, read_u32(bytes, index)?)?, 0),
+      crate::NEW_FUN_EXT => {
+        // The size counts itself, and is followed by the arity, the MD5 of the module, the index
+        // and the number of free variables.
+        let fun = &bytes[..skip(bytes, index, read_u32(bytes, index)?)?];
+        let num_free = read_u32(fun, index + 25)?;
+        let fields = skip(fun, index, 29)?;
+        let old_index = atom_end(fun, fields)?;
+        let old_uniq = integer_end(fun, old_index)?;
+        let pid = integer_end(fun, old_uniq)?;
+        let free_vars = pid_end(fun, pid)?;
+        if depth == MAX_FUN_DEPTH {
+          return Err(Error::Decode);
+        }
+        funs[depth] = (fun.len(), pending);
+        depth += 1;
+        (free_vars, num_free)
+      }
+      crate::EXPORT_EXT => (index, 3),
+      crate::MAP_EXT => {
+        let entries = read_u32(bytes, index)?.checked_mul(2);
+        (index + 4, entries.ok_or(Error::Decode)?)
+      }
+      crate::FUN_EXT => {
+        let fields = read_u32(bytes, index)?.checked_add(4);
+        (index + 4, fields.ok_or(Error::Decode)?)
+      }
+      _ => return Err(Error::Decode),
+    };
+    index = end;
+    pending = pending.checked_add(elements).ok_or(Error::Decode)?;
+    // Every term takes at least one byte, which bounds the work done on malicious arities.
+    if pending > bytes.len() - index {
+      return Err(Error::Decode);
+    }
+  }
+  Ok(index)
+}
@@ -0,0 +1,713 @@
+//! Routing of the messages received by a C node to several mailboxes.
+
+use crate::{c_str, CNode, Connection, Error, Received, ToTerm, XBuff};
+use alloc::{boxed::Box, collections::BTreeMap, string::String, vec::Vec};
+use libc::{c_long, c_uint};
+
+/// What a [`Mailbox`] receives.
+///
+/// [`Mailbox`]: trait.Mailbox.html
+pub enum Signal<'a> {
+  /// An ordinary message, sent to the pid of the mailbox or to its registered name.
+  Message {
+    /// The header of the message.
+    msg: &'a crate::erlang_msg,
+    /// The payload of the message, starting with the version byte.
+    payload: &'a XBuff,
+  },
+  /// The process `from` linked to the mailbox.
+  Link {
+    /// The process on the other end of the link.
+    from: &'a crate::erlang_pid,
+  },
+  /// The process `from` removed its link to the mailbox.
+  Unlink {
+    /// The process on the other end of the link.
+    from: &'a crate::erlang_pid,
+  },
+  /// The process `from` sent an exit signal to the mailbox, either because it is linked to it and
+  /// exited or with `exit/2`.
+  Exit {
+    /// The process that sent the signal.
+    from: &'a crate::erlang_pid,
+    /// The reason of the exit, starting with the version byte.
+    reason: &'a XBuff,
+    /// `true` if the signal was sent by `exit/2`, rather than by a link.
+    explicit: bool,
+  },
+}
+
+/// A process of this node, which receives the signals sent to its pid by a [`Dispatcher`].
+///
+/// Closures taking a connection and a signal are mailboxes.
+///
+/// [`Dispatcher`]: struct.Dispatcher.html
+pub trait Mailbox {
+  /// Handles a signal received on `connection`, which can be used to reply.
+  fn deliver(&mut self, connection: &mut Connection, signal: Signal);
+}
+
+impl<F> Mailbox for F
+where
+  F: FnMut(&mut Connection, Signal),
+{
+  #[inline]
+  fn deliver(&mut self, connection: &mut Connection, signal: Signal) {
+    self(connection, signal)
+  }
+}
+
+/// A table of the processes of a C node, to route the messages it receives to them.
+///
+/// Each mailbox gets its own pid, minted from the pid of the node, and can be registered under
+/// names.
+///
+/// The dispatcher also keeps track of the links between its mailboxes and the processes of other
+/// nodes, so that they can be supervised like Erlang processes: when a mailbox exits with
+/// [`exit`], the processes linked to it are sent exit signals, and when a connection is lost,
+/// [`disconnected`] sends `noconnection` exit signals to the mailboxes linked to its processes.
+///
+/// [`exit`]: #method.exit
+/// [`disconnected`]: #method.disconnected
+pub struct Dispatcher {
+  node: CNode,
+  mailboxes: BTreeMap<LocalPid, Box<dyn Mailbox + Send>>,
+  names: BTreeMap<String, LocalPid>,
+  links: BTreeMap<LocalPid, Vec<crate::erlang_pid>>,
+  next: c_uint,
+  serial: c_uint,
+}
+
+/// The largest number of a pid, which is 15 bits in older pid encodings.
+const MAX_NUM: c_uint = 0x7fff;
+/// The largest serial of a pid, which is 13 bits in older pid encodings.
+const MAX_SERIAL: c_uint = 0x1fff;
+
+/// The part of a pid that identifies a process of this node.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+struct LocalPid {
+  num: c_uint,
+  serial: c_uint,
+}
+
+impl LocalPid {
+  fn of(pid: &crate::erlang_pid) -> Self {
+    Self {
+      num: pid.num,
+      serial: pid.serial,
+    }
+  }
+}
+
+impl Dispatcher {
+  /// Creates a dispatcher for the processes of `node`.
+  pub fn new(node: &CNode) -> Self {
+    Self {
+      node: node.clone(),
+      mailboxes: BTreeMap::new(),
+      names: BTreeMap::new(),
+      links: BTreeMap::new(),
+      next: 0,
+      serial: node.pid().serial,
+    }
+  }
+
+  /// Adds `mailbox` to the table and returns its new pid.
+  ///
+  /// As in the VM, the numbers of the pids are handed out in turn, and their serial is bumped
+  /// each time the numbers wrap around, so that the pid of a removed mailbox is not soon reused.
+  ///
+  /// Fails with [`Error::Alloc`] if all the numbers are taken.
+  ///
+  /// [`Error::Alloc`]: enum.Error.html#variant.Alloc
+  pub fn spawn<M: Mailbox + Send + 'static>(
+    &mut self,
+    mailbox: M,
+  ) -> Result<crate::erlang_pid, Error> {
+    // The number of the pid of the node itself is left alone.
+    if self.mailboxes.len() >= MAX_NUM as usize {
+      return Err(Error::Alloc);
+    }
+    loop {
+      self.next = (self.next + 1) & MAX_NUM;
+      if self.next == 0 {
+        self.serial = (self.serial + 1) & MAX_SERIAL;
+      }
+      if self.next != self.node.pid().num && !self.num_in_use(self.next) {
+        break;
+      }
+    }
+    let mut pid = self.node.pid().clone();
+    pid.num = self.next;
+    pid.serial = self.serial;
+    self.mailboxes.insert(LocalPid::of(&pid), Box::new(mailbox));
+    Ok(pid)
+  }
+
+  /// Returns `true` if a mailbox has a pid with the number `num`, whatever its serial.
+  fn num_in_use(&self, num: c_uint) -> bool {
+    let first = LocalPid { num, serial: 0 };
+    let last = LocalPid {
+      num,
+      serial: c_uint::MAX,
+    };
+    self.mailboxes.range(first..=last).next().is_some()
+  }
+
+  /// Registers the mailbox `pid` under `name`, replacing any previous registration of the name.
+  ///
+  /// Fails with [`Error::InvalidArgument`] if there is no such mailbox.
+  ///
+  /// [`Error::InvalidArgument`]: enum.Error.html#variant.InvalidArgument
+  pub fn register(&mut self, name: &str, pid: &crate::erlang_pid) -> Result<(), Error> {
+    let key = LocalPid::of(pid);
+    if !self.mailboxes.contains_key(&key) {
+      return Err(Error::InvalidArgument);
+    }
+    self.names.insert(name.into(), key);
+    Ok(())
+  }
+
+  /// Removes the registration of `name`.
+  pub fn unregister(&mut self, name: &str) {
+    self.names.remove(name);
+  }
+
+  /// Removes the mailbox `pid`, its names and its links from the table, and returns it.
+  ///
+  /// The processes linked to the mailbox are not notified, unlike with [`exit`].
+  ///
+  /// [`exit`]: #method.exit
+  pub fn remove(&mut self, pid: &crate::erlang_pid) -> Option<Box<dyn Mailbox + Send>> {
+    let key = LocalPid::of(pid);
+    self.names.retain(|_, registered| *registered != key);
+    self.links.remove(&key);
+    self.mailboxes.remove(&key)
+  }
+
+  /// Links the mailbox `pid` to the process `to` on the other end of `connection`.
+  ///
+  /// Fails with [`Error::InvalidArgument`] if there is no such mailbox.
+  ///
+  /// [`Error::InvalidArgument`]: enum.Error.html#variant.InvalidArgument
+  pub fn link(
+    &mut self,
+    connection: &mut Connection,
+    pid: &crate::erlang_pid,
+    to: &crate::erlang_pid,
+  ) -> Result<(), Error> {
+    let key = LocalPid::of(pid);
+    if !self.mailboxes.contains_key(&key) {
+      return Err(Error::InvalidArgument);
+    }
+    connection.link(pid, to)?;
+    self.add_link(key, to);
+    Ok(())
+  }
+
+  /// Removes the link between the mailbox `pid` and the process `to` on the other end of
+  /// `connection`.
+  ///
+  /// Fails with [`Error::InvalidArgument`] if there is no such mailbox.
+  ///
+  /// [`Error::InvalidArgument`]: enum.Error.html#variant.InvalidArgument
+  pub fn unlink(
+    &mut self,
+    connection: &mut Connection,
+    pid: &crate::erlang_pid,
+    to: &crate::erlang_pid,
+  ) -> Result<(), Error> {
+    let key = LocalPid::of(pid);
+    if !self.mailboxes.contains_key(&key) {
+      return Err(Error::InvalidArgument);
+    }
+    connection.unlink(pid, to)?;
+    self.remove_link(key, to);
+    Ok(())
+  }
+
+  /// Returns the processes linked to the mailbox `pid`.
+  pub fn links(&self, pid: &crate::erlang_pid) -> &[crate::erlang_pid] {
+    self
+      .links
+      .get(&LocalPid::of(pid))
+      .map_or(&[], |links| links.as_slice())
+  }
+
+  /// Removes the mailbox `pid` from the table, like [`remove`], and sends an exit signal with
+  /// `reason` to the processes linked to it on the other end of `connection`.
+  ///
+  /// Links to the processes of other nodes are dropped, since they are not reachable through
+  /// `connection`. If the [`peer_name`] of `connection` is empty, the signal is sent to all of
+  /// them.
+  ///
+  /// [`peer_name`]: struct.Connection.html#method.peer_name
+  ///
+  /// [`remove`]: #method.remove
+  pub fn exit<R: ToTerm>(
+    &mut self,
+    connection: &mut Connection,
+    pid: &crate::erlang_pid,
+    reason: R,
+  ) -> Result<Option<Box<dyn Mailbox + Send>>, Error> {
+    let links = self.links.remove(&LocalPid::of(pid)).unwrap_or_default();
+    let mailbox = self.remove(pid);
+    for to in &links {
+      if on_peer(connection, to) {
+        connection.exit(pid, to, &reason)?;
+      }
+    }
+    Ok(mailbox)
+  }
+
+  /// Breaks the links to the processes on the other end of `connection`, which was lost, and
+  /// delivers `noconnection` exit signals to the mailboxes that were linked to them.
+  ///
+  /// The processes are told apart by the [`peer_name`] of `connection`, which is empty for
+  /// connections opened with [`CNode::connect_addr`] or [`Connection::from_raw_fd`]. All the links
+  /// are then broken, including those to the processes of other nodes.
+  ///
+  /// [`peer_name`]: struct.Connection.html#method.peer_name
+  /// [`CNode::connect_addr`]: struct.CNode.html#method.connect_addr
+  /// [`Connection::from_raw_fd`]: struct.Connection.html#method.from_raw_fd
+  pub fn disconnected(&mut self, connection: &mut Connection) -> Result<(), Error> {
+    let mut reason = XBuff::with_version()?;
+    reason.encoder().atom("noconnection")?;
+    for (key, links) in self.links.iter_mut() {
+      let (broken, kept) = links.drain(..).partition(|to| on_peer(connection, to));
+      *links = kept;
+      if let Some(mailbox) = self.mailboxes.get_mut(key) {
+        for from in &broken {
+          mailbox.deliver(
+            connection,
+            Signal::Exit {
+              from,
+              reason: &reason,
+              explicit: false,
+            },
+          );
+        }
+      }
+    }
+    self.links.retain(|_, links| !links.is_empty());
+    Ok(())
+  }
+
+  fn add_link(&mut self, key: LocalPid, to: &crate::erlang_pid) {
+    let links = self.links.entry(key).or_default();
+    if !links.iter().any(|linked| same_pid(linked, to)) {
+      links.push(to.clone());
+    }
+  }
+
+  fn remove_link(&mut self, key: LocalPid, to: &crate::erlang_pid) {
+    if let Some(links) = self.links.get_mut(&key) {
+      links.retain(|linked| !same_pid(linked, to));
+      if links.is_empty() {
+        self.links.remove(&key);
+      }
+    }
+  }
+
+  /// Returns `true` if `pid` is the pid of a mailbox of the table.
+  pub fn contains(&self, pid: &crate::erlang_pid) -> bool {
+    self.mailboxes.contains_key(&LocalPid::of(pid))
+  }
+
+  /// Returns the pid of the mailbox registered under `name`, if any.
+  pub fn whereis(&self, name: &str) -> Option<crate::erlang_pid> {
+    let key = self.names.get(name)?;
+    let mut pid = self.node.pid().clone();
+    pid.num = key.num;
+    pid.serial = key.serial;
+    Some(pid)
+  }
+
+  /// Delivers what was received on `connection` to the mailbox it is addressed to, and keeps
+  /// track of the links made and removed by the other node.
+  ///
+  /// Returns the message back if it is not addressed to any mailbox of the table, such as a
+  /// message sent to the pid of the node itself. Ticks are consumed.
+  pub fn dispatch(
+    &mut self,
+    connection: &mut Connection,
+    received: Received,
+  ) -> Result<Option<Received>, Error> {
+    let (msg, payload) = match received {
+      Received::Tick => return Ok(None),
+      Received::Message { msg, payload } => (msg, payload),
+    };
+
+    let key = if msg.msgtype == c_long::from(crate::ERL_REG_SEND) {
+      c_str::to_str(&msg.toname)
+        .ok()
+        .and_then(|name| self.names.get(name).copied())
+    } else {
+      Some(LocalPid::of(&msg.to))
+    };
+    let key = match key.filter(|key| self.mailboxes.contains_key(key)) {
+      Some(key) => key,
+      None => return Ok(Some(Received::Message { msg, payload })),
+    };
+    match msg.msgtype as u8 {
+      crate::ERL_LINK => self.add_link(key, &msg.from),
+      crate::ERL_UNLINK | crate::ERL_EXIT => self.remove_link(key, &msg.from),
+      _ => {}
+    }
+
+    let signal = match msg.msgtype as u8 {
+      crate::ERL_SEND | crate::ERL_REG_SEND => Signal::Message {
+        msg: &msg,
+        payload: &payload,
+      },
+      crate::ERL_LINK => Signal::Link { from: &msg.from },
+      crate::ERL_UNLINK => Signal::Unlink { from: &msg.from },
+      crate::ERL_EXIT | crate::ERL_EXIT2 => Signal::Exit {
+        from: &msg.from,
+        reason: &payload,
+        explicit: msg.msgtype == c_long::from(crate::ERL_EXIT2),
+      },
+      _ => return Ok(Some(Received::Message { msg, payload })),
+    };
+    if let Some(mailbox) = self.mailboxes.get_mut(&key) {
+      mailbox.deliver(connection, signal);
+    }
+    Ok(None)
+  }
+}
+
+/// Returns `true` if `pid` is a process on the other end of `connection`, or if that is unknown.
+fn on_peer(connection: &Connection, pid: &crate::erlang_pid) -> bool {
+  let peer = connection.peer_name();
+  peer.is_empty() || c_str::to_str(&pid.node) == Ok(peer)
+}
+
+fn same_pid(a: &crate::erlang_pid, b: &crate::erlang_pid) -> bool {
+  a.num == b.num && a.serial == b.serial && c_str::to_str(&a.node) == c_str::to_str(&b.node)
+}
+
+#[cfg(all(test, feature = "std", unix))]
+mod tests {
+  use super::*;
+  use alloc::vec;
+  use core::mem;
+  use std::{
+    io::Read,
+    os::unix::{io::IntoRawFd, net::UnixStream},
+    sync::{Arc, Mutex},
+  };
+
+  const PEER: &str = "peer@host";
+
+  /// An owned copy of a signal, as recorded by a mailbox.
+  #[derive(Debug, PartialEq)]
+  enum Got {
+    Message(Vec<u8>),
+    Link(c_uint),
+    Unlink(c_uint),
+    Exit(c_uint, Vec<u8>, bool),
+  }
+
+  fn node() -> CNode {
+    CNode::builder("test", "cookie")
+      .host_name("host")
+      .build()
+      .unwrap()
+  }
+
+  /// Returns a connection from `node` and the socket on its other end.
+  fn connect(node: &CNode) -> (Connection, UnixStream) {
+    let (ours, theirs) = UnixStream::pair().unwrap();
+    let connection = unsafe { Connection::from_raw_fd(node.clone(), ours.into_raw_fd()) };
+    (connection, theirs)
+  }
+
+  fn pid(node: &str, num: c_uint) -> crate::erlang_pid {
+    let mut pid: crate::erlang_pid = unsafe { mem::zeroed() };
+    c_str::copy(node, &mut pid.node).unwrap();
+    pid.num = num;
+    pid
+  }
+
+  fn recorder() -> (Arc<Mutex<Vec<Got>>>, impl Mailbox + Send + 'static) {
+    let got = Arc::new(Mutex::new(Vec::new()));
+    let sink = got.clone();
+    let mailbox = move |_: &mut Connection, signal: Signal| {
+      sink.lock().unwrap().push(match signal {
+        Signal::Message { payload, .. } => Got::Message(payload.as_bytes().to_vec()),
+        Signal::Link { from } => Got::Link(from.num),
+        Signal::Unlink { from } => Got::Unlink(from.num),
+        Signal::Exit {
+          from,
+          reason,
+          explicit,
+        } => Got::Exit(from.num, reason.as_bytes().to_vec(), explicit),
+      })
+    };
+    (got, mailbox)
+  }
+
+  fn received(
+    msgtype: u8,
+    from: &crate::erlang_pid,
+    to: &crate::erlang_pid,
+    toname: &str,
+    payload: &[u8],
+  ) -> Received {
+    let mut msg: crate::erlang_msg = unsafe { mem::zeroed() };
+    msg.msgtype = c_long::from(msgtype);
+    msg.from = from.clone();
+    msg.to = to.clone();
+    c_str::copy(toname, &mut msg.toname).unwrap();
+    let mut buf = XBuff::new().unwrap();
+    buf.extend_from_slice(payload).unwrap();
+    Received::Message { msg, payload: buf }
+  }
+
+  /// Returns the type of the message handed back by `dispatch`, if any.
+  fn returned(result: Result<Option<Received>, Error>) -> Option<c_long> {
+    match result.unwrap() {
+      Some(Received::Message { msg, .. }) => Some(msg.msgtype),
+      Some(Received::Tick) => panic!("a tick was returned"),
+      None => None,
+    }
+  }
+
+  #[test]
+  fn routes_messages_by_pid() {
+    let node = node();
+    let (mut connection, _peer) = connect(&node);
+    let mut dispatcher = Dispatcher::new(&node);
+    let (a_got, a) = recorder();
+    let (b_got, b) = recorder();
+    let a = dispatcher.spawn(a).unwrap();
+    let b = dispatcher.spawn(b).unwrap();
+    assert_ne!(a.num, b.num);
+    let from = pid(PEER, 1);
+
+    let msg = received(crate::ERL_SEND, &from, &b, "", b"\x83a\x01");
+    assert_eq!(returned(dispatcher.dispatch(&mut connection, msg)), None);
+    let msg = received(crate::ERL_SEND, &from, &a, "", b"\x83a\x02");
+    assert_eq!(returned(dispatcher.dispatch(&mut connection, msg)), None);
+    assert_eq!(*a_got.lock().unwrap(), [Got::Message(vec![131, b'a', 2])]);
+    assert_eq!(*b_got.lock().unwrap(), [Got::Message(vec![131, b'a', 1])]);
+  }
+
+  #[test]
+  fn routes_messages_by_registered_name() {
+    let node = node();
+    let (mut connection, _peer) = connect(&node);
+    let mut dispatcher = Dispatcher::new(&node);
+    let (got, mailbox) = recorder();
+    let server = dispatcher.spawn(mailbox).unwrap();
+    dispatcher.register("server", &server).unwrap();
+    assert_eq!(
+      dispatcher.whereis("server").map(|pid| pid.num),
+      Some(server.num)
+    );
+    let from = pid(PEER, 1);
+    let nobody = pid("", 0);
+
+    let msg = received(crate::ERL_REG_SEND, &from, &nobody, "server", b"\x83a\x01");
+    assert_eq!(returned(dispatcher.dispatch(&mut connection, msg)), None);
+    assert_eq!(*got.lock().unwrap(), [Got::Message(vec![131, b'a', 1])]);
+
+    let msg = received(crate::ERL_REG_SEND, &from, &nobody, "other", b"\x83a\x01");
+    assert_eq!(
+      returned(dispatcher.dispatch(&mut connection, msg)),
+      Some(c_long::from(crate::ERL_REG_SEND))
+    );
+    dispatcher.unregister("server");
+    assert!(dispatcher.whereis("server").is_none());
+    let msg = received(crate::ERL_REG_SEND, &from, &nobody, "server", b"\x83a\x01");
+    assert!(returned(dispatcher.dispatch(&mut connection, msg)).is_some());
+    assert_eq!(got.lock().unwrap().len(), 1);
+  }
+
+  #[test]
+  fn returns_what_no_mailbox_takes() {
+    let node = node();
+    let (mut connection, _peer) = connect(&node);
+    let mut dispatcher = Dispatcher::new(&node);
+    let (got, mailbox) = recorder();
+    let removed = dispatcher.spawn(mailbox).unwrap();
+    assert!(dispatcher.remove(&removed).is_some());
+    assert!(!dispatcher.contains(&removed));
+    let from = pid(PEER, 1);
+
+    assert!(dispatcher
+      .dispatch(&mut connection, Received::Tick)
+      .unwrap()
+      .is_none());
+    for to in [node.pid(), &removed] {
+      let msg = received(crate::ERL_SEND, &from, to, "", b"\x83a\x01");
+      assert_eq!(
+        returned(dispatcher.dispatch(&mut connection, msg)),
+        Some(c_long::from(crate::ERL_SEND))
+      );
+    }
+    assert!(got.lock().unwrap().is_empty());
+    assert_eq!(
+      dispatcher.register("server", &removed),
+      Err(Error::InvalidArgument)
+    );
+  }
+
+  /// Spawns a mailbox that ignores its signals.
+  fn spawn(dispatcher: &mut Dispatcher) -> Result<crate::erlang_pid, Error> {
+    dispatcher.spawn(|_: &mut Connection, _: Signal| {})
+  }
+
+  #[test]
+  fn spawn_skips_the_pid_of_the_node() {
+    let mut node = node();
+    node.pid_mut().num = 1;
+    let serial = node.pid().serial;
+    let mut dispatcher = Dispatcher::new(&node);
+    let first = spawn(&mut dispatcher).unwrap();
+    assert_eq!((first.num, first.serial), (2, serial));
+
+    // Numbers wrap around to 15 bits, still skipping the node and the mailboxes in use, and the
+    // serial is bumped.
+    dispatcher.next = 0x7ffe;
+    let last = spawn(&mut dispatcher).unwrap();
+    assert_eq!((last.num, last.serial), (0x7fff, serial));
+    let wrapped = spawn(&mut dispatcher).unwrap();
+    assert_eq!((wrapped.num, wrapped.serial), (0, serial + 1));
+    let next = spawn(&mut dispatcher).unwrap();
+    assert_eq!((next.num, next.serial), (3, serial + 1));
+  }
+
+  #[test]
+  fn spawn_fails_when_all_numbers_are_taken() {
+    let mut dispatcher = Dispatcher::new(&node());
+    let pids: Vec<_> = (0..MAX_NUM)
+      .map(|_| spawn(&mut dispatcher).unwrap())
+      .collect();
+    assert!(matches!(spawn(&mut dispatcher), Err(Error::Alloc)));
+
+    // The number of a removed mailbox is reused, with another serial.
+    let removed = &pids[10];
+    assert!(dispatcher.remove(removed).is_some());
+    let pid = spawn(&mut dispatcher).unwrap();
+    assert_eq!(pid.num, removed.num);
+    assert_ne!(pid.serial, removed.serial);
+    assert!(matches!(spawn(&mut dispatcher), Err(Error::Alloc)));
+  }
+
+  /// Reads the next control message sent to `peer`, which has no payload.
+  fn read_control(peer: &mut UnixStream) -> Vec<u8> {
+    let mut len = [0; 4];
+    peer.read_exact(&mut len).unwrap();
+    let mut frame = vec![0; u32::from_be_bytes(len) as usize];
+    peer.read_exact(&mut frame).unwrap();
+    assert_eq!(frame[0], crate::ERL_PASS_THROUGH);
+    frame.split_off(1)
+  }
+
+  fn control(msgtype: u8, from: &crate::erlang_pid, to: &crate::erlang_pid) -> Vec<u8> {
+    let mut control = XBuff::with_version().unwrap();
+    control
+      .encoder()
+      .tuple(3, |e| {
+        e.long(c_long::from(msgtype))?.pid(from)?.pid(to)?;
+        Ok(())
+      })
+      .unwrap();
+    control.as_bytes().to_vec()
+  }
+
+  fn link_nums(dispatcher: &Dispatcher, pid: &crate::erlang_pid) -> Vec<c_uint> {
+    dispatcher.links(pid).iter().map(|pid| pid.num).collect()
+  }
+
+  #[test]
+  fn tracks_the_links_of_the_other_node() {
+    let node = node();
+    let (mut connection, _peer) = connect(&node);
+    let mut dispatcher = Dispatcher::new(&node);
+    let (got, mailbox) = recorder();
+    let a = dispatcher.spawn(mailbox).unwrap();
+    let from = pid(PEER, 1);
+    let mut dispatch = |msgtype, payload: &[u8]| {
+      let msg = received(msgtype, &from, &a, "", payload);
+      assert_eq!(returned(dispatcher.dispatch(&mut connection, msg)), None);
+      link_nums(&dispatcher, &a)
+    };
+
+    assert_eq!(dispatch(crate::ERL_LINK, b""), [1]);
+    assert_eq!(dispatch(crate::ERL_LINK, b""), [1]);
+    assert_eq!(dispatch(crate::ERL_UNLINK, b""), []);
+    assert_eq!(dispatch(crate::ERL_LINK, b""), [1]);
+    // An explicit exit signal does not break the link, unlike the exit of a linked process.
+    assert_eq!(dispatch(crate::ERL_EXIT2, b"\x83a\x02"), [1]);
+    assert_eq!(dispatch(crate::ERL_EXIT, b"\x83a\x03"), []);
+    assert_eq!(
+      *got.lock().unwrap(),
+      [
+        Got::Link(1),
+        Got::Link(1),
+        Got::Unlink(1),
+        Got::Link(1),
+        Got::Exit(1, vec![131, b'a', 2], true),
+        Got::Exit(1, vec![131, b'a', 3], false),
+      ]
+    );
+  }
+
+  #[test]
+  fn link_and_unlink_send_control_messages() {
+    let node = node();
+    let (mut connection, mut peer) = connect(&node);
+    let mut dispatcher = Dispatcher::new(&node);
+    let a = dispatcher
+      .spawn(|_: &mut Connection, _: Signal| {})
+      .unwrap();
+    let to = pid(PEER, 1);
+
+    dispatcher.link(&mut connection, &a, &to).unwrap();
+    assert_eq!(read_control(&mut peer), control(crate::ERL_LINK, &a, &to));
+    assert_eq!(link_nums(&dispatcher, &a), [1]);
+    dispatcher.unlink(&mut connection, &a, &to).unwrap();
+    assert_eq!(read_control(&mut peer), control(crate::ERL_UNLINK, &a, &to));
+    assert!(dispatcher.links(&a).is_empty());
+
+    let removed = pid("", 1000);
+    assert_eq!(
+      dispatcher.unlink(&mut connection, &removed, &to),
+      Err(Error::InvalidArgument)
+    );
+  }
+
+  #[test]
+  fn disconnected_breaks_the_links_to_the_peer_only() {
+    let node = node();
+    let (mut connection, _peer) = connect(&node);
+    let mut peer_name = [0; crate::MAXNODELEN + 1];
+    c_str::copy(PEER, &mut peer_name).unwrap();
+    connection.set_peer(&peer_name);
+    let mut dispatcher = Dispatcher::new(&node);
+    let (got, mailbox) = recorder();
+    let a = dispatcher.spawn(mailbox).unwrap();
+    for from in [pid(PEER, 1), pid("other@host", 2)] {
+      let msg = received(crate::ERL_LINK, &from, &a, "", b"");
+      dispatcher.dispatch(&mut connection, msg).unwrap();
+    }
+    got.lock().unwrap().clear();
+
+    dispatcher.disconnected(&mut connection).unwrap();
+    assert_eq!(link_nums(&dispatcher, &a), [2]);
+    let mut noconnection = XBuff::with_version().unwrap();
+    noconnection.encoder().atom("noconnection").unwrap();
+    assert_eq!(
+      *got.lock().unwrap(),
+      [Got::Exit(1, noconnection.as_bytes().to_vec(), false)]
+    );
+
+    // Without the name of the other node, every link is broken.
+    let (mut anonymous, _peer) = connect(&node);
+    dispatcher.disconnected(&mut anonymous).unwrap();
+    assert!(dispatcher.links(&a).is_empty());
+    assert_eq!(got.lock().unwrap().len(), 2);
+  }
+}
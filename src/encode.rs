@@ -0,0 +1,320 @@
+//! A safe encoder of terms over the `ei_x_encode_*` functions.
+
+use crate::{
+  error::{check, Error},
+  XBuff,
+};
+use core::convert::TryFrom;
+use libc::{c_char, c_int, c_long, c_void};
+
+/// Encodes terms at the end of an [`XBuff`].
+///
+/// Each method encodes exactly one term and returns the encoder, so calls can be chained with
+/// `?`. Compound terms are encoded by passing a closure that encodes their elements. In debug
+/// builds, the encoder checks that the closure encoded as many terms as the arity it declared and
+/// panics otherwise.
+///
+/// When a term fails to encode, the encoder can still be used, but a compound term that failed
+/// partway leaves its header and the elements encoded so far in the buffer, which then no longer
+/// holds valid terms. Such a buffer should be cleared or discarded.
+///
+/// [`XBuff`]: struct.XBuff.html
+pub struct Encoder<'a> {
+  buf: &'a mut XBuff,
+  #[cfg(debug_assertions)]
+  count: usize,
+}
+
+impl<'a> Encoder<'a> {
+  /// Creates an encoder that appends terms to `buf`.
+  #[inline]
+  pub fn new(buf: &'a mut XBuff) -> Self {
+    Encoder {
+      buf,
+      #[cfg(debug_assertions)]
+      count: 0,
+    }
+  }
+
+  #[inline]
+  fn encoded(&mut self, code: c_int) -> Result<&mut Self, Error> {
+    check(code, Error::Encode)?;
+    #[cfg(debug_assertions)]
+    {
+      self.count += 1;
+    }
+    Ok(self)
+  }
+
+  /// Appends a term that was already encoded, in parts.
+  pub(crate) fn raw(&mut self, parts: &[&[u8]]) -> Result<&mut Self, Error> {
+    for part in parts {
+      self.buf.extend_from_slice(part)?;
+    }
+    #[cfg(debug_assertions)]
+    {
+      self.count += 1;
+    }
+    Ok(self)
+  }
+
+  /// Appends the already encoded `header` of a compound term, followed by its elements, encoded by
+  /// `f`.
+  #[cfg(feature = "alloc")]
+  pub(crate) fn compound<F>(
+    &mut self,
+    header: &[u8],
+    arity: usize,
+    f: F,
+  ) -> Result<&mut Self, Error>
+  where
+    F: FnOnce(&mut Self) -> Result<(), Error>,
+  {
+    self.buf.extend_from_slice(header)?;
+    self.elements(arity, f)
+  }
+
+  /// Runs `f`, which must encode exactly `arity` terms, as the elements of a compound term.
+  fn elements<F>(&mut self, arity: usize, f: F) -> Result<&mut Self, Error>
+  where
+    F: FnOnce(&mut Self) -> Result<(), Error>,
+  {
+    #[cfg(debug_assertions)]
+    let outer = core::mem::replace(&mut self.count, 0);
+    let result = f(self);
+    #[cfg(debug_assertions)]
+    {
+      // A failed term is not counted, like the terms that fail in `encoded`.
+      let count = core::mem::replace(&mut self.count, outer);
+      if result.is_ok() {
+        debug_assert_eq!(
+          count, arity,
+          "a compound term encoded a different number of elements than its arity"
+        );
+        self.count += 1;
+      }
+    }
+    #[cfg(not(debug_assertions))]
+    let _ = arity;
+    result?;
+    Ok(self)
+  }
+
+  /// Encodes an atom from its UTF-8 name.
+  pub fn atom(&mut self, name: &str) -> Result<&mut Self, Error> {
+    let len = c_int::try_from(name.len()).map_err(|_| Error::Encode)?;
+    let code = unsafe {
+      crate::ei_x_encode_atom_len_as(
+        self.buf.as_mut_ptr(),
+        name.as_ptr() as *const c_char,
+        len,
+        crate::ERLANG_UTF8,
+        crate::ERLANG_UTF8,
+      )
+    };
+    self.encoded(code)
+  }
+
+  /// Encodes `true` or `false`.
+  pub fn boolean(&mut self, value: bool) -> Result<&mut Self, Error> {
+    let code = unsafe { crate::ei_x_encode_boolean(self.buf.as_mut_ptr(), value as c_int) };
+    self.encoded(code)
+  }
+
+  /// Encodes an integer.
+  pub fn long(&mut self, value: c_long) -> Result<&mut Self, Error> {
+    let code = unsafe { crate::ei_x_encode_long(self.buf.as_mut_ptr(), value) };
+    self.encoded(code)
+  }
+
+  /// Encodes a non-negative integer.
+  pub fn ulong(&mut self, value: libc::c_ulong) -> Result<&mut Self, Error> {
+    let code = unsafe { crate::ei_x_encode_ulong(self.buf.as_mut_ptr(), value) };
+    self.encoded(code)
+  }
+
+  /// Encodes a 64 bits integer.
+  pub fn longlong(&mut self, value: libc::c_longlong) -> Result<&mut Self, Error> {
+    let code = unsafe { crate::ei_x_encode_longlong(self.buf.as_mut_ptr(), value) };
+    self.encoded(code)
+  }
+
+  /// Encodes a non-negative 64 bits integer.
+  pub fn ulonglong(&mut self, value: libc::c_ulonglong) -> Result<&mut Self, Error> {
+    let code = unsafe { crate::ei_x_encode_ulonglong(self.buf.as_mut_ptr(), value) };
+    self.encoded(code)
+  }
+
+  /// Encodes a float.
+  ///
+  /// Erlang has no representation for infinities and NaN, so encoding them fails.
+  pub fn double(&mut self, value: f64) -> Result<&mut Self, Error> {
+    let code = unsafe { crate::ei_x_encode_double(self.buf.as_mut_ptr(), value) };
+    self.encoded(code)
+  }
+
+  /// Encodes a binary.
+  pub fn binary(&mut self, bytes: &[u8]) -> Result<&mut Self, Error> {
+    let len = c_int::try_from(bytes.len()).map_err(|_| Error::Encode)?;
+    let code = unsafe {
+      crate::ei_x_encode_binary(self.buf.as_mut_ptr(), bytes.as_ptr() as *const c_void, len)
+    };
+    self.encoded(code)
+  }
+
+  /// Encodes a string, that is a list of bytes.
+  ///
+  /// The bytes of `value` are encoded as is. Erlang will not interpret them as UTF-8.
+  pub fn string<S>(&mut self, value: &S) -> Result<&mut Self, Error>
+  where
+    S: AsRef<[u8]> + ?Sized,
+  {
+    let value = value.as_ref();
+    let len = c_int::try_from(value.len()).map_err(|_| Error::Encode)?;
+    let code = unsafe {
+      crate::ei_x_encode_string_len(self.buf.as_mut_ptr(), value.as_ptr() as *const c_char, len)
+    };
+    self.encoded(code)
+  }
+
+  /// Encodes a process identifier.
+  pub fn pid(&mut self, pid: &crate::erlang_pid) -> Result<&mut Self, Error> {
+    let code = unsafe { crate::ei_x_encode_pid(self.buf.as_mut_ptr(), pid) };
+    self.encoded(code)
+  }
+
+  /// Encodes a port identifier.
+  pub fn port(&mut self, port: &crate::erlang_port) -> Result<&mut Self, Error> {
+    let code = unsafe { crate::ei_x_encode_port(self.buf.as_mut_ptr(), port) };
+    self.encoded(code)
+  }
+
+  /// Encodes a reference.
+  pub fn reference(&mut self, reference: &crate::erlang_ref) -> Result<&mut Self, Error> {
+    let code = unsafe { crate::ei_x_encode_ref(self.buf.as_mut_ptr(), reference) };
+    self.encoded(code)
+  }
+
+  /// Encodes the empty list.
+  pub fn empty_list(&mut self) -> Result<&mut Self, Error> {
+    let code = unsafe { crate::ei_x_encode_empty_list(self.buf.as_mut_ptr()) };
+    self.encoded(code)
+  }
+
+  /// Encodes a tuple of `arity` elements, which are encoded by `f`.
+  pub fn tuple<F>(&mut self, arity: usize, f: F) -> Result<&mut Self, Error>
+  where
+    F: FnOnce(&mut Self) -> Result<(), Error>,
+  {
+    let n = c_long::try_from(arity).map_err(|_| Error::Encode)?;
+    check(
+      unsafe { crate::ei_x_encode_tuple_header(self.buf.as_mut_ptr(), n) },
+      Error::Encode,
+    )?;
+    self.elements(arity, f)
+  }
+
+  /// Encodes a proper list of the `items`, each of them encoded by `f`.
+  pub fn list<I, F>(&mut self, items: I, mut f: F) -> Result<&mut Self, Error>
+  where
+    I: IntoIterator,
+    I::IntoIter: ExactSizeIterator,
+    F: FnMut(&mut Self, I::Item) -> Result<(), Error>,
+  {
+    let items = items.into_iter();
+    let arity = items.len();
+    if arity == 0 {
+      return self.empty_list();
+    }
+
+    let n = c_long::try_from(arity).map_err(|_| Error::Encode)?;
+    check(
+      unsafe { crate::ei_x_encode_list_header(self.buf.as_mut_ptr(), n) },
+      Error::Encode,
+    )?;
+    self.elements(arity, |e| {
+      for item in items {
+        f(e, item)?;
+      }
+      Ok(())
+    })?;
+    check(
+      unsafe { crate::ei_x_encode_empty_list(self.buf.as_mut_ptr()) },
+      Error::Encode,
+    )?;
+    Ok(self)
+  }
+
+  /// Encodes an improper list of the `items`, each of them encoded by `f`, and of the tail encoded
+  /// by `tail`.
+  ///
+  /// An improper list must have at least one item.
+  pub fn improper_list<I, F, T>(&mut self, items: I, mut f: F, tail: T) -> Result<&mut Self, Error>
+  where
+    I: IntoIterator,
+    I::IntoIter: ExactSizeIterator,
+    F: FnMut(&mut Self, I::Item) -> Result<(), Error>,
+    T: FnOnce(&mut Self) -> Result<(), Error>,
+  {
+    let items = items.into_iter();
+    let arity = items.len();
+    if arity == 0 {
+      return Err(Error::Encode);
+    }
+
+    let n = c_long::try_from(arity).map_err(|_| Error::Encode)?;
+    check(
+      unsafe { crate::ei_x_encode_list_header(self.buf.as_mut_ptr(), n) },
+      Error::Encode,
+    )?;
+    self.elements(arity + 1, |e| {
+      for item in items {
+        f(e, item)?;
+      }
+      tail(e)
+    })
+  }
+
+  /// Encodes a map of the `entries`, each of them encoded by `f` as a key followed by a value.
+  pub fn map<I, F>(&mut self, entries: I, mut f: F) -> Result<&mut Self, Error>
+  where
+    I: IntoIterator,
+    I::IntoIter: ExactSizeIterator,
+    F: FnMut(&mut Self, I::Item) -> Result<(), Error>,
+  {
+    let entries = entries.into_iter();
+    let arity = entries.len();
+    let n = c_long::try_from(arity).map_err(|_| Error::Encode)?;
+    check(
+      unsafe { crate::ei_x_encode_map_header(self.buf.as_mut_ptr(), n) },
+      Error::Encode,
+    )?;
+    self.elements(2 * arity, |e| {
+      for entry in entries {
+        f(e, entry)?;
+      }
+      Ok(())
+    })
+  }
+
+  /// Encodes a map of `arity` entries, whose keys and values are encoded by `f`, alternating.
+  pub fn map_with<F>(&mut self, arity: usize, f: F) -> Result<&mut Self, Error>
+  where
+    F: FnOnce(&mut Self) -> Result<(), Error>,
+  {
+    let n = c_long::try_from(arity).map_err(|_| Error::Encode)?;
+    check(
+      unsafe { crate::ei_x_encode_map_header(self.buf.as_mut_ptr(), n) },
+      Error::Encode,
+    )?;
+    self.elements(2 * arity, f)
+  }
+}
+
+impl XBuff {
+  /// Returns an encoder that appends terms to this buffer.
+  #[inline]
+  pub fn encoder(&mut self) -> Encoder<'_> {
+    Encoder::new(self)
+  }
+}
@@ -0,0 +1,463 @@
+//! A client of EPMD, the Erlang Port Mapper Daemon, which maps the names of the nodes of a host
+//! to the ports they listen on.
+
+use crate::error::{io_error, Error};
+use core::{
+  convert::TryFrom,
+  net::{Ipv4Addr, SocketAddr},
+  time::Duration,
+};
+use std::{
+  env,
+  io::{self, Read, Write},
+  net::{TcpStream, ToSocketAddrs},
+  string::String,
+  vec::Vec,
+};
+
+/// The port EPMD listens on, unless the `ERL_EPMD_PORT` environment variable says otherwise.
+pub const EPMD_PORT: u16 = 4369;
+
+pub(crate) const ALIVE2_X_RESP: u8 = 118;
+pub(crate) const PORT2_RESP: u8 = 119;
+pub(crate) const ALIVE2_REQ: u8 = 120;
+pub(crate) const ALIVE2_RESP: u8 = 121;
+pub(crate) const PORT_PLEASE2_REQ: u8 = 122;
+pub(crate) const NAMES_REQ: u8 = 110;
+pub(crate) const DUMP_REQ: u8 = 100;
+
+/// A client of an EPMD instance.
+///
+/// Each request is made on its own connection, as EPMD closes connections after replying, except
+/// for registrations.
+///
+/// # See Also
+///
+/// [The official description of the protocol in the Erlang documentation.](http://erlang.org/doc/apps/erts/erl_dist_protocol.html#epmd-protocol)
+#[derive(Debug, Clone)]
+pub struct Epmd {
+  addr: SocketAddr,
+  timeout: Option<Duration>,
+}
+
+/// Whether a node is visible to the nodes it connects to.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum NodeType {
+  /// An Erlang node, which joins the network of the nodes it connects to.
+  Normal,
+  /// A hidden node, such as a C node, which only connects to the nodes it talks to.
+  Hidden,
+  /// Another type, unknown to this crate.
+  Other(u8),
+}
+
+/// A node registered with EPMD.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NodeInfo {
+  /// The alive name of the node, the part of its name before the `@`.
+  pub name: String,
+  /// The port the node listens on.
+  pub port: u16,
+  /// Whether the node is hidden.
+  pub node_type: NodeType,
+  /// The transport of the node, `0` for TCP over IPv4.
+  pub protocol: u8,
+  /// The highest version of the distribution protocol the node supports.
+  pub highest_version: u16,
+  /// The lowest version of the distribution protocol the node supports.
+  pub lowest_version: u16,
+  /// Extra data, empty for most nodes.
+  pub extra: Vec<u8>,
+}
+
+/// A registration with EPMD, which lasts until it is dropped.
+#[derive(Debug)]
+pub struct Registration {
+  stream: TcpStream,
+  creation: u32,
+}
+
+/// An entry of the debugging dump of EPMD.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DumpEntry {
+  /// The alive name of the node.
+  pub name: String,
+  /// The port the node listens on.
+  pub port: u16,
+  /// The file descriptor of the registration in EPMD.
+  pub fd: i32,
+  /// `false` if the registration was closed and EPMD only remembers the name.
+  pub active: bool,
+}
+
+impl Epmd {
+  /// Creates a client of the EPMD instance at `addr`.
+  pub fn new(addr: SocketAddr) -> Self {
+    Self {
+      addr,
+      timeout: None,
+    }
+  }
+
+  /// Creates a client of the EPMD instance of the local host, on the port given by the
+  /// `ERL_EPMD_PORT` environment variable or on [`EPMD_PORT`], like ei.
+  ///
+  /// [`EPMD_PORT`]: constant.EPMD_PORT.html
+  pub fn local() -> Self {
+    Self::new(SocketAddr::from((Ipv4Addr::LOCALHOST, port())))
+  }
+
+  /// Creates a client of the EPMD instance of `host`, on the same port as [`local`].
+  ///
+  /// [`local`]: #method.local
+  pub fn host(host: &str) -> Result<Self, Error> {
+    let addr = (host, port())
+      .to_socket_addrs()
+      .map_err(io_error)?
+      .find(SocketAddr::is_ipv4)
+      .ok_or(Error::InvalidArgument)?;
+    Ok(Self::new(addr))
+  }
+
+  /// Returns the address of the EPMD instance.
+  #[inline]
+  pub fn addr(&self) -> SocketAddr {
+    self.addr
+  }
+
+  /// Sets how long connecting and each read and write may take, or removes the limit with `None`,
+  /// the default.
+  pub fn set_timeout(&mut self, timeout: Option<Duration>) {
+    self.timeout = timeout.filter(|timeout| !timeout.is_zero());
+  }
+
+  /// Looks up the node with the alive name `name`, or returns `None` if it is not registered.
+  ///
+  /// This is `PORT_PLEASE2_REQ`, which ei uses to find the port of the nodes it connects to.
+  pub fn port_please(&self, name: &str) -> Result<Option<NodeInfo>, Error> {
+    let mut request = Vec::with_capacity(name.len() + 1);
+    request.push(PORT_PLEASE2_REQ);
+    request.extend_from_slice(name.as_bytes());
+    let mut stream = self.request(&request)?;
+
+    let mut header = [0; 2];
+    read_exact(&mut stream, &mut header)?;
+    match header {
+      [PORT2_RESP, 0] => {}
+      [PORT2_RESP, _] => return Ok(None),
+      _ => return Err(Error::Decode),
+    }
+    let mut fixed = [0; 8];
+    read_exact(&mut stream, &mut fixed)?;
+    let name = read_string(&mut stream)?;
+    let extra = read_bytes(&mut stream)?;
+    Ok(Some(NodeInfo {
+      name,
+      port: u16::from_be_bytes([fixed[0], fixed[1]]),
+      node_type: NodeType::from(fixed[2]),
+      protocol: fixed[3],
+      highest_version: u16::from_be_bytes([fixed[4], fixed[5]]),
+      lowest_version: u16::from_be_bytes([fixed[6], fixed[7]]),
+      extra,
+    }))
+  }
+
+  /// Returns the alive names and ports of the registered nodes, like `epmd -names`.
+  ///
+  /// This is `NAMES_REQ`.
+  pub fn names(&self) -> Result<Vec<(String, u16)>, Error> {
+    let text = self.text(NAMES_REQ)?;
+    text
+      .lines()
+      .filter(|line| !line.is_empty())
+      .map(parse_names_entry)
+      .collect()
+  }
+
+  /// Returns the registrations EPMD knows of, active or not, like `epmd -dump`.
+  ///
+  /// This is `DUMP_REQ`, which is meant for debugging, so the format of its reply is not
+  /// specified. Entries that cannot be parsed are skipped.
+  pub fn dump(&self) -> Result<Vec<DumpEntry>, Error> {
+    let text = self.text(DUMP_REQ)?;
+    Ok(
+      text
+        .split(['\n', '\0'])
+        .filter_map(parse_dump_entry)
+        .collect(),
+    )
+  }
+
+  /// Registers `node` and returns the registration, which lasts until it is dropped.
+  ///
+  /// This is `ALIVE2_REQ`, which ei makes in [`ei_publish`]. Fails with
+  /// [`Error::InvalidArgument`] if the name is empty or too long, or if EPMD refuses it, such as
+  /// when it is already registered.
+  ///
+  /// [`ei_publish`]: fn.ei_publish.html
+  /// [`Error::InvalidArgument`]: enum.Error.html#variant.InvalidArgument
+  pub fn register(&self, node: &NodeInfo) -> Result<Registration, Error> {
+    let name_len = u16::try_from(node.name.len()).map_err(|_| Error::InvalidArgument)?;
+    let extra_len = u16::try_from(node.extra.len()).map_err(|_| Error::InvalidArgument)?;
+    if node.name.is_empty() {
+      return Err(Error::InvalidArgument);
+    }
+    let mut request = Vec::with_capacity(13 + node.name.len() + node.extra.len());
+    request.push(ALIVE2_REQ);
+    request.extend_from_slice(&node.port.to_be_bytes());
+    request.push(node.node_type.into());
+    request.push(node.protocol);
+    request.extend_from_slice(&node.highest_version.to_be_bytes());
+    request.extend_from_slice(&node.lowest_version.to_be_bytes());
+    request.extend_from_slice(&name_len.to_be_bytes());
+    request.extend_from_slice(node.name.as_bytes());
+    request.extend_from_slice(&extra_len.to_be_bytes());
+    request.extend_from_slice(&node.extra);
+    let mut stream = self.request(&request)?;
+
+    let mut header = [0; 2];
+    read_exact(&mut stream, &mut header)?;
+    let creation = match header {
+      [ALIVE2_RESP, 0] => {
+        let mut creation = [0; 2];
+        read_exact(&mut stream, &mut creation)?;
+        u32::from(u16::from_be_bytes(creation))
+      }
+      [ALIVE2_X_RESP, 0] => {
+        let mut creation = [0; 4];
+        read_exact(&mut stream, &mut creation)?;
+        u32::from_be_bytes(creation)
+      }
+      [ALIVE2_RESP, _] | [ALIVE2_X_RESP, _] => return Err(Error::InvalidArgument),
+      _ => return Err(Error::Decode),
+    };
+    Ok(Registration { stream, creation })
+  }
+
+  /// Opens a connection and sends `request`, framed by its length.
+  fn request(&self, request: &[u8]) -> Result<TcpStream, Error> {
+    let len = u16::try_from(request.len()).map_err(|_| Error::InvalidArgument)?;
+    let mut stream = match self.timeout {
+      Some(timeout) => TcpStream::connect_timeout(&self.addr, timeout),
+      None => TcpStream::connect(self.addr),
+    }
+    .map_err(epmd_error)?;
+    stream.set_read_timeout(self.timeout).map_err(io_error)?;
+    stream.set_write_timeout(self.timeout).map_err(io_error)?;
+
+    let mut frame = Vec::with_capacity(2 + request.len());
+    frame.extend_from_slice(&len.to_be_bytes());
+    frame.extend_from_slice(request);
+    stream.write_all(&frame).map_err(epmd_error)?;
+    Ok(stream)
+  }
+
+  /// Makes a request whose reply is the port of EPMD followed by text until the connection is
+  /// closed.
+  fn text(&self, request: u8) -> Result<String, Error> {
+    let mut stream = self.request(&[request])?;
+    let mut port = [0; 4];
+    read_exact(&mut stream, &mut port)?;
+    let mut text = Vec::new();
+    stream.read_to_end(&mut text).map_err(epmd_error)?;
+    String::from_utf8(text).map_err(|_| Error::Decode)
+  }
+}
+
+impl NodeInfo {
+  /// Describes a hidden node listening on `port` over TCP and IPv4, such as a C node, to be
+  /// registered.
+  pub fn new(name: &str, port: u16) -> Self {
+    Self {
+      name: name.into(),
+      port,
+      node_type: NodeType::Hidden,
+      protocol: 0,
+      highest_version: 6,
+      lowest_version: 5,
+      extra: Vec::new(),
+    }
+  }
+}
+
+impl Registration {
+  /// Returns the creation EPMD assigned to the node, which tells its incarnations apart.
+  #[inline]
+  pub fn creation(&self) -> u32 {
+    self.creation
+  }
+}
+
+#[cfg(unix)]
+impl std::os::unix::io::AsRawFd for Registration {
+  #[inline]
+  fn as_raw_fd(&self) -> libc::c_int {
+    self.stream.as_raw_fd()
+  }
+}
+
+impl From<u8> for NodeType {
+  fn from(byte: u8) -> Self {
+    match byte {
+      77 => NodeType::Normal,
+      72 => NodeType::Hidden,
+      byte => NodeType::Other(byte),
+    }
+  }
+}
+
+impl From<NodeType> for u8 {
+  fn from(node_type: NodeType) -> Self {
+    match node_type {
+      NodeType::Normal => 77,
+      NodeType::Hidden => 72,
+      NodeType::Other(byte) => byte,
+    }
+  }
+}
+
+/// Returns the port of EPMD, from `ERL_EPMD_PORT` if it is set.
+fn port() -> u16 {
+  env::var("ERL_EPMD_PORT")
+    .ok()
+    .and_then(|port| port.parse().ok())
+    .unwrap_or(EPMD_PORT)
+}
+
+/// Parses a line of the reply to `NAMES_REQ`, such as `name foo at port 36563`.
+fn parse_names_entry(line: &str) -> Result<(String, u16), Error> {
+  let (name, port) = line
+    .strip_prefix("name ")
+    .and_then(|line| line.rsplit_once(" at port "))
+    .ok_or(Error::Decode)?;
+  let port = port.trim().parse().map_err(|_| Error::Decode)?;
+  Ok((String::from(name), port))
+}
+
+/// Parses a line of the reply to `DUMP_REQ`, such as `active name     <foo> at port 36563, fd = 7`,
+/// or `old/unused name, <foo>, at port 36563, fd = 7` for a registration that was closed.
+fn parse_dump_entry(line: &str) -> Option<DumpEntry> {
+  let line = line.trim();
+  let start = line.find('<')?;
+  let end = start + line[start..].find('>')?;
+  let rest = &line[end + 1..];
+  Some(DumpEntry {
+    name: String::from(&line[start + 1..end]),
+    port: number_after(rest, "port")?,
+    fd: number_after(rest, "fd")?,
+    active: line.starts_with("active"),
+  })
+}
+
+/// Parses the number after `key` in `text`, skipping spaces and an `=` between them.
+fn number_after<T: core::str::FromStr>(text: &str, key: &str) -> Option<T> {
+  let rest = &text[text.find(key)? + key.len()..];
+  let rest = rest.trim_start_matches([' ', '=']);
+  let len = rest
+    .find(|c: char| !c.is_ascii_digit() && c != '-')
+    .unwrap_or(rest.len());
+  rest[..len].parse().ok()
+}
+
+fn read_exact(stream: &mut TcpStream, buf: &mut [u8]) -> Result<(), Error> {
+  stream.read_exact(buf).map_err(epmd_error)
+}
+
+/// Reads bytes prefixed by their length on two bytes.
+fn read_bytes(stream: &mut TcpStream) -> Result<Vec<u8>, Error> {
+  let mut len = [0; 2];
+  read_exact(stream, &mut len)?;
+  let mut bytes = std::vec![0; usize::from(u16::from_be_bytes(len))];
+  read_exact(stream, &mut bytes)?;
+  Ok(bytes)
+}
+
+fn read_string(stream: &mut TcpStream) -> Result<String, Error> {
+  String::from_utf8(read_bytes(stream)?).map_err(|_| Error::Decode)
+}
+
+/// Converts an I/O error, telling timeouts and replies cut short apart.
+fn epmd_error(error: io::Error) -> Error {
+  match error.kind() {
+    io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock => Error::Timeout,
+    io::ErrorKind::UnexpectedEof => Error::Decode,
+    _ => io_error(error),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn parses_names() {
+    // As printed by `epmd -names`, after its own first line.
+    assert_eq!(
+      parse_names_entry("name foo at port 36563"),
+      Ok((String::from("foo"), 36563))
+    );
+    assert_eq!(
+      parse_names_entry("name with at port spaces at port 4242"),
+      Ok((String::from("with at port spaces"), 4242))
+    );
+    assert_eq!(parse_names_entry("name foo at port"), Err(Error::Decode));
+    assert_eq!(parse_names_entry("name foo at port x"), Err(Error::Decode));
+    assert_eq!(
+      parse_names_entry("name foo at port 65536"),
+      Err(Error::Decode)
+    );
+    assert_eq!(
+      parse_names_entry("epmd: up and running on port 4369 with data:"),
+      Err(Error::Decode)
+    );
+  }
+
+  #[test]
+  fn parses_dump_entries() {
+    // As printed by `epmd -dump`, after its own first line.
+    assert_eq!(
+      parse_dump_entry("active name     <foo> at port 36563, fd = 7"),
+      Some(DumpEntry {
+        name: String::from("foo"),
+        port: 36563,
+        fd: 7,
+        active: true,
+      })
+    );
+    assert_eq!(
+      parse_dump_entry("old/unused name, <bar>, at port 41017, fd = 8 "),
+      Some(DumpEntry {
+        name: String::from("bar"),
+        port: 41017,
+        fd: 8,
+        active: false,
+      })
+    );
+    assert_eq!(
+      parse_dump_entry("epmd: up and running on port 4369 with data:"),
+      None
+    );
+    assert_eq!(
+      parse_dump_entry("active name     <foo> at port 36563"),
+      None
+    );
+    assert_eq!(
+      parse_dump_entry("active name     <foo at port 36563, fd = 7"),
+      None
+    );
+    assert_eq!(parse_dump_entry(""), None);
+  }
+
+  #[test]
+  fn parses_numbers_after_keys() {
+    assert_eq!(number_after::<i32>("fd = 7", "fd"), Some(7));
+    assert_eq!(number_after::<i32>("fd=-1,", "fd"), Some(-1));
+    assert_eq!(
+      number_after::<u16>("at port 4369, fd = 7", "port"),
+      Some(4369)
+    );
+    assert_eq!(number_after::<u16>("at port , fd = 7", "port"), None);
+    assert_eq!(number_after::<u16>("fd = 7", "port"), None);
+    assert_eq!(number_after::<u16>("port 70000", "port"), None);
+  }
+}
@@ -0,0 +1,106 @@
+//! Errors reported by the safe wrappers of this crate.
+
+use core::fmt;
+
+/// The error type of the safe wrappers of this crate.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum Error {
+  /// ei could not allocate memory, or a [`Dispatcher`] ran out of pids.
+  ///
+  /// [`Dispatcher`]: struct.Dispatcher.html
+  Alloc,
+  /// ei could not encode a term, either because it is invalid or because it could not allocate
+  /// memory.
+  Encode,
+  /// A term could not be decoded, either because the input is malformed or because the term is
+  /// not of the expected type.
+  Decode,
+  /// The buffer given to decode a term into is too small to hold it.
+  BufferTooSmall,
+  /// An argument is malformed or exceeds the limits of ei, such as a node name that is too long
+  /// or contains a null character.
+  InvalidArgument,
+  /// An operation on a connection did not complete in time.
+  Timeout,
+  /// A non-blocking operation on a connection could not complete without blocking.
+  WouldBlock,
+  /// The other node sent nothing on a connection, not even a tick, for longer than its tick time.
+  NetTickTimeout,
+  /// An operation on a connection failed, with the given error number from [`erl_errno`] or the
+  /// operating system.
+  ///
+  /// [`erl_errno`]: fn.__erl_errno_place.html
+  Io(i32),
+}
+
+impl fmt::Display for Error {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      Error::Alloc => f.write_str("could not allocate memory or a pid"),
+      Error::Encode => f.write_str("ei could not encode a term"),
+      Error::Decode => f.write_str("ei could not decode a term"),
+      Error::BufferTooSmall => f.write_str("the buffer is too small to hold the decoded term"),
+      Error::InvalidArgument => f.write_str("an argument is malformed or exceeds the limits of ei"),
+      Error::Timeout => f.write_str("the operation timed out"),
+      Error::WouldBlock => f.write_str("the operation would block"),
+      Error::NetTickTimeout => f.write_str("the other node stopped responding"),
+      Error::Io(errno) => write!(f, "the operation failed with error number {}", errno),
+    }
+  }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for Error {}
+
+/// Converts an I/O error of the standard library, which only carries an error number here.
+#[cfg(feature = "std")]
+pub(crate) fn io_error(error: std::io::Error) -> Error {
+  Error::Io(error.raw_os_error().unwrap_or(0))
+}
+
+/// Locks `mutex`, ignoring poisoning: the data behind the mutexes of this crate stays consistent
+/// when a thread panics while holding them.
+#[cfg(feature = "std")]
+pub(crate) fn lock<T>(mutex: &std::sync::Mutex<T>) -> std::sync::MutexGuard<'_, T> {
+  mutex
+    .lock()
+    .unwrap_or_else(std::sync::PoisonError::into_inner)
+}
+
+/// Converts the return code of most ei functions, `0` for success and `-1` for failure, to a
+/// `Result`.
+#[inline]
+pub(crate) fn check(code: libc::c_int, error: Error) -> Result<(), Error> {
+  if code == 0 {
+    Ok(())
+  } else {
+    Err(error)
+  }
+}
+
+/// Returns `true` if `error` means that the connection it happened on is lost.
+#[cfg(feature = "std")]
+pub(crate) fn is_lost(error: Error) -> bool {
+  matches!(error, Error::Io(_) | Error::NetTickTimeout)
+}
+
+/// Returns the error of the last failed operation on a connection, from [`erl_errno`].
+///
+/// [`erl_errno`]: fn.__erl_errno_place.html
+pub(crate) fn last_error() -> Error {
+  match unsafe { *crate::__erl_errno_place() } {
+    libc::ETIMEDOUT => Error::Timeout,
+    errno => Error::Io(errno),
+  }
+}
+
+/// Returns the error of the last failed system call, from `errno`.
+///
+/// Without the `std` feature there is no portable way to read `errno`, so the error is `EIO`.
+pub(crate) fn os_error() -> Error {
+  #[cfg(feature = "std")]
+  return Error::Io(std::io::Error::last_os_error().raw_os_error().unwrap_or(0));
+  #[cfg(not(feature = "std"))]
+  return Error::Io(libc::EIO);
+}
@@ -0,0 +1,219 @@
+//! The call and cast protocol of `gen_server`, in both directions.
+
+use crate::{
+  c_str, connection::Deadline, Connection, Decoder, Error, FromTerm, Received, ToTerm, XBuff,
+  MAXATOMLEN_UTF8,
+};
+use core::time::Duration;
+use libc::c_long;
+
+/// A `gen_server` process on another node, either registered under a name or known by its pid.
+#[derive(Clone, Copy)]
+pub enum Server<'a> {
+  /// A process registered under a name.
+  Name(&'a str),
+  /// A process known by its pid.
+  Pid(&'a crate::erlang_pid),
+}
+
+impl<'a> From<&'a str> for Server<'a> {
+  #[inline]
+  fn from(name: &'a str) -> Self {
+    Server::Name(name)
+  }
+}
+
+impl<'a> From<&'a crate::erlang_pid> for Server<'a> {
+  #[inline]
+  fn from(pid: &'a crate::erlang_pid) -> Self {
+    Server::Pid(pid)
+  }
+}
+
+/// The caller of a `gen_server` call, to reply to.
+pub struct Caller {
+  pid: crate::erlang_pid,
+  tag: XBuff,
+}
+
+impl Caller {
+  /// Returns the pid of the process waiting for the reply.
+  #[inline]
+  pub fn pid(&self) -> &crate::erlang_pid {
+    &self.pid
+  }
+}
+
+/// A process of this node that answers `gen_server` calls and casts from Erlang.
+///
+/// Messages are handed to the server with [`Connection::handle_gen_server`].
+///
+/// [`Connection::handle_gen_server`]: struct.Connection.html#method.handle_gen_server
+pub trait GenServer {
+  /// The requests of calls.
+  type Call: FromTerm;
+  /// The replies to calls.
+  type Reply: ToTerm;
+  /// The requests of casts.
+  type Cast: FromTerm;
+
+  /// Handles a call, made with `gen_server:call/2,3`, and returns the reply.
+  fn handle_call(&mut self, request: Self::Call, from: &Caller) -> Self::Reply;
+
+  /// Handles a cast, made with `gen_server:cast/2`.
+  fn handle_cast(&mut self, request: Self::Cast);
+
+  /// Handles any other message. The default implementation ignores it.
+  fn handle_info(&mut self, msg: &crate::erlang_msg, payload: &XBuff) {
+    let _ = (msg, payload);
+  }
+}
+
+impl Connection {
+  /// Calls a `gen_server` on the other node with `request` and decodes its reply, like
+  /// `gen_server:call/3`.
+  ///
+  /// Other messages received while waiting for the reply are discarded.
+  pub fn call<'s, S, Q, R>(&mut self, server: S, request: Q, timeout: Duration) -> Result<R, Error>
+  where
+    S: Into<Server<'s>>,
+    Q: ToTerm,
+    R: FromTerm,
+  {
+    let reference = self.node().make_ref();
+    let pid = self.node().pid().clone();
+    let mut buf = XBuff::with_version()?;
+    buf.encoder().tuple(3, |e| {
+      e.atom("$gen_call")?.tuple(2, |e| {
+        e.pid(&pid)?.reference(&reference)?;
+        Ok(())
+      })?;
+      request.to_term(e)
+    })?;
+    self.send_to(server.into(), &buf)?;
+
+    let deadline = Deadline::new(timeout);
+    loop {
+      let remaining = deadline.remaining().ok_or(Error::Timeout)?;
+      if let Received::Message { msg, payload } = self.receive_timeout(remaining)? {
+        if !is_send(&msg) {
+          continue;
+        }
+        let mut decoder = match Decoder::from_x_buff(&payload) {
+          Ok(decoder) => decoder,
+          Err(_) => continue,
+        };
+        if decoder.decode_tuple_header() == Ok(2)
+          && decoder.decode_ref().is_ok_and(|r| same_ref(&r, &reference))
+        {
+          return R::from_term(&mut decoder);
+        }
+      }
+    }
+  }
+
+  /// Sends `request` to a `gen_server` on the other node without waiting, like
+  /// `gen_server:cast/2`.
+  pub fn cast<'s, S, Q>(&mut self, server: S, request: Q) -> Result<(), Error>
+  where
+    S: Into<Server<'s>>,
+    Q: ToTerm,
+  {
+    let mut buf = XBuff::with_version()?;
+    buf.encoder().tuple(2, |e| {
+      e.atom("$gen_cast")?;
+      request.to_term(e)
+    })?;
+    self.send_to(server.into(), &buf)
+  }
+
+  /// Hands a message received on this connection to `server`, and sends the reply if it is a
+  /// call.
+  ///
+  /// Calls and casts whose request cannot be decoded as [`GenServer::Call`] or
+  /// [`GenServer::Cast`] are ignored, so the caller of such a call times out.
+  ///
+  /// [`GenServer::Call`]: trait.GenServer.html#associatedtype.Call
+  /// [`GenServer::Cast`]: trait.GenServer.html#associatedtype.Cast
+  pub fn handle_gen_server<S: GenServer>(
+    &mut self,
+    server: &mut S,
+    msg: &crate::erlang_msg,
+    payload: &XBuff,
+  ) -> Result<(), Error> {
+    let mut decoder = match Decoder::from_x_buff(payload) {
+      Ok(decoder) if is_send(msg) => decoder,
+      _ => {
+        server.handle_info(msg, payload);
+        return Ok(());
+      }
+    };
+    let mut atom = [0; MAXATOMLEN_UTF8];
+    let arity = decoder.decode_tuple_header();
+    let tag = decoder.decode_atom(&mut atom);
+    match (arity, tag) {
+      (Ok(3), Ok("$gen_call")) => {
+        if decoder.decode_tuple_header()? != 2 {
+          return Err(Error::Decode);
+        }
+        let pid = decoder.decode_pid()?;
+        let tag = decoder.remaining();
+        decoder.skip()?;
+        let mut caller = Caller {
+          pid,
+          tag: XBuff::new()?,
+        };
+        caller
+          .tag
+          .extend_from_slice(&tag[..tag.len() - decoder.remaining().len()])?;
+        let request = match S::Call::from_term(&mut decoder) {
+          Ok(request) => request,
+          Err(_) => return Ok(()),
+        };
+        let reply = server.handle_call(request, &caller);
+        self.reply(&caller, reply)
+      }
+      (Ok(2), Ok("$gen_cast")) => {
+        if let Ok(request) = S::Cast::from_term(&mut decoder) {
+          server.handle_cast(request);
+        }
+        Ok(())
+      }
+      _ => {
+        server.handle_info(msg, payload);
+        Ok(())
+      }
+    }
+  }
+
+  /// Replies to a call, like `gen_server:reply/2`.
+  pub fn reply<R: ToTerm>(&mut self, caller: &Caller, reply: R) -> Result<(), Error> {
+    let mut buf = XBuff::with_version()?;
+    buf.encoder().tuple(2, |e| {
+      e.raw(&[caller.tag.as_bytes()])?;
+      reply.to_term(e)
+    })?;
+    self.send(&caller.pid, &buf)
+  }
+
+  fn send_to(&mut self, server: Server, buf: &XBuff) -> Result<(), Error> {
+    match server {
+      Server::Name(name) => self.reg_send(name, buf),
+      Server::Pid(pid) => self.send(pid, buf),
+    }
+  }
+}
+
+/// Returns `true` if `msg` is an ordinary message, sent to a pid or a registered name.
+fn is_send(msg: &crate::erlang_msg) -> bool {
+  msg.msgtype == c_long::from(crate::ERL_SEND) || msg.msgtype == c_long::from(crate::ERL_REG_SEND)
+}
+
+/// Returns `true` if `a` and `b` are the same reference, ignoring how they were encoded.
+fn same_ref(a: &crate::erlang_ref, b: &crate::erlang_ref) -> bool {
+  let len = a.len as usize;
+  a.len == b.len
+    && len <= a.n.len()
+    && a.n[..len] == b.n[..len]
+    && c_str::to_str(&a.node) == c_str::to_str(&b.node)
+}
@@ -9,6 +9,83 @@ use core::{ffi::c_void, ops};
 use in_addr;
 use libc::{c_char, c_int, c_long, c_longlong, c_short, c_uchar, c_uint, c_ulong, c_ulonglong};
 
+#[cfg(feature = "alloc")]
+extern crate alloc;
+#[cfg(feature = "std")]
+extern crate std;
+
+#[cfg(all(feature = "tokio", unix))]
+mod async_connection;
+mod c_str;
+mod cnode;
+mod connection;
+mod convert;
+#[cfg(feature = "std")]
+mod cookie;
+mod decode;
+#[cfg(feature = "alloc")]
+mod dispatcher;
+mod encode;
+#[cfg(feature = "std")]
+mod epmd;
+mod error;
+mod gen_server;
+#[cfg(feature = "std")]
+mod listener;
+mod node_name;
+#[cfg(feature = "std")]
+mod pool;
+mod rpc;
+#[cfg(feature = "std")]
+mod supervisor;
+mod tag;
+#[cfg(feature = "alloc")]
+mod term;
+mod term_ref;
+#[cfg(feature = "serde")]
+mod term_serde;
+#[cfg(feature = "test-support")]
+pub mod test_support;
+mod x_buff;
+
+#[cfg(all(feature = "tokio", unix))]
+pub use async_connection::{AsyncConnection, AsyncListener};
+pub use cnode::{CNode, CNodeBuilder};
+pub use connection::{Connection, Received};
+pub use convert::{FromTerm, ToTerm};
+#[cfg(feature = "std")]
+pub use cookie::{Cookie, CookieLookup, CookieSource, COOKIE_ENV_VAR};
+pub use decode::Decoder;
+#[cfg(feature = "alloc")]
+pub use dispatcher::{Dispatcher, Mailbox, Signal};
+pub use encode::Encoder;
+#[cfg(feature = "std")]
+pub use epmd::{DumpEntry, Epmd, NodeInfo, NodeType, Registration, EPMD_PORT};
+pub use error::Error;
+pub use gen_server::{Caller, GenServer, Server};
+#[cfg(feature = "std")]
+pub use listener::{Listener, PeerInfo};
+pub use node_name::{NameKind, NodeName};
+#[cfg(feature = "std")]
+pub use pool::{NodePool, NodePoolBuilder, RemoteNode};
+pub use rpc::{RpcError, RpcId, RpcReply};
+#[cfg(feature = "std")]
+pub use supervisor::{
+  ConnectionEvent, SendPolicy, SupervisedConnection, SupervisedConnectionBuilder,
+};
+pub use tag::{ExtTag, InvalidExtTag};
+#[cfg(feature = "alloc")]
+pub use term::{BigInt, BitString, Pid, Port, Reference, Term};
+pub use term_ref::{Elements, Entries, TermRef};
+#[cfg(feature = "serde")]
+pub use term_serde::{
+  Deserializer, OptionMapping, SerdeConfig, SerdeError, Serializer, StructMapping,
+};
+pub use x_buff::XBuff;
+
+#[cfg(feature = "derive")]
+pub use ei_sys_derive::{FromTerm, ToTerm};
+
 pub const ERL_TICK: c_int = 0;
 pub const ERL_MSG: c_int = 1;
 pub const ERL_ERROR: c_int = -1;
@@ -26,6 +103,10 @@ pub const ERL_NODE_LINK: u8 = 5;
 pub const ERL_REG_SEND: u8 = 6;
 pub const ERL_GROUP_LEADER: u8 = 7;
 pub const ERL_EXIT2: u8 = 8;
+pub const ERL_SEND_TT: u8 = 12;
+pub const ERL_EXIT_TT: u8 = 13;
+pub const ERL_REG_SEND_TT: u8 = 16;
+pub const ERL_EXIT2_TT: u8 = 18;
 pub const ERL_PASS_THROUGH: u8 = b'p';
 
 pub const EI_MAXHOSTNAMELEN: usize = 64;
@@ -279,6 +360,39 @@ extern "C" {
     ms: c_uint,
   ) -> c_int;
 
+  /// Connects to the Erlang node listening on `port` of `hostname`, without asking EPMD.
+  ///
+  /// # See Also
+  ///
+  /// [The official entry for this function in the Erlang documentation.](http://erlang.org/doc/man/ei_connect.html#ei_connect_host_port)
+  pub fn ei_connect_host_port(ec: *mut ei_cnode, hostname: *mut c_char, port: c_int) -> c_int;
+
+  pub fn ei_connect_host_port_tmo(
+    ec: *mut ei_cnode,
+    hostname: *mut c_char,
+    port: c_int,
+    ms: c_uint,
+  ) -> c_int;
+
+  /// Connects to the Erlang node listening on `port` of the address `remote_host`, without asking
+  /// EPMD.
+  ///
+  /// # See Also
+  ///
+  /// [The official entry for this function in the Erlang documentation.](http://erlang.org/doc/man/ei_connect.html#ei_xconnect_host_port)
+  pub fn ei_xconnect_host_port(
+    ec: *mut ei_cnode,
+    remote_host: *mut in_addr::in_addr,
+    port: c_int,
+  ) -> c_int;
+
+  pub fn ei_xconnect_host_port_tmo(
+    ec: *mut ei_cnode,
+    remote_host: *mut in_addr::in_addr,
+    port: c_int,
+    ms: c_uint,
+  ) -> c_int;
+
   pub fn ei_receive(fd: c_int, bufp: *mut c_uchar, bufsize: c_int) -> c_int;
 
   pub fn ei_receive_tmo(fd: c_int, bufp: *mut c_uchar, bufsize: c_int, ms: c_uint) -> c_int;
@@ -521,6 +635,17 @@ extern "C" {
 
   pub fn ei_x_encode_map_header(x: *mut ei_x_buff, n: c_long) -> c_int;
 
+  /// Reads the type and size of the term starting at `index` in `buf`, without moving the index.
+  ///
+  /// The tag written to `type_` is normalized for some terms, such as atoms, which are always
+  /// reported as [`ATOM_EXT`]. Refer to the documentation of [`ExtTag`] for the complete list.
+  ///
+  /// [`ATOM_EXT`]: constant.ATOM_EXT.html
+  /// [`ExtTag`]: enum.ExtTag.html
+  ///
+  /// # See Also
+  ///
+  /// [The official entry for this function in the Erlang documentation.](http://erlang.org/doc/man/ei.html#ei_get_type)
   pub fn ei_get_type(
     buf: *const c_char,
     index: *const c_int,
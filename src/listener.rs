@@ -0,0 +1,186 @@
+//! Accepting connections from other nodes.
+
+use crate::{
+  c_str,
+  connection::millis,
+  error::{io_error, last_error, Error},
+  CNode, Connection,
+};
+use core::{mem, net::Ipv4Addr, time::Duration};
+use libc::{c_int, c_uint};
+use std::net::TcpListener;
+
+/// A socket accepting connections from other nodes, published to EPMD under the name of its node.
+///
+/// The node is unpublished and the socket closed when the listener is dropped.
+pub struct Listener {
+  node: CNode,
+  fd: c_int,
+  epmd: c_int,
+  port: u16,
+}
+
+/// Information on the node at the other end of an accepted connection.
+#[derive(Clone)]
+pub struct PeerInfo {
+  raw: crate::ErlConnect,
+}
+
+impl CNode {
+  /// Listens on `port`, or on any available port if it is `0`, and publishes the node to the
+  /// local EPMD instance.
+  ///
+  /// # See Also
+  ///
+  /// [The official entry for this function in the Erlang documentation.](http://erlang.org/doc/man/ei_connect.html#ei_publish)
+  pub fn listen(&self, port: u16) -> Result<Listener, Error> {
+    let socket = TcpListener::bind((Ipv4Addr::UNSPECIFIED, port)).map_err(io_error)?;
+    let port = socket.local_addr().map_err(io_error)?.port();
+    let fd = into_raw_fd(socket);
+
+    let mut node = self.clone();
+    let epmd = unsafe { crate::ei_publish(node.as_mut_ptr(), c_int::from(port)) };
+    if epmd < 0 {
+      let error = last_error();
+      close_socket(fd);
+      return Err(error);
+    }
+    Ok(Listener {
+      node,
+      fd,
+      epmd,
+      port,
+    })
+  }
+}
+
+impl Listener {
+  /// Returns the port the listener accepts connections on.
+  #[inline]
+  pub fn port(&self) -> u16 {
+    self.port
+  }
+
+  /// Returns the file descriptor of the listening socket.
+  #[inline]
+  pub fn fd(&self) -> c_int {
+    self.fd
+  }
+
+  /// Waits for another node to connect.
+  ///
+  /// # See Also
+  ///
+  /// [The official entry for this function in the Erlang documentation.](http://erlang.org/doc/man/ei_connect.html#ei_accept)
+  pub fn accept(&mut self) -> Result<(Connection, PeerInfo), Error> {
+    self.accept_with(0)
+  }
+
+  /// Waits for another node to connect, or fails with [`Error::Timeout`] after `timeout`.
+  ///
+  /// [`Error::Timeout`]: enum.Error.html#variant.Timeout
+  ///
+  /// # See Also
+  ///
+  /// [The official entry for this function in the Erlang documentation.](http://erlang.org/doc/man/ei_connect.html#ei_accept_tmo)
+  pub fn accept_timeout(&mut self, timeout: Duration) -> Result<(Connection, PeerInfo), Error> {
+    self.accept_with(millis(timeout))
+  }
+
+  fn accept_with(&mut self, ms: c_uint) -> Result<(Connection, PeerInfo), Error> {
+    let mut raw: crate::ErlConnect = unsafe { mem::zeroed() };
+    let fd = unsafe { crate::ei_accept_tmo(self.node.as_mut_ptr(), self.fd, &mut raw, ms) };
+    if fd < 0 {
+      return Err(last_error());
+    }
+    let mut connection = unsafe { Connection::from_raw_fd(self.node.clone(), fd) };
+    c_str::to_str(&raw.nodename)?;
+    connection.set_peer(&raw.nodename);
+    Ok((connection, PeerInfo { raw }))
+  }
+}
+
+impl Drop for Listener {
+  fn drop(&mut self) {
+    // EPMD unpublishes the node when the connection it was published on closes.
+    close_socket(self.epmd);
+    close_socket(self.fd);
+  }
+}
+
+impl PeerInfo {
+  /// Returns the name of the other node, `alive@host`.
+  pub fn node_name(&self) -> &str {
+    // Checked when the connection was accepted.
+    c_str::to_str(&self.raw.nodename).unwrap_or_default()
+  }
+
+  /// Returns the address of the other node.
+  pub fn ip(&self) -> Ipv4Addr {
+    let [a, b, c, d] = self.raw.ipadr;
+    Ipv4Addr::new(a as u8, b as u8, c as u8, d as u8)
+  }
+
+  /// Returns the raw information, as filled by [`ei_accept`].
+  ///
+  /// [`ei_accept`]: fn.ei_accept.html
+  #[inline]
+  pub fn as_raw(&self) -> &crate::ErlConnect {
+    &self.raw
+  }
+}
+
+#[cfg(unix)]
+impl std::os::unix::io::AsRawFd for Listener {
+  #[inline]
+  fn as_raw_fd(&self) -> c_int {
+    self.fd
+  }
+}
+
+#[cfg(all(feature = "mio", unix))]
+impl mio::event::Source for Listener {
+  fn register(
+    &mut self,
+    registry: &mio::Registry,
+    token: mio::Token,
+    interests: mio::Interest,
+  ) -> std::io::Result<()> {
+    mio::unix::SourceFd(&self.fd).register(registry, token, interests)
+  }
+
+  fn reregister(
+    &mut self,
+    registry: &mio::Registry,
+    token: mio::Token,
+    interests: mio::Interest,
+  ) -> std::io::Result<()> {
+    mio::unix::SourceFd(&self.fd).reregister(registry, token, interests)
+  }
+
+  fn deregister(&mut self, registry: &mio::Registry) -> std::io::Result<()> {
+    mio::unix::SourceFd(&self.fd).deregister(registry)
+  }
+}
+
+#[cfg(unix)]
+fn into_raw_fd(socket: TcpListener) -> c_int {
+  std::os::unix::io::IntoRawFd::into_raw_fd(socket)
+}
+
+#[cfg(windows)]
+fn into_raw_fd(socket: TcpListener) -> c_int {
+  std::os::windows::io::IntoRawSocket::into_raw_socket(socket) as c_int
+}
+
+#[cfg(unix)]
+fn close_socket(fd: c_int) {
+  unsafe { libc::close(fd) };
+}
+
+/// Closes `fd` with `closesocket`, as sockets are not file descriptors on Windows.
+#[cfg(windows)]
+fn close_socket(fd: c_int) {
+  use std::os::windows::io::{FromRawSocket, RawSocket};
+  drop(unsafe { std::net::TcpStream::from_raw_socket(fd as RawSocket) });
+}
@@ -0,0 +1,203 @@
+//! Names of nodes, `alive@host`.
+
+use crate::{c_str, Error, EI_MAXALIVELEN, EI_MAXHOSTNAMELEN, MAXNODELEN};
+use core::{ffi::CStr, fmt, hash, str::FromStr};
+use libc::c_char;
+
+/// Whether a node name is short, as given to `erl -sname`, or long, as given to `erl -name`.
+///
+/// Nodes only connect to nodes whose names are of the same kind.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum NameKind {
+  /// A name whose host is a plain host name, such as `node@host`.
+  Short,
+  /// A name whose host is a fully qualified domain name or an IP address, such as
+  /// `node@host.example.com`.
+  Long,
+}
+
+/// A valid node name, `alive@host`, along with the C strings ei expects of it.
+///
+/// The alive name is made of ASCII letters, digits, `_` and `-`, and the host of ASCII letters,
+/// digits, `.`, `_` and `-`, neither empty and each within the limits of ei,
+/// [`EI_MAXALIVELEN`] and [`EI_MAXHOSTNAMELEN`].
+///
+/// [`EI_MAXALIVELEN`]: constant.EI_MAXALIVELEN.html
+/// [`EI_MAXHOSTNAMELEN`]: constant.EI_MAXHOSTNAMELEN.html
+#[derive(Clone)]
+pub struct NodeName {
+  node: [c_char; MAXNODELEN + 1],
+  alive: [c_char; EI_MAXALIVELEN + 1],
+  host: [c_char; EI_MAXHOSTNAMELEN + 1],
+}
+
+impl NodeName {
+  /// Creates the name `alive@host`.
+  ///
+  /// Fails with [`Error::InvalidArgument`] if either part is invalid.
+  ///
+  /// [`Error::InvalidArgument`]: enum.Error.html#variant.InvalidArgument
+  pub fn new(alive: &str, host: &str) -> Result<Self, Error> {
+    let valid_alive = alive
+      .bytes()
+      .all(|b| b.is_ascii_alphanumeric() || b == b'_' || b == b'-');
+    let valid_host = host
+      .bytes()
+      .all(|b| b.is_ascii_alphanumeric() || b == b'.' || b == b'_' || b == b'-');
+    if alive.is_empty() || host.is_empty() || !valid_alive || !valid_host {
+      return Err(Error::InvalidArgument);
+    }
+
+    let mut name = Self {
+      node: [0; MAXNODELEN + 1],
+      alive: [0; EI_MAXALIVELEN + 1],
+      host: [0; EI_MAXHOSTNAMELEN + 1],
+    };
+    c_str::copy(alive, &mut name.alive).map_err(|_| Error::InvalidArgument)?;
+    c_str::copy(host, &mut name.host).map_err(|_| Error::InvalidArgument)?;
+    // Both parts fit, so `alive@host` fits with its null character.
+    let node = alive.bytes().chain(Some(b'@')).chain(host.bytes());
+    for (dst, src) in name.node.iter_mut().zip(node) {
+      *dst = src as c_char;
+    }
+    Ok(name)
+  }
+
+  /// Parses `name`, of the form `alive@host`.
+  ///
+  /// Fails with [`Error::InvalidArgument`] if it is not a valid node name.
+  ///
+  /// [`Error::InvalidArgument`]: enum.Error.html#variant.InvalidArgument
+  pub fn parse(name: &str) -> Result<Self, Error> {
+    let (alive, host) = name.split_once('@').ok_or(Error::InvalidArgument)?;
+    Self::new(alive, host)
+  }
+
+  /// Creates the short name `alive@host`, where `host` is the name of the local host up to its
+  /// first dot, like `erl -sname alive`.
+  #[cfg(unix)]
+  pub fn short(alive: &str) -> Result<Self, Error> {
+    let mut buf = [0; 256];
+    let host = local_host_name(&mut buf)?;
+    Self::new(alive, host.split('.').next().unwrap_or(host))
+  }
+
+  /// Creates the long name `alive@host`, where `host` is the name of the local host, like
+  /// `erl -name alive`.
+  ///
+  /// Fails with [`Error::InvalidArgument`] if the name of the local host is not fully qualified.
+  ///
+  /// [`Error::InvalidArgument`]: enum.Error.html#variant.InvalidArgument
+  #[cfg(unix)]
+  pub fn long(alive: &str) -> Result<Self, Error> {
+    let mut buf = [0; 256];
+    let host = local_host_name(&mut buf)?;
+    if !host.contains('.') {
+      return Err(Error::InvalidArgument);
+    }
+    Self::new(alive, host)
+  }
+
+  /// Returns the name, `alive@host`.
+  #[inline]
+  pub fn as_str(&self) -> &str {
+    // Only ever set from ASCII.
+    c_str::to_str(&self.node).unwrap_or_default()
+  }
+
+  /// Returns the alive name, the part before the `@`.
+  #[inline]
+  pub fn alive(&self) -> &str {
+    c_str::to_str(&self.alive).unwrap_or_default()
+  }
+
+  /// Returns the host, the part after the `@`.
+  #[inline]
+  pub fn host(&self) -> &str {
+    c_str::to_str(&self.host).unwrap_or_default()
+  }
+
+  /// Returns whether the name is short or long, depending on whether its host has a dot.
+  pub fn kind(&self) -> NameKind {
+    if self.host().contains('.') {
+      NameKind::Long
+    } else {
+      NameKind::Short
+    }
+  }
+
+  /// Returns the name as a C string, as expected by [`ei_connect`] and the `thisnodename` of
+  /// [`ei_connect_xinit`].
+  ///
+  /// [`ei_connect`]: fn.ei_connect.html
+  /// [`ei_connect_xinit`]: fn.ei_connect_xinit.html
+  #[inline]
+  pub fn as_c_str(&self) -> &CStr {
+    unsafe { CStr::from_ptr(self.node.as_ptr()) }
+  }
+
+  /// Returns the alive name as a C string, as expected by [`ei_connect_init`] and
+  /// [`ei_connect_xinit`].
+  ///
+  /// [`ei_connect_init`]: fn.ei_connect_init.html
+  /// [`ei_connect_xinit`]: fn.ei_connect_xinit.html
+  #[inline]
+  pub fn alive_c_str(&self) -> &CStr {
+    unsafe { CStr::from_ptr(self.alive.as_ptr()) }
+  }
+
+  /// Returns the host as a C string, as expected by [`ei_connect_xinit`].
+  ///
+  /// [`ei_connect_xinit`]: fn.ei_connect_xinit.html
+  #[inline]
+  pub fn host_c_str(&self) -> &CStr {
+    unsafe { CStr::from_ptr(self.host.as_ptr()) }
+  }
+}
+
+impl FromStr for NodeName {
+  type Err = Error;
+
+  #[inline]
+  fn from_str(name: &str) -> Result<Self, Error> {
+    Self::parse(name)
+  }
+}
+
+impl fmt::Display for NodeName {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    f.write_str(self.as_str())
+  }
+}
+
+impl fmt::Debug for NodeName {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    fmt::Debug::fmt(self.as_str(), f)
+  }
+}
+
+impl PartialEq for NodeName {
+  fn eq(&self, other: &Self) -> bool {
+    self.as_str() == other.as_str()
+  }
+}
+
+impl Eq for NodeName {}
+
+impl hash::Hash for NodeName {
+  fn hash<H: hash::Hasher>(&self, state: &mut H) {
+    self.as_str().hash(state)
+  }
+}
+
+/// Returns the name of the local host, stored in `buf`.
+#[cfg(unix)]
+fn local_host_name(buf: &mut [c_char]) -> Result<&str, Error> {
+  if unsafe { libc::gethostname(buf.as_mut_ptr(), buf.len() - 1) } < 0 {
+    return Err(crate::error::os_error());
+  }
+  // The name is truncated, without null character, if it does not fit.
+  let last = buf.len() - 1;
+  buf[last] = 0;
+  c_str::to_str(buf).map_err(|_| Error::InvalidArgument)
+}
@@ -0,0 +1,347 @@
+//! Connections to many nodes, shared between threads.
+
+use crate::{
+  c_str,
+  error::{is_lost, lock},
+  CNode, Connection, Error, FromTerm, RpcError, ToTerm, XBuff,
+};
+use core::time::Duration;
+use std::{
+  collections::HashMap,
+  string::String,
+  sync::{Arc, Mutex},
+  time::Instant,
+  vec::Vec,
+};
+
+/// A node reached through a [`NodePool`], either by its name or by the pid of one of its
+/// processes.
+///
+/// [`NodePool`]: struct.NodePool.html
+#[derive(Clone, Copy)]
+pub enum RemoteNode<'a> {
+  /// A node name, of the form `alive@host`.
+  Name(&'a str),
+  /// The node of a process.
+  Pid(&'a crate::erlang_pid),
+}
+
+impl<'a> From<&'a str> for RemoteNode<'a> {
+  #[inline]
+  fn from(name: &'a str) -> Self {
+    RemoteNode::Name(name)
+  }
+}
+
+impl<'a> From<&'a crate::erlang_pid> for RemoteNode<'a> {
+  #[inline]
+  fn from(pid: &'a crate::erlang_pid) -> Self {
+    RemoteNode::Pid(pid)
+  }
+}
+
+impl RemoteNode<'_> {
+  fn name(&self) -> Result<&str, Error> {
+    match self {
+      RemoteNode::Name(name) => Ok(name),
+      RemoteNode::Pid(pid) => c_str::to_str(&pid.node).map_err(|_| Error::InvalidArgument),
+    }
+  }
+}
+
+/// A set of connections from a node to others, opened when first needed and shared between
+/// threads.
+///
+/// Each connection is used by one thread at a time, so operations on different nodes do not wait
+/// for each other. A connection that is lost is dropped, and opened again by the next operation
+/// on its node.
+///
+/// The pool only sends: messages received on its connections, other than the replies to its own
+/// remote procedure calls, are discarded. It answers ticks, as set with
+/// [`NodePoolBuilder::net_ticktime`], whenever it uses a connection, and drops the connections
+/// found lost then.
+///
+/// [`NodePoolBuilder::net_ticktime`]: struct.NodePoolBuilder.html#method.net_ticktime
+pub struct NodePool {
+  config: NodePoolBuilder,
+  slots: Mutex<HashMap<String, Arc<Mutex<Slot>>>>,
+  swept: Mutex<Instant>,
+}
+
+/// Configures and creates a [`NodePool`].
+///
+/// [`NodePool`]: struct.NodePool.html
+pub struct NodePoolBuilder {
+  node: CNode,
+  connect_timeout: Duration,
+  idle_timeout: Option<Duration>,
+  net_ticktime: Duration,
+}
+
+struct Slot {
+  connection: Option<Connection>,
+  used: Instant,
+}
+
+impl NodePool {
+  /// Starts building a pool of connections from `node`.
+  pub fn builder(node: &CNode) -> NodePoolBuilder {
+    NodePoolBuilder {
+      node: node.clone(),
+      connect_timeout: Duration::from_secs(5),
+      idle_timeout: None,
+      net_ticktime: Duration::from_secs(60),
+    }
+  }
+
+  /// Sends the message in `buf` to the process `to`, on the connection to its node.
+  pub fn send(&self, to: &crate::erlang_pid, buf: &XBuff) -> Result<(), Error> {
+    self.with_connection(to, |connection| connection.send(to, buf))
+  }
+
+  /// Sends the message in `buf` to the process registered as `name` on `node`.
+  pub fn reg_send<'n, N>(&self, node: N, name: &str, buf: &XBuff) -> Result<(), Error>
+  where
+    N: Into<RemoteNode<'n>>,
+  {
+    self.with_connection(node, |connection| connection.reg_send(name, buf))
+  }
+
+  /// Calls `module:function` with `args` on `node` and decodes its result, like
+  /// [`Connection::rpc`].
+  ///
+  /// [`Connection::rpc`]: struct.Connection.html#method.rpc
+  pub fn rpc<'n, N, A, R>(
+    &self,
+    node: N,
+    module: &str,
+    function: &str,
+    args: A,
+    timeout: Duration,
+  ) -> Result<R, RpcError>
+  where
+    N: Into<RemoteNode<'n>>,
+    A: ToTerm,
+    R: FromTerm,
+  {
+    self.run(
+      node.into(),
+      |connection| connection.rpc(module, function, args, timeout),
+      |error| matches!(error, RpcError::Ei(error) if is_lost(*error)),
+    )
+  }
+
+  /// Runs `f` with the connection to `node`, opening it first if needed, and drops the connection
+  /// if `f` fails because it was lost.
+  pub fn with_connection<'n, N, T, F>(&self, node: N, f: F) -> Result<T, Error>
+  where
+    N: Into<RemoteNode<'n>>,
+    F: FnOnce(&mut Connection) -> Result<T, Error>,
+  {
+    self.run(node.into(), f, |error| is_lost(*error))
+  }
+
+  /// Closes the connection to the node named `node_name`, if it is open.
+  ///
+  /// Waits for the operation using the connection, if any, to complete.
+  pub fn close(&self, node_name: &str) {
+    let slot = lock(&self.slots).remove(node_name);
+    if let Some(slot) = slot {
+      lock(&slot).connection = None;
+    }
+  }
+
+  /// Closes the connections that were not used for the idle timeout, and returns how many.
+  /// Answers ticks on the others, and drops those found lost.
+  ///
+  /// This is done regularly by the other operations, so calling it is only needed to close idle
+  /// connections while the pool is not used.
+  pub fn close_idle(&self) -> usize {
+    let idle_timeout = match self.config.idle_timeout {
+      Some(idle_timeout) => idle_timeout,
+      None => return 0,
+    };
+    // The connections are ticked without holding the lock of the map, so that other threads can
+    // use their connections meanwhile.
+    let slots: Vec<_> = lock(&self.slots)
+      .iter()
+      .map(|(name, shared)| (name.clone(), Arc::clone(shared)))
+      .collect();
+    let mut closed = 0;
+    for (name, shared) in slots {
+      {
+        // A slot that is locked is in use.
+        let mut slot = match shared.try_lock() {
+          Ok(slot) => slot,
+          Err(_) => continue,
+        };
+        if slot.connection.is_some() && slot.used.elapsed() >= idle_timeout {
+          slot.connection = None;
+          closed += 1;
+        }
+        if let Some(connection) = &mut slot.connection {
+          if keep_alive(connection).is_err() {
+            slot.connection = None;
+          }
+        }
+        if slot.connection.is_some() {
+          continue;
+        }
+      }
+      let mut slots = lock(&self.slots);
+      // Another thread may hold the slot, besides the map and this function, without having
+      // locked it yet, to open a connection in it, which must stay the only one to its node.
+      let unused = slots.get(&name).is_some_and(|current| {
+        Arc::ptr_eq(current, &shared)
+          && Arc::strong_count(&shared) == 2
+          && shared
+            .try_lock()
+            .is_ok_and(|slot| slot.connection.is_none())
+      });
+      if unused {
+        slots.remove(&name);
+      }
+    }
+    closed
+  }
+
+  /// Returns the names of the nodes with an open connection.
+  pub fn connected_nodes(&self) -> Vec<String> {
+    lock(&self.slots)
+      .iter()
+      .filter(|(_, slot)| {
+        slot
+          .try_lock()
+          .map_or(true, |slot| slot.connection.is_some())
+      })
+      .map(|(name, _)| name.clone())
+      .collect()
+  }
+
+  fn run<T, E, F, L>(&self, node: RemoteNode, f: F, lost: L) -> Result<T, E>
+  where
+    E: From<Error>,
+    F: FnOnce(&mut Connection) -> Result<T, E>,
+    L: FnOnce(&E) -> bool,
+  {
+    let node_name = node.name()?;
+    let slot = self.slot(node_name);
+    let mut slot = lock(&slot);
+    slot.used = Instant::now();
+    let mut connection = match slot.connection.take() {
+      Some(mut connection) => match keep_alive(&mut connection) {
+        Ok(()) => connection,
+        Err(_) => self.config.open(node_name)?,
+      },
+      None => self.config.open(node_name)?,
+    };
+    let result = f(&mut connection);
+    match &result {
+      Err(error) if lost(error) => {}
+      _ => slot.connection = Some(connection),
+    }
+    result
+  }
+
+  /// Returns the slot of the connection to `node_name`, after closing idle connections if it is
+  /// time to.
+  fn slot(&self, node_name: &str) -> Arc<Mutex<Slot>> {
+    if let Some(idle_timeout) = self.config.idle_timeout {
+      let mut swept = lock(&self.swept);
+      if swept.elapsed() >= idle_timeout / 2 {
+        *swept = Instant::now();
+        drop(swept);
+        self.close_idle();
+      }
+    }
+    let mut slots = lock(&self.slots);
+    let slot = slots.entry(node_name.into()).or_insert_with(|| {
+      Arc::new(Mutex::new(Slot {
+        connection: None,
+        used: Instant::now(),
+      }))
+    });
+    Arc::clone(slot)
+  }
+}
+
+impl NodePoolBuilder {
+  /// Sets how long opening a connection may take, 5 seconds by default.
+  pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+    self.connect_timeout = timeout;
+    self
+  }
+
+  /// Closes the connections that are not used for `timeout`. Connections are kept open by
+  /// default.
+  ///
+  /// The pool only answers ticks when it is used, so other nodes drop the connections that it
+  /// does not use for their `net_ticktime`, and `timeout` should be shorter.
+  pub fn idle_timeout(mut self, timeout: Duration) -> Self {
+    self.idle_timeout = Some(timeout);
+    self
+  }
+
+  /// Sets the tick time of the connections, as with [`Connection::set_net_ticktime`], 60 seconds
+  /// by default like Erlang nodes.
+  ///
+  /// [`Connection::set_net_ticktime`]: struct.Connection.html#method.set_net_ticktime
+  pub fn net_ticktime(mut self, ticktime: Duration) -> Self {
+    self.net_ticktime = ticktime;
+    self
+  }
+
+  /// Creates the pool, without opening any connection.
+  pub fn build(self) -> NodePool {
+    NodePool {
+      config: self,
+      slots: Mutex::new(HashMap::new()),
+      swept: Mutex::new(Instant::now()),
+    }
+  }
+
+  fn open(&self, node_name: &str) -> Result<Connection, Error> {
+    let mut connection = self.node.connect_timeout(node_name, self.connect_timeout)?;
+    connection.set_net_ticktime(Some(self.net_ticktime));
+    Ok(connection)
+  }
+}
+
+/// Discards what the other node sent since the connection was last used, then answers ticks, and
+/// fails if the connection was lost.
+fn keep_alive(connection: &mut Connection) -> Result<(), Error> {
+  #[cfg(unix)]
+  loop {
+    match connection.try_receive() {
+      Ok(_) => {}
+      Err(Error::WouldBlock) => break,
+      Err(error) => return Err(error),
+    }
+  }
+  connection.heartbeat()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn close_idle_keeps_the_slots_held_by_other_threads() {
+    let node = CNode::builder("test", "cookie")
+      .host_name("host")
+      .build()
+      .unwrap();
+    let pool = NodePool::builder(&node)
+      .idle_timeout(Duration::from_secs(60))
+      .build();
+
+    // As if another thread was about to open a connection in the slot.
+    let slot = pool.slot("peer@host");
+    pool.close_idle();
+    assert!(lock(&pool.slots).contains_key("peer@host"));
+
+    drop(slot);
+    pool.close_idle();
+    assert!(lock(&pool.slots).is_empty());
+  }
+}
@@ -0,0 +1,299 @@
+//! Remote procedure calls through the `rex` server of other nodes.
+
+#[cfg(not(all(feature = "std", unix)))]
+use crate::connection::millis;
+#[cfg(all(feature = "std", unix))]
+use crate::Received;
+use crate::{
+  c_str, connection::Deadline, error::last_error, Connection, Decoder, Error, FromTerm, ToTerm,
+  XBuff, MAXATOMLEN_UTF8,
+};
+use core::{convert::TryFrom, fmt, mem, time::Duration};
+use libc::{c_int, c_long};
+
+/// Identifies a call made with [`Connection::rpc_to`], to match it with its reply.
+///
+/// [`Connection::rpc_to`]: struct.Connection.html#method.rpc_to
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct RpcId(u32);
+
+/// A reply received by [`Connection::rpc_from`].
+///
+/// [`Connection::rpc_from`]: struct.Connection.html#method.rpc_from
+#[derive(Debug)]
+pub struct RpcReply {
+  id: RpcId,
+  reply: XBuff,
+}
+
+impl RpcReply {
+  /// Returns the reply in the message `msg`, if it is one.
+  pub(crate) fn new(msg: &crate::erlang_msg, reply: XBuff) -> Option<Self> {
+    if msg.msgtype == c_long::from(crate::ERL_SEND) && is_rex(&reply) {
+      Some(RpcReply {
+        id: RpcId(msg.to.serial),
+        reply,
+      })
+    } else {
+      None
+    }
+  }
+
+  /// Returns the call this is the reply to.
+  #[inline]
+  pub fn id(&self) -> RpcId {
+    self.id
+  }
+
+  /// Decodes the result of the call, or fails with [`RpcError::BadRpc`] if the call failed.
+  ///
+  /// [`RpcError::BadRpc`]: enum.RpcError.html#variant.BadRpc
+  pub fn decode<R: FromTerm>(&self) -> Result<R, RpcError> {
+    let mut decoder = Decoder::from_x_buff(&self.reply)?;
+    decoder.decode_tuple_header()?;
+    decoder.skip()?;
+
+    let mut atom = [0; MAXATOMLEN_UTF8];
+    let mut reason = decoder.clone();
+    if reason.decode_tuple_header() == Ok(2) && reason.decode_atom(&mut atom) == Ok("badrpc") {
+      let bytes = reason.remaining();
+      reason.skip()?;
+      let mut buf = XBuff::with_version()?;
+      buf.extend_from_slice(&bytes[..bytes.len() - reason.remaining().len()])?;
+      return Err(RpcError::BadRpc(buf));
+    }
+    Ok(R::from_term(&mut decoder)?)
+  }
+}
+
+/// The error type of remote procedure calls.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum RpcError {
+  /// No reply came in time.
+  Timeout,
+  /// The call failed on the other node, which replied `{badrpc, Reason}`. The buffer holds the
+  /// reason, starting with the version byte.
+  BadRpc(XBuff),
+  /// The call could not be sent or its reply could not be received or decoded.
+  Ei(Error),
+}
+
+impl From<Error> for RpcError {
+  fn from(error: Error) -> Self {
+    match error {
+      Error::Timeout => RpcError::Timeout,
+      error => RpcError::Ei(error),
+    }
+  }
+}
+
+impl fmt::Display for RpcError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      RpcError::Timeout => f.write_str("the remote procedure call timed out"),
+      RpcError::BadRpc(reason) => write!(f, "the remote procedure call failed: {:?}", reason),
+      RpcError::Ei(error) => fmt::Display::fmt(error, f),
+    }
+  }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for RpcError {}
+
+impl Connection {
+  /// Calls `module:function` with `args` on the other node and decodes its result.
+  ///
+  /// The arguments are either a tuple, such as `(1, 10)` for `lists:seq(1, 10)` or `()` for none,
+  /// or a list, such as a slice of arguments of the same type.
+  ///
+  /// Replies to other calls made with [`rpc_to`] and received meanwhile are kept for
+  /// [`rpc_from`], unless the `alloc` feature is disabled, in which case they are discarded.
+  /// Other messages are discarded.
+  ///
+  /// [`rpc_to`]: #method.rpc_to
+  /// [`rpc_from`]: #method.rpc_from
+  ///
+  /// # See Also
+  ///
+  /// [The official entry for this function in the Erlang documentation.](http://erlang.org/doc/man/ei_connect.html#ei_rpc)
+  pub fn rpc<A: ToTerm, R: FromTerm>(
+    &mut self,
+    module: &str,
+    function: &str,
+    args: A,
+    timeout: Duration,
+  ) -> Result<R, RpcError> {
+    let id = self.rpc_to(module, function, args)?;
+    let deadline = Deadline::new(timeout);
+    loop {
+      let reply = self.receive_reply(deadline.remaining().ok_or(RpcError::Timeout)?)?;
+      if reply.id() == id {
+        return reply.decode();
+      }
+      #[cfg(feature = "alloc")]
+      self.replies.push_back(reply);
+    }
+  }
+
+  /// Calls `module:function` with `args` on the other node, without waiting for the reply.
+  ///
+  /// The reply is received with [`rpc_from`] and matched to this call with the returned id, so
+  /// that many calls can be outstanding at once. Refer to [`rpc`] for the form of the arguments.
+  ///
+  /// [`rpc_from`]: #method.rpc_from
+  /// [`rpc`]: #method.rpc
+  ///
+  /// # See Also
+  ///
+  /// [The official entry for this function in the Erlang documentation.](http://erlang.org/doc/man/ei_connect.html#ei_rpc_to)
+  pub fn rpc_to<A: ToTerm>(
+    &mut self,
+    module: &str,
+    function: &str,
+    args: A,
+  ) -> Result<RpcId, Error> {
+    let mut module_name = [0; MAXATOMLEN_UTF8];
+    let mut function_name = [0; MAXATOMLEN_UTF8];
+    c_str::copy(module, &mut module_name).map_err(|_| Error::InvalidArgument)?;
+    c_str::copy(function, &mut function_name).map_err(|_| Error::InvalidArgument)?;
+    let args = encode_args(&args)?;
+    let len = c_int::try_from(args.byte_len()).map_err(|_| Error::InvalidArgument)?;
+
+    // rex replies to the pid that made the call, so each call is made from its own pid, which
+    // differs from that of the node by its serial. Serials are 13 bits in older pid encodings.
+    let serial = self.next_call() % 0x1fff + 1;
+    let fd = self.fd();
+    let pid = self.node_mut().pid_mut();
+    let node_serial = mem::replace(&mut pid.serial, serial);
+    let code = unsafe {
+      crate::ei_rpc_to(
+        self.node_mut().as_mut_ptr(),
+        fd,
+        module_name.as_mut_ptr(),
+        function_name.as_mut_ptr(),
+        args.as_bytes().as_ptr() as *const _,
+        len,
+      )
+    };
+    self.node_mut().pid_mut().serial = node_serial;
+    if code < 0 {
+      return Err(last_error());
+    }
+    self.sent();
+    Ok(RpcId(serial))
+  }
+
+  /// Waits for the reply to any call made with [`rpc_to`], or fails with [`RpcError::Timeout`]
+  /// after `timeout`.
+  ///
+  /// The replies kept by [`rpc`] are returned first, in the order they were received. Other
+  /// messages received meanwhile are discarded.
+  ///
+  /// [`rpc_to`]: #method.rpc_to
+  /// [`rpc`]: #method.rpc
+  /// [`RpcError::Timeout`]: enum.RpcError.html#variant.Timeout
+  ///
+  /// # See Also
+  ///
+  /// [The official entry for this function in the Erlang documentation.](http://erlang.org/doc/man/ei_connect.html#ei_rpc_from)
+  pub fn rpc_from(&mut self, timeout: Duration) -> Result<RpcReply, RpcError> {
+    #[cfg(feature = "alloc")]
+    if let Some(reply) = self.replies.pop_front() {
+      return Ok(reply);
+    }
+    self.receive_reply(timeout)
+  }
+
+  /// Waits for the next reply from the other node, ignoring the replies kept by `rpc`.
+  ///
+  /// On Unix, frames are read by the connection rather than by ei, so the reply is received like
+  /// any other message, as `ei_rpc_from` would.
+  #[cfg(all(feature = "std", unix))]
+  fn receive_reply(&mut self, timeout: Duration) -> Result<RpcReply, RpcError> {
+    let deadline = Deadline::new(timeout);
+    loop {
+      let remaining = deadline.remaining().ok_or(RpcError::Timeout)?;
+      match self.receive_timeout(remaining) {
+        Ok(Received::Tick) => continue,
+        Ok(Received::Message { msg, payload }) => {
+          if let Some(reply) = RpcReply::new(&msg, payload) {
+            return Ok(reply);
+          }
+        }
+        Err(Error::Timeout) => return Err(RpcError::Timeout),
+        Err(error) => return Err(error.into()),
+      }
+    }
+  }
+
+  /// Waits for the next reply from the other node, ignoring the replies kept by `rpc`.
+  #[cfg(not(all(feature = "std", unix)))]
+  fn receive_reply(&mut self, timeout: Duration) -> Result<RpcReply, RpcError> {
+    let deadline = Deadline::new(timeout);
+    loop {
+      let remaining = deadline.remaining().ok_or(RpcError::Timeout)?;
+      let ms = c_int::try_from(millis(remaining)).unwrap_or(c_int::MAX);
+      let mut msg: crate::erlang_msg = unsafe { mem::zeroed() };
+      let mut reply = XBuff::new()?;
+      let fd = self.fd();
+      let code = unsafe {
+        crate::ei_rpc_from(
+          self.node_mut().as_mut_ptr(),
+          fd,
+          ms,
+          &mut msg,
+          reply.as_mut_ptr(),
+        )
+      };
+      if code == crate::ERL_TICK || code == crate::ERL_MSG {
+        self.received();
+      }
+      match code {
+        crate::ERL_TICK => continue,
+        crate::ERL_TIMEOUT => return Err(RpcError::Timeout),
+        crate::ERL_MSG => {
+          if let Some(reply) = RpcReply::new(&msg, reply) {
+            return Ok(reply);
+          }
+        }
+        _ => return Err(last_error().into()),
+      }
+    }
+  }
+}
+
+/// Returns `true` if `buf` holds a reply from rex, `{rex, Result}`.
+fn is_rex(buf: &XBuff) -> bool {
+  let mut atom = [0; MAXATOMLEN_UTF8];
+  match Decoder::from_x_buff(buf) {
+    Ok(mut decoder) => {
+      decoder.decode_tuple_header() == Ok(2) && decoder.decode_atom(&mut atom) == Ok("rex")
+    }
+    Err(_) => false,
+  }
+}
+
+/// Encodes `args` as the list of arguments of a call, converting a tuple to a list.
+fn encode_args<A: ToTerm>(args: &A) -> Result<XBuff, Error> {
+  let mut buf = XBuff::new()?;
+  args.to_term(&mut buf.encoder())?;
+
+  let bytes = buf.as_bytes();
+  let (arity, elements) = match bytes.first().copied() {
+    Some(crate::SMALL_TUPLE_EXT) => (u32::from(bytes[1]), &bytes[2..]),
+    Some(crate::LARGE_TUPLE_EXT) => (
+      u32::from_be_bytes([bytes[1], bytes[2], bytes[3], bytes[4]]),
+      &bytes[5..],
+    ),
+    _ => return Ok(buf),
+  };
+  let mut list = XBuff::new()?;
+  if arity > 0 {
+    list.extend_from_slice(&[crate::LIST_EXT])?;
+    list.extend_from_slice(&arity.to_be_bytes())?;
+    list.extend_from_slice(elements)?;
+  }
+  list.extend_from_slice(&[crate::NIL_EXT])?;
+  Ok(list)
+}
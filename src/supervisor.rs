@@ -0,0 +1,609 @@
+//! Connections that reconnect when the other node goes away.
+
+use crate::{
+  connection::Deadline, error::is_lost, CNode, Connection, Error, FromTerm, Received, RpcError,
+  ToTerm, XBuff,
+};
+use core::time::Duration;
+use std::{
+  boxed::Box,
+  collections::VecDeque,
+  string::String,
+  thread,
+  time::{Instant, SystemTime, UNIX_EPOCH},
+  vec::Vec,
+};
+
+/// What a [`SupervisedConnection`] does with the messages sent while it is disconnected.
+///
+/// [`SupervisedConnection`]: struct.SupervisedConnection.html
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum SendPolicy {
+  /// Fail with `Error::Io(ENOTCONN)`.
+  Reject,
+  /// Keep up to that many messages, to be sent in order once reconnected, and fail with
+  /// `Error::Io(ENOBUFS)` beyond. A kept message that then fails to send, other than because the
+  /// connection was lost again, is dropped with a [`ConnectionEvent::Dropped`].
+  ///
+  /// [`ConnectionEvent::Dropped`]: enum.ConnectionEvent.html#variant.Dropped
+  Buffer(usize),
+  /// Reconnect before sending, waiting as long as it takes.
+  Wait,
+}
+
+/// A change in the state of a [`SupervisedConnection`].
+///
+/// [`SupervisedConnection`]: struct.SupervisedConnection.html
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ConnectionEvent {
+  /// The connection was opened, or reopened.
+  Connected,
+  /// The connection was lost because of the given error.
+  Disconnected(Error),
+  /// Connecting failed with `error`, and will be attempted again after `delay`.
+  RetryScheduled {
+    /// The number of consecutive failed attempts.
+    attempt: u32,
+    /// Why the attempt failed.
+    error: Error,
+    /// How long until the next attempt.
+    delay: Duration,
+  },
+  /// A message kept while disconnected could not be sent once reconnected because of the given
+  /// error, which came from the message rather than the connection, and was dropped.
+  Dropped(Error),
+}
+
+/// A connection to another node, reopened with exponential backoff whenever it is lost, such as
+/// when the other node restarts.
+///
+/// The connection is opened by the first operation that needs it. Operations that wait, such as
+/// [`receive`], reconnect as many times as needed, while the others make at most one attempt,
+/// and only once the backoff delay has passed.
+///
+/// [`receive`]: #method.receive
+pub struct SupervisedConnection {
+  config: SupervisedConnectionBuilder,
+  connection: Option<Connection>,
+  pending: VecDeque<Pending>,
+  attempt: u32,
+  retry_at: Option<Instant>,
+  seed: u64,
+}
+
+/// Configures and creates a [`SupervisedConnection`].
+///
+/// [`SupervisedConnection`]: struct.SupervisedConnection.html
+pub struct SupervisedConnectionBuilder {
+  node: CNode,
+  peer: String,
+  connect_timeout: Duration,
+  initial_backoff: Duration,
+  max_backoff: Duration,
+  net_ticktime: Option<Duration>,
+  policy: SendPolicy,
+  names: Vec<String>,
+  on_event: Option<EventHandler>,
+}
+
+type EventHandler = Box<dyn FnMut(&ConnectionEvent) + Send>;
+
+/// A message sent while disconnected.
+enum Pending {
+  Send(Box<crate::erlang_pid>, XBuff),
+  RegSend(String, XBuff),
+}
+
+impl SupervisedConnection {
+  /// Starts building a connection from `node` to the node named `node_name`, of the form
+  /// `alive@host`.
+  pub fn builder(node: &CNode, node_name: &str) -> SupervisedConnectionBuilder {
+    SupervisedConnectionBuilder {
+      node: node.clone(),
+      peer: node_name.into(),
+      connect_timeout: Duration::from_secs(5),
+      initial_backoff: Duration::from_millis(100),
+      max_backoff: Duration::from_secs(30),
+      net_ticktime: None,
+      policy: SendPolicy::Reject,
+      names: Vec::new(),
+      on_event: None,
+    }
+  }
+
+  /// Returns `true` if the connection is currently open.
+  #[inline]
+  pub fn is_connected(&self) -> bool {
+    self.connection.is_some()
+  }
+
+  /// Returns the name of the other node.
+  #[inline]
+  pub fn peer_name(&self) -> &str {
+    &self.config.peer
+  }
+
+  /// Returns the number of messages sent while disconnected and not sent yet.
+  #[inline]
+  pub fn pending(&self) -> usize {
+    self.pending.len()
+  }
+
+  /// Sends the message in `buf` to the process `to`, or handles it according to the
+  /// [`SendPolicy`] if disconnected.
+  ///
+  /// [`SendPolicy`]: enum.SendPolicy.html
+  pub fn send(&mut self, to: &crate::erlang_pid, buf: &XBuff) -> Result<(), Error> {
+    self.deliver(
+      || Ok(Pending::Send(Box::new(to.clone()), copy(buf)?)),
+      |c| c.send(to, buf),
+    )
+  }
+
+  /// Sends the message in `buf` to the process registered as `name` on the other node, or handles
+  /// it according to the [`SendPolicy`] if disconnected.
+  ///
+  /// [`SendPolicy`]: enum.SendPolicy.html
+  pub fn reg_send(&mut self, name: &str, buf: &XBuff) -> Result<(), Error> {
+    self.deliver(
+      || Ok(Pending::RegSend(name.into(), copy(buf)?)),
+      |c| c.reg_send(name, buf),
+    )
+  }
+
+  /// Waits for the next message or tick from the other node, reconnecting as many times as
+  /// needed.
+  pub fn receive(&mut self) -> Result<Received, Error> {
+    self.receive_within(None)
+  }
+
+  /// Waits for the next message or tick from the other node, reconnecting as many times as
+  /// needed, or fails with [`Error::Timeout`] after `timeout`.
+  ///
+  /// [`Error::Timeout`]: enum.Error.html#variant.Timeout
+  pub fn receive_timeout(&mut self, timeout: Duration) -> Result<Received, Error> {
+    self.receive_within(Some(Deadline::new(timeout)))
+  }
+
+  fn receive_within(&mut self, deadline: Option<Deadline>) -> Result<Received, Error> {
+    loop {
+      let connection = self.wait_connected(deadline.as_ref())?;
+      let received = match &deadline {
+        Some(deadline) => connection.receive_timeout(deadline.remaining().ok_or(Error::Timeout)?),
+        None => connection.receive(),
+      };
+      match received {
+        Err(error) if is_lost(error) => self.disconnect(error),
+        received => return received,
+      }
+    }
+  }
+
+  /// Calls `module:function` with `args` on the other node and decodes its result, like
+  /// [`Connection::rpc`], reconnecting first if needed.
+  ///
+  /// The call is not made again if the connection is lost while waiting for its reply.
+  ///
+  /// [`Connection::rpc`]: struct.Connection.html#method.rpc
+  pub fn rpc<A: ToTerm, R: FromTerm>(
+    &mut self,
+    module: &str,
+    function: &str,
+    args: A,
+    timeout: Duration,
+  ) -> Result<R, RpcError> {
+    let deadline = Deadline::new(timeout);
+    let connection = self.wait_connected(Some(&deadline))?;
+    let remaining = deadline.remaining().ok_or(RpcError::Timeout)?;
+    match connection.rpc(module, function, args, remaining) {
+      Err(RpcError::Ei(error)) if is_lost(error) => {
+        self.disconnect(error);
+        Err(RpcError::Ei(error))
+      }
+      result => result,
+    }
+  }
+
+  /// Runs `f` with the connection, reconnecting first if needed, and notes if `f` fails because
+  /// the connection was lost.
+  pub fn with_connection<T, F>(&mut self, f: F) -> Result<T, Error>
+  where
+    F: FnOnce(&mut Connection) -> Result<T, Error>,
+  {
+    let connection = self.wait_connected(None)?;
+    match f(connection) {
+      Err(error) if is_lost(error) => {
+        self.disconnect(error);
+        Err(error)
+      }
+      result => result,
+    }
+  }
+
+  fn deliver<P, F>(&mut self, pending: P, send: F) -> Result<(), Error>
+  where
+    P: FnOnce() -> Result<Pending, Error>,
+    F: FnOnce(&mut Connection) -> Result<(), Error>,
+  {
+    if self.config.policy == SendPolicy::Wait {
+      self.wait_connected(None)?;
+    } else {
+      self.poll_connect();
+    }
+    let connection = match &mut self.connection {
+      Some(connection) => connection,
+      None => {
+        return match self.config.policy {
+          SendPolicy::Buffer(capacity) if self.pending.len() < capacity => {
+            self.pending.push_back(pending()?);
+            Ok(())
+          }
+          SendPolicy::Buffer(_) => Err(Error::Io(libc::ENOBUFS)),
+          _ => Err(Error::Io(libc::ENOTCONN)),
+        }
+      }
+    };
+    // A message that failed midway may have been partly sent, so it is not buffered.
+    match send(connection) {
+      Err(error) if is_lost(error) => {
+        self.disconnect(error);
+        Err(error)
+      }
+      result => result,
+    }
+  }
+
+  /// Reconnects as many times as needed, sleeping between attempts, or fails with
+  /// [`Error::Timeout`] once `deadline` has passed.
+  ///
+  /// [`Error::Timeout`]: enum.Error.html#variant.Timeout
+  fn wait_connected(&mut self, deadline: Option<&Deadline>) -> Result<&mut Connection, Error> {
+    while !self.poll_connect() {
+      let delay = self.retry_at.map_or(Duration::ZERO, |at| {
+        at.saturating_duration_since(Instant::now())
+      });
+      if let Some(deadline) = deadline {
+        let remaining = deadline.remaining().ok_or(Error::Timeout)?;
+        if remaining < delay {
+          thread::sleep(remaining);
+          return Err(Error::Timeout);
+        }
+      }
+      thread::sleep(delay);
+    }
+    self.connection.as_mut().ok_or(Error::Io(libc::ENOTCONN))
+  }
+
+  /// Makes one attempt to connect if disconnected and the backoff delay has passed, then sends
+  /// the buffered messages, and returns `true` if connected.
+  fn poll_connect(&mut self) -> bool {
+    if self.connection.is_some() {
+      return true;
+    }
+    if self.retry_at.is_some_and(|at| Instant::now() < at) {
+      return false;
+    }
+    match self.config.open() {
+      Ok(connection) => {
+        self.connection = Some(connection);
+        self.attempt = 0;
+        self.retry_at = None;
+        self.config.emit(&ConnectionEvent::Connected);
+        self.flush();
+      }
+      Err(error) => {
+        self.attempt = self.attempt.saturating_add(1);
+        let delay = self.backoff();
+        self.retry_at = Some(Instant::now() + delay);
+        self.config.emit(&ConnectionEvent::RetryScheduled {
+          attempt: self.attempt,
+          error,
+          delay,
+        });
+      }
+    }
+    self.connection.is_some()
+  }
+
+  /// Sends the buffered messages, in order, until the connection is lost.
+  fn flush(&mut self) {
+    while let (Some(connection), Some(pending)) = (&mut self.connection, self.pending.front()) {
+      let sent = match pending {
+        Pending::Send(to, buf) => connection.send(to, buf),
+        Pending::RegSend(name, buf) => connection.reg_send(name, buf),
+      };
+      match sent {
+        Err(error) if is_lost(error) => self.disconnect(error),
+        // Other errors come from the message itself, so retrying would fail again.
+        Err(error) => {
+          self.pending.pop_front();
+          self.config.emit(&ConnectionEvent::Dropped(error));
+        }
+        Ok(()) => {
+          self.pending.pop_front();
+        }
+      }
+    }
+  }
+
+  fn disconnect(&mut self, error: Error) {
+    self.connection = None;
+    self.config.emit(&ConnectionEvent::Disconnected(error));
+  }
+
+  /// Returns the delay before the next attempt: the initial backoff, doubled with each failed
+  /// attempt up to the maximum, of which a random part up to half is taken off so that nodes do
+  /// not all reconnect at once.
+  fn backoff(&mut self) -> Duration {
+    let doublings = self.attempt.saturating_sub(1).min(31);
+    let delay = self
+      .config
+      .initial_backoff
+      .checked_mul(1 << doublings)
+      .map_or(self.config.max_backoff, |delay| {
+        delay.min(self.config.max_backoff)
+      });
+
+    // xorshift64
+    self.seed ^= self.seed << 13;
+    self.seed ^= self.seed >> 7;
+    self.seed ^= self.seed << 17;
+    let jitter = (self.seed >> 11) as f64 / (1u64 << 53) as f64;
+    delay.mul_f64(1.0 - jitter / 2.0)
+  }
+}
+
+impl SupervisedConnectionBuilder {
+  /// Sets how long each attempt to connect may take, 5 seconds by default.
+  pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+    self.connect_timeout = timeout;
+    self
+  }
+
+  /// Sets the delay after the first failed attempt to connect, 100 milliseconds by default, and
+  /// the maximum delay it doubles up to, 30 seconds by default.
+  pub fn backoff(mut self, initial: Duration, max: Duration) -> Self {
+    self.initial_backoff = initial;
+    self.max_backoff = max.max(initial);
+    self
+  }
+
+  /// Sets the tick time of the connection, as with [`Connection::set_net_ticktime`], so that a
+  /// silent node is detected and reconnected to.
+  ///
+  /// [`Connection::set_net_ticktime`]: struct.Connection.html#method.set_net_ticktime
+  pub fn net_ticktime(mut self, ticktime: Duration) -> Self {
+    self.net_ticktime = Some(ticktime);
+    self
+  }
+
+  /// Sets what happens to the messages sent while disconnected, [`SendPolicy::Reject`] by default.
+  ///
+  /// [`SendPolicy::Reject`]: enum.SendPolicy.html#variant.Reject
+  pub fn send_policy(mut self, policy: SendPolicy) -> Self {
+    self.policy = policy;
+    self
+  }
+
+  /// Registers the pid of the node under `name` with `global` each time the connection is opened,
+  /// since the registration is dropped when the connection is lost.
+  ///
+  /// An attempt to connect fails if the registration fails, but not if the name is taken.
+  pub fn register_global(mut self, name: &str) -> Self {
+    self.names.push(name.into());
+    self
+  }
+
+  /// Calls `f` with each change in the state of the connection.
+  pub fn on_event<F>(mut self, f: F) -> Self
+  where
+    F: FnMut(&ConnectionEvent) + Send + 'static,
+  {
+    self.on_event = Some(Box::new(f));
+    self
+  }
+
+  /// Creates the connection, which is opened by the first operation that needs it.
+  pub fn build(self) -> SupervisedConnection {
+    let now = SystemTime::now()
+      .duration_since(UNIX_EPOCH)
+      .unwrap_or_default();
+    let seed = ((now.as_nanos() as u64) ^ (u64::from(self.node.pid().num) << 32)) | 1;
+    SupervisedConnection {
+      config: self,
+      connection: None,
+      pending: VecDeque::new(),
+      attempt: 0,
+      retry_at: None,
+      seed,
+    }
+  }
+
+  fn open(&self) -> Result<Connection, Error> {
+    let mut connection = self
+      .node
+      .connect_timeout(&self.peer, self.connect_timeout)?;
+    connection.set_net_ticktime(self.net_ticktime);
+    for name in &self.names {
+      let pid = self.node.pid();
+      let registered: Result<crate::Term, RpcError> = connection.rpc(
+        "global",
+        "register_name",
+        GlobalName(name, pid),
+        self.connect_timeout,
+      );
+      match registered {
+        Ok(_) => {}
+        Err(RpcError::Ei(error)) => return Err(error),
+        Err(RpcError::Timeout) => return Err(Error::Timeout),
+        Err(_) => return Err(Error::InvalidArgument),
+      }
+    }
+    Ok(connection)
+  }
+
+  fn emit(&mut self, event: &ConnectionEvent) {
+    if let Some(on_event) = &mut self.on_event {
+      on_event(event);
+    }
+  }
+}
+
+/// The arguments of `global:register_name/2`.
+struct GlobalName<'a>(&'a str, &'a crate::erlang_pid);
+
+impl ToTerm for GlobalName<'_> {
+  fn to_term(&self, encoder: &mut crate::Encoder) -> Result<(), Error> {
+    encoder.tuple(2, |e| {
+      e.atom(self.0)?.pid(self.1)?;
+      Ok(())
+    })?;
+    Ok(())
+  }
+}
+
+fn copy(buf: &XBuff) -> Result<XBuff, Error> {
+  let mut copy = XBuff::new()?;
+  copy.append(buf)?;
+  Ok(copy)
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+  use super::*;
+  use alloc::vec;
+  use std::{
+    io::Read,
+    os::unix::{io::IntoRawFd, net::UnixStream},
+    sync::{Arc, Mutex},
+  };
+
+  fn node() -> CNode {
+    CNode::builder("test", "cookie")
+      .host_name("host")
+      .build()
+      .unwrap()
+  }
+
+  /// Returns a connection that does not attempt to connect for an hour.
+  fn disconnected(builder: SupervisedConnectionBuilder) -> SupervisedConnection {
+    let mut supervised = builder.build();
+    supervised.retry_at = Some(Instant::now() + Duration::from_secs(3600));
+    supervised
+  }
+
+  /// Opens the connection of `supervised`, returning the socket on its other end.
+  fn reconnect(supervised: &mut SupervisedConnection) -> UnixStream {
+    let (ours, theirs) = UnixStream::pair().unwrap();
+    let node = supervised.config.node.clone();
+    supervised.connection = Some(unsafe { Connection::from_raw_fd(node, ours.into_raw_fd()) });
+    supervised.flush();
+    theirs
+  }
+
+  fn atom(name: &str) -> XBuff {
+    let mut buf = XBuff::with_version().unwrap();
+    buf.encoder().atom(name).unwrap();
+    buf
+  }
+
+  /// Reads the next frame sent to `peer` and returns whether it ends with `payload`.
+  fn sent(peer: &mut UnixStream, payload: &XBuff) -> bool {
+    let mut len = [0; 4];
+    peer.read_exact(&mut len).unwrap();
+    let mut frame = vec![0; u32::from_be_bytes(len) as usize];
+    peer.read_exact(&mut frame).unwrap();
+    frame.ends_with(payload.as_bytes())
+  }
+
+  #[test]
+  fn backoff_doubles_up_to_the_maximum() {
+    let builder = SupervisedConnection::builder(&node(), "peer@host")
+      .backoff(Duration::from_millis(100), Duration::from_secs(1));
+    let mut supervised = builder.build();
+    for (attempt, max) in [
+      (1, 100),
+      (2, 200),
+      (3, 400),
+      (4, 800),
+      (5, 1000),
+      (64, 1000),
+    ] {
+      supervised.attempt = attempt;
+      let max = Duration::from_millis(max);
+      let delay = supervised.backoff();
+      assert!(max / 2 <= delay && delay <= max, "{}: {:?}", attempt, delay);
+    }
+  }
+
+  #[test]
+  fn backoff_is_jittered() {
+    let mut supervised = SupervisedConnection::builder(&node(), "peer@host")
+      .backoff(Duration::from_secs(1), Duration::from_secs(1))
+      .build();
+    supervised.attempt = 1;
+    let delays: Vec<_> = (0..100).map(|_| supervised.backoff()).collect();
+    assert!(delays
+      .iter()
+      .all(|delay| Duration::from_millis(500) <= *delay && *delay <= Duration::from_secs(1)));
+    assert!(delays
+      .iter()
+      .any(|delay| *delay < Duration::from_millis(750)));
+    assert!(delays
+      .iter()
+      .any(|delay| *delay > Duration::from_millis(750)));
+  }
+
+  #[test]
+  fn reject_fails_while_disconnected() {
+    let mut supervised = disconnected(SupervisedConnection::builder(&node(), "peer@host"));
+    assert_eq!(
+      supervised.reg_send("name", &atom("one")),
+      Err(Error::Io(libc::ENOTCONN))
+    );
+    assert_eq!(supervised.pending(), 0);
+  }
+
+  #[test]
+  fn buffer_sends_messages_in_order_once_reconnected() {
+    let node = node();
+    let to = node.pid().clone();
+    let builder =
+      SupervisedConnection::builder(&node, "peer@host").send_policy(SendPolicy::Buffer(2));
+    let mut supervised = disconnected(builder);
+    supervised.reg_send("name", &atom("one")).unwrap();
+    supervised.send(&to, &atom("two")).unwrap();
+    assert_eq!(
+      supervised.reg_send("name", &atom("three")),
+      Err(Error::Io(libc::ENOBUFS))
+    );
+    assert_eq!(supervised.pending(), 2);
+
+    let mut peer = reconnect(&mut supervised);
+    assert_eq!(supervised.pending(), 0);
+    assert!(sent(&mut peer, &atom("one")));
+    assert!(sent(&mut peer, &atom("two")));
+  }
+
+  #[test]
+  fn buffer_drops_messages_that_cannot_be_sent() {
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let sink = events.clone();
+    let builder = SupervisedConnection::builder(&node(), "peer@host")
+      .send_policy(SendPolicy::Buffer(2))
+      .on_event(move |event| sink.lock().unwrap().push(*event));
+    let mut supervised = disconnected(builder);
+    let long_name = "x".repeat(1024);
+    supervised.reg_send(&long_name, &atom("one")).unwrap();
+    supervised.reg_send("name", &atom("two")).unwrap();
+
+    let mut peer = reconnect(&mut supervised);
+    assert_eq!(supervised.pending(), 0);
+    assert!(sent(&mut peer, &atom("two")));
+    assert_eq!(
+      *events.lock().unwrap(),
+      [ConnectionEvent::Dropped(Error::InvalidArgument)]
+    );
+  }
+}
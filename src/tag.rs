@@ -0,0 +1,159 @@
+//! Tags of the external term format.
+
+use core::{convert::TryFrom, fmt};
+
+/// The tag that starts every term in the external term format.
+///
+/// The tags can be converted to and from the loose `*_EXT` constants at the root of this crate.
+///
+/// # Tags Reported By `ei_get_type`
+///
+/// [`ei_get_type`] does not report the tag byte verbatim. It normalizes some of them before
+/// writing them to `type_`, hence the following variants will never come out of it.
+///
+/// * [`SmallAtom`], [`AtomUtf8`] and [`SmallAtomUtf8`] are reported as [`Atom`].
+/// * [`NewFloat`] is reported as [`Float`].
+/// * [`NewPid`] is reported as [`Pid`].
+/// * [`NewPort`] is reported as [`Port`].
+/// * [`NewerReference`] is reported as [`NewReference`].
+///
+/// All the other tags are reported as is. In particular, integers are not normalized and may be
+/// any of [`SmallInteger`], [`Integer`], [`SmallBig`] or [`LargeBig`], which is why
+/// [`is_integer`] should be preferred over comparing with a single variant.
+///
+/// [`ei_get_type`]: fn.ei_get_type.html
+/// [`SmallAtom`]: #variant.SmallAtom
+/// [`AtomUtf8`]: #variant.AtomUtf8
+/// [`SmallAtomUtf8`]: #variant.SmallAtomUtf8
+/// [`Atom`]: #variant.Atom
+/// [`NewFloat`]: #variant.NewFloat
+/// [`Float`]: #variant.Float
+/// [`NewPid`]: #variant.NewPid
+/// [`Pid`]: #variant.Pid
+/// [`NewPort`]: #variant.NewPort
+/// [`Port`]: #variant.Port
+/// [`NewerReference`]: #variant.NewerReference
+/// [`NewReference`]: #variant.NewReference
+/// [`SmallInteger`]: #variant.SmallInteger
+/// [`Integer`]: #variant.Integer
+/// [`SmallBig`]: #variant.SmallBig
+/// [`LargeBig`]: #variant.LargeBig
+/// [`is_integer`]: #method.is_integer
+#[repr(u8)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum ExtTag {
+  SmallInteger = crate::SMALL_INTEGER_EXT,
+  Integer = crate::INTEGER_EXT,
+  Float = crate::FLOAT_EXT,
+  Atom = crate::ATOM_EXT,
+  SmallAtom = crate::SMALL_ATOM_EXT,
+  Reference = crate::REFERENCE_EXT,
+  NewReference = crate::NEW_REFERENCE_EXT,
+  NewerReference = crate::NEWER_REFERENCE_EXT,
+  Port = crate::PORT_EXT,
+  NewPort = crate::NEW_PORT_EXT,
+  NewFloat = crate::NEW_FLOAT_EXT,
+  Pid = crate::PID_EXT,
+  NewPid = crate::NEW_PID_EXT,
+  SmallTuple = crate::SMALL_TUPLE_EXT,
+  LargeTuple = crate::LARGE_TUPLE_EXT,
+  Nil = crate::NIL_EXT,
+  String = crate::STRING_EXT,
+  List = crate::LIST_EXT,
+  Binary = crate::BINARY_EXT,
+  BitBinary = crate::BIT_BINARY_EXT,
+  SmallBig = crate::SMALL_BIG_EXT,
+  LargeBig = crate::LARGE_BIG_EXT,
+  NewFun = crate::NEW_FUN_EXT,
+  Export = crate::EXPORT_EXT,
+  Map = crate::MAP_EXT,
+  Fun = crate::FUN_EXT,
+  AtomUtf8 = crate::ATOM_UTF8_EXT,
+  SmallAtomUtf8 = crate::SMALL_ATOM_UTF8_EXT,
+}
+
+impl ExtTag {
+  /// Returns `true` if the tag starts an atom, in any of its encodings.
+  #[inline]
+  pub fn is_atom(self) -> bool {
+    matches!(
+      self,
+      ExtTag::Atom | ExtTag::SmallAtom | ExtTag::AtomUtf8 | ExtTag::SmallAtomUtf8
+    )
+  }
+
+  /// Returns `true` if the tag starts an integer, whether it fits in a machine word or not.
+  #[inline]
+  pub fn is_integer(self) -> bool {
+    matches!(
+      self,
+      ExtTag::SmallInteger | ExtTag::Integer | ExtTag::SmallBig | ExtTag::LargeBig
+    )
+  }
+
+  /// Returns `true` if the tag starts a list.
+  ///
+  /// This includes the empty list, as well as lists of bytes that are encoded as strings.
+  #[inline]
+  pub fn is_list(self) -> bool {
+    matches!(self, ExtTag::Nil | ExtTag::String | ExtTag::List)
+  }
+}
+
+impl From<ExtTag> for u8 {
+  #[inline]
+  fn from(tag: ExtTag) -> u8 {
+    tag as u8
+  }
+}
+
+impl TryFrom<u8> for ExtTag {
+  type Error = InvalidExtTag;
+
+  fn try_from(byte: u8) -> Result<Self, Self::Error> {
+    let tag = match byte {
+      crate::SMALL_INTEGER_EXT => ExtTag::SmallInteger,
+      crate::INTEGER_EXT => ExtTag::Integer,
+      crate::FLOAT_EXT => ExtTag::Float,
+      crate::ATOM_EXT => ExtTag::Atom,
+      crate::SMALL_ATOM_EXT => ExtTag::SmallAtom,
+      crate::REFERENCE_EXT => ExtTag::Reference,
+      crate::NEW_REFERENCE_EXT => ExtTag::NewReference,
+      crate::NEWER_REFERENCE_EXT => ExtTag::NewerReference,
+      crate::PORT_EXT => ExtTag::Port,
+      crate::NEW_PORT_EXT => ExtTag::NewPort,
+      crate::NEW_FLOAT_EXT => ExtTag::NewFloat,
+      crate::PID_EXT => ExtTag::Pid,
+      crate::NEW_PID_EXT => ExtTag::NewPid,
+      crate::SMALL_TUPLE_EXT => ExtTag::SmallTuple,
+      crate::LARGE_TUPLE_EXT => ExtTag::LargeTuple,
+      crate::NIL_EXT => ExtTag::Nil,
+      crate::STRING_EXT => ExtTag::String,
+      crate::LIST_EXT => ExtTag::List,
+      crate::BINARY_EXT => ExtTag::Binary,
+      crate::BIT_BINARY_EXT => ExtTag::BitBinary,
+      crate::SMALL_BIG_EXT => ExtTag::SmallBig,
+      crate::LARGE_BIG_EXT => ExtTag::LargeBig,
+      crate::NEW_FUN_EXT => ExtTag::NewFun,
+      crate::EXPORT_EXT => ExtTag::Export,
+      crate::MAP_EXT => ExtTag::Map,
+      crate::FUN_EXT => ExtTag::Fun,
+      crate::ATOM_UTF8_EXT => ExtTag::AtomUtf8,
+      crate::SMALL_ATOM_UTF8_EXT => ExtTag::SmallAtomUtf8,
+      _ => return Err(InvalidExtTag(byte)),
+    };
+    Ok(tag)
+  }
+}
+
+/// The error returned when converting a byte that is not the tag of a term to an [`ExtTag`].
+///
+/// [`ExtTag`]: enum.ExtTag.html
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct InvalidExtTag(pub u8);
+
+impl fmt::Display for InvalidExtTag {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "invalid external term format tag {}", self.0)
+  }
+}
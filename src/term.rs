@@ -0,0 +1,469 @@
+//! An owned representation of any term.
+
+use crate::{c_str, Decoder, Encoder, Error, ExtTag, XBuff};
+use alloc::{borrow::ToOwned, boxed::Box, string::String, vec::Vec};
+use core::{convert::TryFrom, mem};
+
+/// An owned Erlang term.
+///
+/// Encoding a term and decoding it back produces the same term, provided the term is in canonical
+/// form. The constructors of this crate always produce canonical terms, and the variants document
+/// the invariants that must be upheld when building terms by hand.
+///
+/// The other way around is not guaranteed: decoding bytes and encoding the term back can produce
+/// other bytes for the same term, such as UTF-8 atoms for Latin-1 ones, or the shortest encoding
+/// of integers.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Term {
+  /// An integer that fits in 64 bits.
+  Integer(i64),
+  /// An integer that does not fit in 64 bits.
+  BigInt(BigInt),
+  /// A float. It must be finite, since Erlang has no infinities nor NaN.
+  Float(f64),
+  /// An atom, by its UTF-8 name.
+  Atom(String),
+  /// A binary, that is a bitstring whose length is a multiple of 8.
+  Binary(Vec<u8>),
+  /// A bitstring whose length is not a multiple of 8.
+  BitString(BitString),
+  /// A list of bytes. It must have between 1 and 65535 bytes, otherwise it is encoded as a
+  /// [`List`].
+  ///
+  /// [`List`]: #variant.List
+  String(Vec<u8>),
+  /// A proper list. The empty list is `List(vec![])`.
+  List(Vec<Term>),
+  /// An improper list. It must have at least one element and its tail must not be a list, that is
+  /// a [`List`], including the empty list, a [`String`] or an `ImproperList`, since `[a | [b]]` is
+  /// the list `[a, b]`.
+  ///
+  /// [`List`]: #variant.List
+  /// [`String`]: #variant.String
+  ImproperList(Vec<Term>, Box<Term>),
+  /// A tuple.
+  Tuple(Vec<Term>),
+  /// A map, with its entries in the order in which they are encoded.
+  Map(Vec<(Term, Term)>),
+  /// A process identifier.
+  Pid(Pid),
+  /// A port identifier.
+  Port(Port),
+  /// A reference.
+  Ref(Reference),
+  /// An external fun, as created by `fun Module:Function/Arity`.
+  Export {
+    module: String,
+    function: String,
+    arity: u8,
+  },
+  /// A local fun, kept in its encoded form, starting with its tag.
+  Closure(Vec<u8>),
+}
+
+/// The magnitude and sign of an integer that does not fit in 64 bits.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct BigInt {
+  negative: bool,
+  magnitude: Vec<u8>,
+}
+
+impl BigInt {
+  /// Returns `true` if the integer is negative.
+  #[inline]
+  pub fn is_negative(&self) -> bool {
+    self.negative
+  }
+
+  /// Returns the absolute value of the integer, as little endian bytes.
+  #[inline]
+  pub fn magnitude(&self) -> &[u8] {
+    &self.magnitude
+  }
+}
+
+/// A bitstring whose length is not a multiple of 8.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct BitString {
+  /// The bytes of the bitstring. It must not be empty.
+  pub bytes: Vec<u8>,
+  /// The number of significant bits in the last byte, the most significant first, from 1 to 8.
+  pub bits: u8,
+}
+
+/// A process identifier.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Pid {
+  pub node: String,
+  pub num: u32,
+  pub serial: u32,
+  pub creation: u32,
+}
+
+impl Pid {
+  /// Returns the process identifier in the form expected by ei.
+  pub fn to_raw(&self) -> Result<crate::erlang_pid, Error> {
+    let mut raw: crate::erlang_pid = unsafe { mem::zeroed() };
+    c_str::copy(&self.node, &mut raw.node)?;
+    raw.num = self.num;
+    raw.serial = self.serial;
+    raw.creation = self.creation;
+    Ok(raw)
+  }
+
+  /// Converts a process identifier filled by ei.
+  pub fn from_raw(raw: &crate::erlang_pid) -> Result<Self, Error> {
+    Ok(Pid {
+      node: c_str::to_str(&raw.node)?.to_owned(),
+      num: raw.num,
+      serial: raw.serial,
+      creation: raw.creation,
+    })
+  }
+}
+
+/// A port identifier.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Port {
+  pub node: String,
+  pub id: u32,
+  pub creation: u32,
+}
+
+impl Port {
+  /// Returns the port identifier in the form expected by ei.
+  pub fn to_raw(&self) -> Result<crate::erlang_port, Error> {
+    let mut raw: crate::erlang_port = unsafe { mem::zeroed() };
+    c_str::copy(&self.node, &mut raw.node)?;
+    raw.id = self.id;
+    raw.creation = self.creation;
+    Ok(raw)
+  }
+
+  /// Converts a port identifier filled by ei.
+  pub fn from_raw(raw: &crate::erlang_port) -> Result<Self, Error> {
+    Ok(Port {
+      node: c_str::to_str(&raw.node)?.to_owned(),
+      id: raw.id,
+      creation: raw.creation,
+    })
+  }
+}
+
+/// A reference.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Reference {
+  pub node: String,
+  /// The identifiers of the reference. There must be between 1 and 3 of them.
+  pub ids: Vec<u32>,
+  pub creation: u32,
+}
+
+impl Reference {
+  /// Returns the reference in the form expected by ei.
+  pub fn to_raw(&self) -> Result<crate::erlang_ref, Error> {
+    let mut raw: crate::erlang_ref = unsafe { mem::zeroed() };
+    c_str::copy(&self.node, &mut raw.node)?;
+    if self.ids.is_empty() || self.ids.len() > raw.n.len() {
+      return Err(Error::Encode);
+    }
+    raw.len = self.ids.len() as libc::c_int;
+    raw.n[..self.ids.len()].copy_from_slice(&self.ids);
+    raw.creation = self.creation;
+    Ok(raw)
+  }
+
+  /// Converts a reference filled by ei.
+  pub fn from_raw(raw: &crate::erlang_ref) -> Result<Self, Error> {
+    let len = usize::try_from(raw.len).map_err(|_| Error::Decode)?;
+    Ok(Reference {
+      node: c_str::to_str(&raw.node)?.to_owned(),
+      ids: raw.n.get(..len).ok_or(Error::Decode)?.to_vec(),
+      creation: raw.creation,
+    })
+  }
+}
+
+impl Term {
+  /// Returns the integer of the given sign and magnitude, in little endian bytes, in canonical
+  /// form.
+  pub fn from_big(negative: bool, magnitude: &[u8]) -> Term {
+    let len = magnitude.iter().rposition(|&b| b != 0).map_or(0, |i| i + 1);
+    let magnitude = &magnitude[..len];
+    if len <= 8 {
+      let mut bytes = [0; 8];
+      bytes[..len].copy_from_slice(magnitude);
+      let value = u64::from_le_bytes(bytes);
+      if !negative && value <= i64::MAX as u64 {
+        return Term::Integer(value as i64);
+      } else if negative && value <= i64::MIN.unsigned_abs() {
+        return Term::Integer((value as i64).wrapping_neg());
+      }
+    }
+    Term::BigInt(BigInt {
+      negative,
+      magnitude: magnitude.to_vec(),
+    })
+  }
+
+  /// Decodes a single term from `bytes`, optionally preceded by the version magic byte.
+  pub fn from_bytes(bytes: &[u8]) -> Result<Term, Error> {
+    let mut decoder = Decoder::new(bytes)?;
+    let term = Term::decode(&mut decoder)?;
+    if !decoder.is_empty() {
+      return Err(Error::Decode);
+    }
+    Ok(term)
+  }
+
+  /// Decodes the single term in `buf`.
+  #[inline]
+  pub fn from_x_buff(buf: &XBuff) -> Result<Term, Error> {
+    Term::from_bytes(buf.as_bytes())
+  }
+
+  /// Encodes the term in a new buffer, preceded by the version magic byte.
+  pub fn to_x_buff(&self) -> Result<XBuff, Error> {
+    let mut buf = XBuff::with_version()?;
+    self.encode(&mut buf.encoder())?;
+    Ok(buf)
+  }
+
+  /// Decodes the next term of `decoder`.
+  ///
+  /// Fails with [`Error::Decode`] if lists, tuples and maps are nested more than `MAX_DEPTH` (256)
+  /// deep, since decoding, encoding and dropping terms recurse once per level.
+  ///
+  /// [`Error::Decode`]: enum.Error.html#variant.Decode
+  #[inline]
+  pub fn decode(decoder: &mut Decoder) -> Result<Term, Error> {
+    Term::decode_nested(decoder, 0)
+  }
+
+  /// Decodes the next term of `decoder`, which is nested `depth` deep in the term being decoded.
+  fn decode_nested(decoder: &mut Decoder, depth: usize) -> Result<Term, Error> {
+    if depth > MAX_DEPTH {
+      return Err(Error::Decode);
+    }
+    let term = match decoder.peek_type()?.0 {
+      ExtTag::List => {
+        let arity = decoder.decode_list_header()?;
+        let mut elements = Vec::with_capacity(arity);
+        for _ in 0..arity {
+          elements.push(Term::decode_nested(decoder, depth + 1)?);
+        }
+        match Term::decode_nested(decoder, depth + 1)? {
+          Term::List(ref tail) if tail.is_empty() => Term::List(elements),
+          tail => Term::ImproperList(elements, Box::new(tail)),
+        }
+      }
+      ExtTag::SmallTuple | ExtTag::LargeTuple => {
+        let arity = decoder.decode_tuple_header()?;
+        let mut elements = Vec::with_capacity(arity);
+        for _ in 0..arity {
+          elements.push(Term::decode_nested(decoder, depth + 1)?);
+        }
+        Term::Tuple(elements)
+      }
+      ExtTag::Map => {
+        let arity = decoder.decode_map_header()?;
+        let mut entries = Vec::with_capacity(arity);
+        for _ in 0..arity {
+          let key = Term::decode_nested(decoder, depth + 1)?;
+          let value = Term::decode_nested(decoder, depth + 1)?;
+          entries.push((key, value));
+        }
+        Term::Map(entries)
+      }
+      _ => decode_leaf(decoder)?,
+    };
+    Ok(term)
+  }
+
+  /// Encodes the term with `encoder`.
+  ///
+  /// Bignums, bitstrings and funs have no safe counterpart in ei and are encoded directly.
+  pub fn encode(&self, encoder: &mut Encoder) -> Result<(), Error> {
+    match self {
+      Term::List(elements) => {
+        encoder.list(elements, |e, element| element.encode(e))?;
+      }
+      Term::ImproperList(elements, tail) => {
+        encoder.improper_list(elements, |e, element| element.encode(e), |e| tail.encode(e))?;
+      }
+      Term::Tuple(elements) => {
+        encoder.tuple(elements.len(), |e| {
+          for element in elements {
+            element.encode(e)?;
+          }
+          Ok(())
+        })?;
+      }
+      Term::Map(entries) => {
+        encoder.map(entries, |e, (key, value)| {
+          key.encode(e)?;
+          value.encode(e)
+        })?;
+      }
+      _ => encode_leaf(self, encoder)?,
+    }
+    Ok(())
+  }
+}
+
+/// How deep lists, tuples and maps may be nested in decoded terms.
+const MAX_DEPTH: usize = 256;
+
+/// Decodes an atom, with its buffer outside of the frames of `Term::decode_nested`.
+#[inline(never)]
+fn decode_atom(decoder: &mut Decoder) -> Result<String, Error> {
+  let mut buf = [0; crate::MAXATOMLEN_UTF8];
+  Ok(decoder.decode_atom(&mut buf)?.to_owned())
+}
+
+/// Decodes a term that is not a list, a tuple or a map, outside of the frames of
+/// `Term::decode_nested`, since the structures of ei are large.
+#[inline(never)]
+fn decode_leaf(decoder: &mut Decoder) -> Result<Term, Error> {
+  let (tag, size) = decoder.peek_type()?;
+  let term = match tag {
+    ExtTag::SmallInteger | ExtTag::Integer => Term::Integer(decoder.decode_longlong()?),
+    ExtTag::SmallBig | ExtTag::LargeBig => {
+      let bytes = decoder.remaining();
+      let (negative, magnitude) = if tag == ExtTag::SmallBig {
+        (bytes[2] != 0, &bytes[3..3 + bytes[1] as usize])
+      } else {
+        let n = u32::from_be_bytes([bytes[1], bytes[2], bytes[3], bytes[4]]) as usize;
+        (bytes[5] != 0, &bytes[6..6 + n])
+      };
+      decoder.skip()?;
+      Term::from_big(negative, magnitude)
+    }
+    ExtTag::Float | ExtTag::NewFloat => Term::Float(decoder.decode_double()?),
+    ExtTag::Atom | ExtTag::SmallAtom | ExtTag::AtomUtf8 | ExtTag::SmallAtomUtf8 => {
+      Term::Atom(decode_atom(decoder)?)
+    }
+    ExtTag::Binary => Term::Binary(decoder.decode_binary()?.to_vec()),
+    ExtTag::BitBinary => {
+      let bytes = decoder.remaining();
+      let len = u32::from_be_bytes([bytes[1], bytes[2], bytes[3], bytes[4]]) as usize;
+      let bits = bytes[5];
+      let bytes = bytes[6..6 + len].to_vec();
+      decoder.skip()?;
+      Term::BitString(BitString { bytes, bits })
+    }
+    ExtTag::String => {
+      let mut buf = alloc::vec![0; size + 1];
+      let len = decoder.decode_string(&mut buf)?.len();
+      buf.truncate(len);
+      Term::String(buf)
+    }
+    ExtTag::Nil => {
+      decoder.decode_list_header()?;
+      Term::List(Vec::new())
+    }
+    // Decoded by `Term::decode_nested`.
+    ExtTag::List | ExtTag::SmallTuple | ExtTag::LargeTuple | ExtTag::Map => {
+      return Err(Error::Decode)
+    }
+    ExtTag::Pid | ExtTag::NewPid => Term::Pid(Pid::from_raw(&decoder.decode_pid()?)?),
+    ExtTag::Port | ExtTag::NewPort => Term::Port(Port::from_raw(&decoder.decode_port()?)?),
+    ExtTag::Reference | ExtTag::NewReference | ExtTag::NewerReference => {
+      Term::Ref(Reference::from_raw(&decoder.decode_ref()?)?)
+    }
+    ExtTag::Export => {
+      let bytes = decoder.remaining();
+      let end = crate::decode::term_end(bytes, 0)?;
+      let mut elements = Decoder::new(&bytes[1..end])?;
+      let module = decode_atom(&mut elements)?;
+      let function = decode_atom(&mut elements)?;
+      let arity = u8::try_from(elements.decode_long()?).map_err(|_| Error::Decode)?;
+      decoder.skip()?;
+      Term::Export {
+        module,
+        function,
+        arity,
+      }
+    }
+    ExtTag::NewFun | ExtTag::Fun => {
+      let bytes = decoder.remaining();
+      let end = crate::decode::term_end(bytes, 0)?;
+      decoder.skip()?;
+      Term::Closure(bytes[..end].to_vec())
+    }
+  };
+  Ok(term)
+}
+
+/// Encodes a term that is not a list, a tuple or a map, outside of the frames of `Term::encode`,
+/// since the structures of ei are large.
+#[inline(never)]
+fn encode_leaf(term: &Term, encoder: &mut Encoder) -> Result<(), Error> {
+  match term {
+    Term::Integer(value) => {
+      encoder.longlong(*value)?;
+    }
+    Term::BigInt(big) => {
+      let n = big.magnitude.len();
+      let mut header = [0; 6];
+      let header = if n <= u8::MAX as usize {
+        header[..3].copy_from_slice(&[crate::SMALL_BIG_EXT, n as u8, big.negative as u8]);
+        &header[..3]
+      } else {
+        let n = u32::try_from(n).map_err(|_| Error::Encode)?;
+        header[0] = crate::LARGE_BIG_EXT;
+        header[1..5].copy_from_slice(&n.to_be_bytes());
+        header[5] = big.negative as u8;
+        &header[..]
+      };
+      encoder.raw(&[header, &big.magnitude])?;
+    }
+    Term::Float(value) => {
+      encoder.double(*value)?;
+    }
+    Term::Atom(name) => {
+      encoder.atom(name)?;
+    }
+    Term::Binary(bytes) => {
+      encoder.binary(bytes)?;
+    }
+    Term::BitString(bits) => {
+      let len = u32::try_from(bits.bytes.len()).map_err(|_| Error::Encode)?;
+      let mut header = [crate::BIT_BINARY_EXT, 0, 0, 0, 0, bits.bits];
+      header[1..5].copy_from_slice(&len.to_be_bytes());
+      encoder.raw(&[&header, &bits.bytes])?;
+    }
+    Term::String(bytes) => {
+      encoder.string(bytes)?;
+    }
+    Term::Pid(pid) => {
+      encoder.pid(&pid.to_raw()?)?;
+    }
+    Term::Port(port) => {
+      encoder.port(&port.to_raw()?)?;
+    }
+    Term::Ref(reference) => {
+      encoder.reference(&reference.to_raw()?)?;
+    }
+    Term::Export {
+      module,
+      function,
+      arity,
+    } => {
+      encoder.compound(&[crate::EXPORT_EXT], 3, |e| {
+        e.atom(module)?
+          .atom(function)?
+          .long(*arity as libc::c_long)?;
+        Ok(())
+      })?;
+    }
+    Term::Closure(bytes) => {
+      encoder.raw(&[bytes])?;
+    }
+    // Encoded by `Term::encode`.
+    Term::List(_) | Term::ImproperList(..) | Term::Tuple(_) | Term::Map(_) => {
+      return Err(Error::Encode)
+    }
+  }
+  Ok(())
+}
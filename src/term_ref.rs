@@ -0,0 +1,265 @@
+//! A borrowed view of a term, decoded lazily.
+
+use crate::{decode::term_end, Decoder, Error, ExtTag, XBuff};
+use core::{convert::TryFrom, iter::FusedIterator, str};
+use libc::{c_int, c_long, c_longlong, c_ulonglong};
+
+/// A view of a single term in a buffer.
+///
+/// Creating a view only validates the structure of the term. Its contents are decoded when they
+/// are asked for, and binaries and atoms are borrowed from the buffer instead of being copied.
+///
+/// Views are compared and hashed by their encoding, so the same term encoded in two ways, such as
+/// an atom in Latin-1 and in UTF-8, makes two views that are not equal. Compare the decoded
+/// [`Term`]s instead to compare values.
+///
+/// [`Term`]: enum.Term.html
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct TermRef<'a> {
+  bytes: &'a [u8],
+}
+
+impl<'a> TermRef<'a> {
+  /// Creates a view of the single term in `bytes`, optionally preceded by the version magic byte.
+  pub fn new(bytes: &'a [u8]) -> Result<Self, Error> {
+    let bytes = match bytes.first() {
+      Some(&crate::VERSION_MAGIC) => &bytes[1..],
+      _ => bytes,
+    };
+    if c_int::try_from(bytes.len()).is_err() || term_end(bytes, 0)? != bytes.len() {
+      return Err(Error::Decode);
+    }
+    Ok(TermRef { bytes })
+  }
+
+  /// Creates a view of the single term in `buf`.
+  #[inline]
+  pub fn from_x_buff(buf: &'a XBuff) -> Result<Self, Error> {
+    Self::new(buf.as_bytes())
+  }
+
+  /// Returns the encoded term, without the version magic byte.
+  #[inline]
+  pub fn as_bytes(&self) -> &'a [u8] {
+    self.bytes
+  }
+
+  /// Returns a decoder positioned at the start of the term.
+  #[inline]
+  pub fn decoder(&self) -> Decoder<'a> {
+    Decoder::new_unchecked(self.bytes)
+  }
+
+  /// Returns the tag of the term, as reported by [`ei_get_type`].
+  ///
+  /// [`ei_get_type`]: fn.ei_get_type.html
+  #[inline]
+  pub fn tag(&self) -> ExtTag {
+    // The term was validated, so its tag is known.
+    self.decoder().peek_type().map(|(tag, _)| tag).unwrap()
+  }
+
+  /// Returns the value of an integer that fits in a `c_long`.
+  pub fn as_long(&self) -> Result<c_long, Error> {
+    self.decoder().decode_long()
+  }
+
+  /// Returns the value of an integer that fits in a `c_longlong`.
+  pub fn as_longlong(&self) -> Result<c_longlong, Error> {
+    self.decoder().decode_longlong()
+  }
+
+  /// Returns the value of a non-negative integer that fits in a `c_ulonglong`.
+  pub fn as_ulonglong(&self) -> Result<c_ulonglong, Error> {
+    self.decoder().decode_ulonglong()
+  }
+
+  /// Returns the value of a float.
+  pub fn as_double(&self) -> Result<f64, Error> {
+    self.decoder().decode_double()
+  }
+
+  /// Returns the value of the atoms `true` or `false`.
+  pub fn as_boolean(&self) -> Result<bool, Error> {
+    self.decoder().decode_boolean()
+  }
+
+  /// Returns the name of an atom, borrowed from the buffer.
+  ///
+  /// Atoms encoded in Latin-1 can only be borrowed if they are ASCII. Use
+  /// [`Decoder::decode_atom`] to convert the others.
+  ///
+  /// [`Decoder::decode_atom`]: struct.Decoder.html#method.decode_atom
+  pub fn as_atom(&self) -> Result<&'a str, Error> {
+    let b = self.bytes;
+    let (utf8, name) = match b[0] {
+      crate::ATOM_EXT => (false, &b[3..]),
+      crate::ATOM_UTF8_EXT => (true, &b[3..]),
+      crate::SMALL_ATOM_EXT => (false, &b[2..]),
+      crate::SMALL_ATOM_UTF8_EXT => (true, &b[2..]),
+      _ => return Err(Error::Decode),
+    };
+    if !utf8 && !name.is_ascii() {
+      return Err(Error::Decode);
+    }
+    str::from_utf8(name).map_err(|_| Error::Decode)
+  }
+
+  /// Returns the bytes of a binary, borrowed from the buffer.
+  pub fn as_binary(&self) -> Result<&'a [u8], Error> {
+    self.decoder().decode_binary()
+  }
+
+  /// Returns the bytes of a list of bytes encoded as a string, borrowed from the buffer.
+  ///
+  /// Lists of bytes encoded as regular lists are not supported.
+  pub fn as_string(&self) -> Result<&'a [u8], Error> {
+    match self.bytes[0] {
+      crate::STRING_EXT => Ok(&self.bytes[3..]),
+      crate::NIL_EXT => Ok(&[]),
+      _ => Err(Error::Decode),
+    }
+  }
+
+  /// Returns the process identifier.
+  pub fn as_pid(&self) -> Result<crate::erlang_pid, Error> {
+    self.decoder().decode_pid()
+  }
+
+  /// Returns the port identifier.
+  pub fn as_port(&self) -> Result<crate::erlang_port, Error> {
+    self.decoder().decode_port()
+  }
+
+  /// Returns the reference.
+  pub fn as_reference(&self) -> Result<crate::erlang_ref, Error> {
+    self.decoder().decode_ref()
+  }
+
+  /// Returns an iterator over the elements of a tuple.
+  pub fn tuple(&self) -> Result<Elements<'a>, Error> {
+    let mut decoder = self.decoder();
+    let remaining = decoder.decode_tuple_header()?;
+    Ok(Elements {
+      decoder,
+      remaining,
+      has_tail: false,
+    })
+  }
+
+  /// Returns an iterator over the elements of a list, proper or not.
+  ///
+  /// Lists of bytes encoded as strings are not supported, and must be read with [`as_string`].
+  ///
+  /// [`as_string`]: #method.as_string
+  pub fn list(&self) -> Result<Elements<'a>, Error> {
+    let mut decoder = self.decoder();
+    let remaining = decoder.decode_list_header()?;
+    Ok(Elements {
+      decoder,
+      remaining,
+      has_tail: remaining > 0,
+    })
+  }
+
+  /// Returns an iterator over the entries of a map.
+  pub fn map(&self) -> Result<Entries<'a>, Error> {
+    let mut decoder = self.decoder();
+    let arity = decoder.decode_map_header()?;
+    Ok(Entries(Elements {
+      decoder,
+      remaining: 2 * arity,
+      has_tail: false,
+    }))
+  }
+
+  /// Decodes the term to an owned [`Term`].
+  ///
+  /// [`Term`]: enum.Term.html
+  #[cfg(feature = "alloc")]
+  pub fn to_term(&self) -> Result<crate::Term, Error> {
+    crate::Term::decode(&mut self.decoder())
+  }
+}
+
+/// An iterator over the elements of a tuple or a list, created by [`TermRef::tuple`] and
+/// [`TermRef::list`].
+///
+/// [`TermRef::tuple`]: struct.TermRef.html#method.tuple
+/// [`TermRef::list`]: struct.TermRef.html#method.list
+#[derive(Debug, Clone)]
+pub struct Elements<'a> {
+  decoder: Decoder<'a>,
+  remaining: usize,
+  has_tail: bool,
+}
+
+impl<'a> Elements<'a> {
+  /// Skips the remaining elements and returns the tail of a list, which is the empty list unless
+  /// the list is improper.
+  ///
+  /// Returns `None` for tuples and for the empty list.
+  pub fn tail(mut self) -> Option<TermRef<'a>> {
+    for _ in &mut self {}
+    if self.has_tail {
+      self.next_term()
+    } else {
+      None
+    }
+  }
+
+  fn next_term(&mut self) -> Option<TermRef<'a>> {
+    let rest = self.decoder.remaining();
+    self.decoder.skip().ok()?;
+    let len = rest.len() - self.decoder.remaining().len();
+    Some(TermRef {
+      bytes: &rest[..len],
+    })
+  }
+}
+
+impl<'a> Iterator for Elements<'a> {
+  type Item = TermRef<'a>;
+
+  fn next(&mut self) -> Option<TermRef<'a>> {
+    if self.remaining == 0 {
+      return None;
+    }
+
+    self.remaining -= 1;
+    self.next_term()
+  }
+
+  #[inline]
+  fn size_hint(&self) -> (usize, Option<usize>) {
+    (self.remaining, Some(self.remaining))
+  }
+}
+
+impl ExactSizeIterator for Elements<'_> {}
+
+impl FusedIterator for Elements<'_> {}
+
+/// An iterator over the entries of a map, created by [`TermRef::map`].
+///
+/// [`TermRef::map`]: struct.TermRef.html#method.map
+#[derive(Debug, Clone)]
+pub struct Entries<'a>(Elements<'a>);
+
+impl<'a> Iterator for Entries<'a> {
+  type Item = (TermRef<'a>, TermRef<'a>);
+
+  fn next(&mut self) -> Option<Self::Item> {
+    Some((self.0.next()?, self.0.next()?))
+  }
+
+  #[inline]
+  fn size_hint(&self) -> (usize, Option<usize>) {
+    let len = self.0.remaining / 2;
+    (len, Some(len))
+  }
+}
+
+impl ExactSizeIterator for Entries<'_> {}
+
+impl FusedIterator for Entries<'_> {}
@@ -0,0 +1,588 @@
+use super::{SerdeConfig, SerdeError, StructMapping};
+use crate::{Decoder, ExtTag};
+use alloc::string::String;
+use core::str;
+use serde::de::{self, DeserializeSeed, IntoDeserializer, Visitor};
+
+/// A serde deserializer that reads terms with a [`Decoder`].
+///
+/// [`Decoder`]: ../struct.Decoder.html
+pub struct Deserializer<'de> {
+  decoder: Decoder<'de>,
+  config: SerdeConfig,
+  remaining_depth: usize,
+}
+
+/// The number of nested lists, tuples and maps that are deserialized, as serde_json allows, before
+/// failing rather than overflowing the stack.
+const MAX_DEPTH: usize = 128;
+
+impl<'de> Deserializer<'de> {
+  /// Creates a deserializer that reads terms from `decoder`.
+  #[inline]
+  pub fn new(decoder: Decoder<'de>, config: SerdeConfig) -> Self {
+    Deserializer {
+      decoder,
+      config,
+      remaining_depth: MAX_DEPTH,
+    }
+  }
+
+  /// Checks that all the terms were deserialized.
+  pub fn end(&self) -> Result<(), SerdeError> {
+    if self.decoder.is_empty() {
+      Ok(())
+    } else {
+      Err(de::Error::custom("trailing terms"))
+    }
+  }
+
+  fn tag(&self) -> Result<ExtTag, SerdeError> {
+    Ok(self.decoder.peek_type()?.0)
+  }
+
+  fn atom(&mut self) -> Result<String, SerdeError> {
+    let mut buf = [0; crate::MAXATOMLEN_UTF8];
+    Ok(String::from(self.decoder.decode_atom(&mut buf)?))
+  }
+
+  /// Returns `true` if the next term is the atom `name`, without decoding it.
+  fn peek_atom(&self, name: &str) -> bool {
+    let mut buf = [0; crate::MAXATOMLEN_UTF8];
+    self.decoder.clone().decode_atom(&mut buf) == Ok(name)
+  }
+
+  /// Decodes the bytes of a string encoded as a list of bytes, borrowing them from the input.
+  fn string_bytes(&mut self) -> Result<&'de [u8], SerdeError> {
+    let bytes = self.decoder.remaining();
+    let (_, size) = self.decoder.peek_type()?;
+    self.decoder.skip()?;
+    Ok(&bytes[3..3 + size])
+  }
+
+  fn tuple_header(&mut self, arity: usize) -> Result<(), SerdeError> {
+    let actual = self.decoder.decode_tuple_header()?;
+    if actual != arity {
+      return Err(de::Error::invalid_length(
+        actual,
+        &"a tuple of the expected arity",
+      ));
+    }
+    Ok(())
+  }
+
+  /// Runs `f` on the terms nested in a list, tuple or map, failing past `MAX_DEPTH` levels.
+  fn nested<T>(
+    &mut self,
+    f: impl FnOnce(&mut Self) -> Result<T, SerdeError>,
+  ) -> Result<T, SerdeError> {
+    self.remaining_depth = match self.remaining_depth.checked_sub(1) {
+      Some(depth) => depth,
+      None => return Err(de::Error::custom("recursion limit exceeded")),
+    };
+    let result = f(self);
+    self.remaining_depth += 1;
+    result
+  }
+
+  fn seq<V>(&mut self, visitor: V, remaining: usize, list: bool) -> Result<V::Value, SerdeError>
+  where
+    V: Visitor<'de>,
+  {
+    self.nested(|de| de.seq_elements(visitor, remaining, list))
+  }
+
+  fn seq_elements<V>(
+    &mut self,
+    visitor: V,
+    remaining: usize,
+    list: bool,
+  ) -> Result<V::Value, SerdeError>
+  where
+    V: Visitor<'de>,
+  {
+    let mut access = Elements {
+      de: self,
+      remaining,
+    };
+    let value = visitor.visit_seq(&mut access)?;
+    if access.remaining != 0 {
+      return Err(de::Error::invalid_length(remaining, &"fewer elements"));
+    }
+    // The elements of a non-empty list are followed by its tail, which must be the empty list.
+    if list && remaining != 0 && self.decoder.decode_list_header()? != 0 {
+      return Err(de::Error::custom("improper list"));
+    }
+    Ok(value)
+  }
+
+  fn list<V>(&mut self, visitor: V) -> Result<V::Value, SerdeError>
+  where
+    V: Visitor<'de>,
+  {
+    match self.tag()? {
+      ExtTag::String => {
+        let bytes = self.string_bytes()?;
+        visitor.visit_seq(de::value::SeqDeserializer::new(bytes.iter().copied()))
+      }
+      _ => {
+        let arity = self.decoder.decode_list_header()?;
+        self.seq(visitor, arity, true)
+      }
+    }
+  }
+
+  fn map<V>(&mut self, visitor: V) -> Result<V::Value, SerdeError>
+  where
+    V: Visitor<'de>,
+  {
+    let arity = self.decoder.decode_map_header()?;
+    self.nested(|de| {
+      let mut access = Elements {
+        de,
+        remaining: 2 * arity,
+      };
+      let value = visitor.visit_map(&mut access)?;
+      if access.remaining != 0 {
+        return Err(de::Error::invalid_length(arity, &"fewer entries"));
+      }
+      Ok(value)
+    })
+  }
+}
+
+impl<'de> de::Deserializer<'de> for &mut Deserializer<'de> {
+  type Error = SerdeError;
+
+  fn deserialize_any<V>(self, visitor: V) -> Result<V::Value, SerdeError>
+  where
+    V: Visitor<'de>,
+  {
+    match self.tag()? {
+      tag if tag.is_integer() => {
+        let mut decoder = self.decoder.clone();
+        if let Ok(value) = decoder.decode_longlong() {
+          self.decoder = decoder;
+          visitor.visit_i64(value)
+        } else {
+          visitor.visit_u64(self.decoder.decode_ulonglong()?)
+        }
+      }
+      ExtTag::Float | ExtTag::NewFloat => visitor.visit_f64(self.decoder.decode_double()?),
+      tag if tag.is_atom() => match self.atom()?.as_str() {
+        "true" => visitor.visit_bool(true),
+        "false" => visitor.visit_bool(false),
+        name => visitor.visit_str(name),
+      },
+      ExtTag::Binary => {
+        let bytes = self.decoder.decode_binary()?;
+        match str::from_utf8(bytes) {
+          Ok(s) => visitor.visit_borrowed_str(s),
+          Err(_) => visitor.visit_borrowed_bytes(bytes),
+        }
+      }
+      tag if tag.is_list() => self.list(visitor),
+      ExtTag::SmallTuple | ExtTag::LargeTuple => {
+        let arity = self.decoder.decode_tuple_header()?;
+        self.seq(visitor, arity, false)
+      }
+      ExtTag::Map => self.map(visitor),
+      _ => Err(de::Error::custom("unsupported term")),
+    }
+  }
+
+  fn deserialize_bool<V>(self, visitor: V) -> Result<V::Value, SerdeError>
+  where
+    V: Visitor<'de>,
+  {
+    visitor.visit_bool(self.decoder.decode_boolean()?)
+  }
+
+  fn deserialize_i8<V>(self, visitor: V) -> Result<V::Value, SerdeError>
+  where
+    V: Visitor<'de>,
+  {
+    self.deserialize_i64(visitor)
+  }
+
+  fn deserialize_i16<V>(self, visitor: V) -> Result<V::Value, SerdeError>
+  where
+    V: Visitor<'de>,
+  {
+    self.deserialize_i64(visitor)
+  }
+
+  fn deserialize_i32<V>(self, visitor: V) -> Result<V::Value, SerdeError>
+  where
+    V: Visitor<'de>,
+  {
+    self.deserialize_i64(visitor)
+  }
+
+  fn deserialize_i64<V>(self, visitor: V) -> Result<V::Value, SerdeError>
+  where
+    V: Visitor<'de>,
+  {
+    visitor.visit_i64(self.decoder.decode_longlong()?)
+  }
+
+  fn deserialize_u8<V>(self, visitor: V) -> Result<V::Value, SerdeError>
+  where
+    V: Visitor<'de>,
+  {
+    self.deserialize_u64(visitor)
+  }
+
+  fn deserialize_u16<V>(self, visitor: V) -> Result<V::Value, SerdeError>
+  where
+    V: Visitor<'de>,
+  {
+    self.deserialize_u64(visitor)
+  }
+
+  fn deserialize_u32<V>(self, visitor: V) -> Result<V::Value, SerdeError>
+  where
+    V: Visitor<'de>,
+  {
+    self.deserialize_u64(visitor)
+  }
+
+  fn deserialize_u64<V>(self, visitor: V) -> Result<V::Value, SerdeError>
+  where
+    V: Visitor<'de>,
+  {
+    visitor.visit_u64(self.decoder.decode_ulonglong()?)
+  }
+
+  fn deserialize_f32<V>(self, visitor: V) -> Result<V::Value, SerdeError>
+  where
+    V: Visitor<'de>,
+  {
+    self.deserialize_f64(visitor)
+  }
+
+  fn deserialize_f64<V>(self, visitor: V) -> Result<V::Value, SerdeError>
+  where
+    V: Visitor<'de>,
+  {
+    visitor.visit_f64(self.decoder.decode_double()?)
+  }
+
+  fn deserialize_char<V>(self, visitor: V) -> Result<V::Value, SerdeError>
+  where
+    V: Visitor<'de>,
+  {
+    let code = self.decoder.decode_ulonglong()?;
+    match core::char::from_u32(code as u32).filter(|_| code <= u64::from(u32::MAX)) {
+      Some(c) => visitor.visit_char(c),
+      None => Err(de::Error::custom("invalid character")),
+    }
+  }
+
+  fn deserialize_str<V>(self, visitor: V) -> Result<V::Value, SerdeError>
+  where
+    V: Visitor<'de>,
+  {
+    let bytes = match self.tag()? {
+      tag if tag.is_atom() => return visitor.visit_str(&self.atom()?),
+      ExtTag::String => self.string_bytes()?,
+      _ => self.decoder.decode_binary()?,
+    };
+    match str::from_utf8(bytes) {
+      Ok(s) => visitor.visit_borrowed_str(s),
+      Err(_) => Err(de::Error::invalid_value(
+        de::Unexpected::Bytes(bytes),
+        &"a UTF-8 string",
+      )),
+    }
+  }
+
+  fn deserialize_string<V>(self, visitor: V) -> Result<V::Value, SerdeError>
+  where
+    V: Visitor<'de>,
+  {
+    self.deserialize_str(visitor)
+  }
+
+  fn deserialize_bytes<V>(self, visitor: V) -> Result<V::Value, SerdeError>
+  where
+    V: Visitor<'de>,
+  {
+    match self.tag()? {
+      ExtTag::String => visitor.visit_borrowed_bytes(self.string_bytes()?),
+      _ => visitor.visit_borrowed_bytes(self.decoder.decode_binary()?),
+    }
+  }
+
+  fn deserialize_byte_buf<V>(self, visitor: V) -> Result<V::Value, SerdeError>
+  where
+    V: Visitor<'de>,
+  {
+    self.deserialize_bytes(visitor)
+  }
+
+  fn deserialize_option<V>(self, visitor: V) -> Result<V::Value, SerdeError>
+  where
+    V: Visitor<'de>,
+  {
+    if self.peek_atom(self.config.none.atom()) {
+      self.decoder.skip()?;
+      visitor.visit_none()
+    } else {
+      visitor.visit_some(self)
+    }
+  }
+
+  fn deserialize_unit<V>(self, visitor: V) -> Result<V::Value, SerdeError>
+  where
+    V: Visitor<'de>,
+  {
+    self.tuple_header(0)?;
+    visitor.visit_unit()
+  }
+
+  fn deserialize_unit_struct<V>(
+    self,
+    name: &'static str,
+    visitor: V,
+  ) -> Result<V::Value, SerdeError>
+  where
+    V: Visitor<'de>,
+  {
+    if self.atom()? != name {
+      return Err(de::Error::custom("unexpected atom"));
+    }
+    visitor.visit_unit()
+  }
+
+  fn deserialize_newtype_struct<V>(
+    self,
+    _name: &'static str,
+    visitor: V,
+  ) -> Result<V::Value, SerdeError>
+  where
+    V: Visitor<'de>,
+  {
+    visitor.visit_newtype_struct(self)
+  }
+
+  fn deserialize_seq<V>(self, visitor: V) -> Result<V::Value, SerdeError>
+  where
+    V: Visitor<'de>,
+  {
+    self.list(visitor)
+  }
+
+  fn deserialize_tuple<V>(self, len: usize, visitor: V) -> Result<V::Value, SerdeError>
+  where
+    V: Visitor<'de>,
+  {
+    self.tuple_header(len)?;
+    self.seq(visitor, len, false)
+  }
+
+  fn deserialize_tuple_struct<V>(
+    self,
+    _name: &'static str,
+    len: usize,
+    visitor: V,
+  ) -> Result<V::Value, SerdeError>
+  where
+    V: Visitor<'de>,
+  {
+    self.deserialize_tuple(len, visitor)
+  }
+
+  fn deserialize_map<V>(self, visitor: V) -> Result<V::Value, SerdeError>
+  where
+    V: Visitor<'de>,
+  {
+    self.map(visitor)
+  }
+
+  fn deserialize_struct<V>(
+    self,
+    name: &'static str,
+    fields: &'static [&'static str],
+    visitor: V,
+  ) -> Result<V::Value, SerdeError>
+  where
+    V: Visitor<'de>,
+  {
+    match self.config.structs {
+      StructMapping::Map => self.map(visitor),
+      StructMapping::Tuple => self.deserialize_tuple(fields.len(), visitor),
+      StructMapping::Record => {
+        self.tuple_header(fields.len() + 1)?;
+        if self.atom()? != name {
+          return Err(de::Error::custom("unexpected record"));
+        }
+        self.seq(visitor, fields.len(), false)
+      }
+    }
+  }
+
+  fn deserialize_enum<V>(
+    self,
+    _name: &'static str,
+    _variants: &'static [&'static str],
+    visitor: V,
+  ) -> Result<V::Value, SerdeError>
+  where
+    V: Visitor<'de>,
+  {
+    match self.tag()? {
+      tag if tag.is_atom() => visitor.visit_enum(self.atom()?.into_deserializer()),
+      _ => {
+        let arity = self.decoder.decode_tuple_header()?;
+        if arity == 0 {
+          return Err(de::Error::custom("empty tuple as variant"));
+        }
+        self.nested(|de| visitor.visit_enum(Enum { de, arity }))
+      }
+    }
+  }
+
+  fn deserialize_identifier<V>(self, visitor: V) -> Result<V::Value, SerdeError>
+  where
+    V: Visitor<'de>,
+  {
+    self.deserialize_str(visitor)
+  }
+
+  fn deserialize_ignored_any<V>(self, visitor: V) -> Result<V::Value, SerdeError>
+  where
+    V: Visitor<'de>,
+  {
+    self.decoder.skip()?;
+    visitor.visit_unit()
+  }
+}
+
+/// Accesses the elements of lists and tuples, or the entries of maps.
+struct Elements<'b, 'de> {
+  de: &'b mut Deserializer<'de>,
+  remaining: usize,
+}
+
+impl<'de> de::SeqAccess<'de> for Elements<'_, 'de> {
+  type Error = SerdeError;
+
+  fn next_element_seed<T>(&mut self, seed: T) -> Result<Option<T::Value>, SerdeError>
+  where
+    T: DeserializeSeed<'de>,
+  {
+    self.remaining = match self.remaining.checked_sub(1) {
+      Some(remaining) => remaining,
+      None => return Ok(None),
+    };
+    seed.deserialize(&mut *self.de).map(Some)
+  }
+
+  fn size_hint(&self) -> Option<usize> {
+    Some(self.remaining)
+  }
+}
+
+impl<'de> de::MapAccess<'de> for Elements<'_, 'de> {
+  type Error = SerdeError;
+
+  fn next_key_seed<K>(&mut self, seed: K) -> Result<Option<K::Value>, SerdeError>
+  where
+    K: DeserializeSeed<'de>,
+  {
+    de::SeqAccess::next_element_seed(self, seed)
+  }
+
+  fn next_value_seed<V>(&mut self, seed: V) -> Result<V::Value, SerdeError>
+  where
+    V: DeserializeSeed<'de>,
+  {
+    // A visitor may ask for a value without a key, which would read past the map.
+    self.remaining = self
+      .remaining
+      .checked_sub(1)
+      .ok_or_else(|| <SerdeError as de::Error>::custom("value without a key"))?;
+    seed.deserialize(&mut *self.de)
+  }
+
+  fn size_hint(&self) -> Option<usize> {
+    Some(self.remaining / 2)
+  }
+}
+
+/// Accesses a variant encoded as a tagged tuple.
+struct Enum<'b, 'de> {
+  de: &'b mut Deserializer<'de>,
+  arity: usize,
+}
+
+impl<'b, 'de> de::EnumAccess<'de> for Enum<'b, 'de> {
+  type Error = SerdeError;
+  type Variant = Self;
+
+  fn variant_seed<V>(self, seed: V) -> Result<(V::Value, Self), SerdeError>
+  where
+    V: DeserializeSeed<'de>,
+  {
+    let variant = seed.deserialize(&mut *self.de)?;
+    Ok((variant, self))
+  }
+}
+
+impl<'de> de::VariantAccess<'de> for Enum<'_, 'de> {
+  type Error = SerdeError;
+
+  fn unit_variant(self) -> Result<(), SerdeError> {
+    if self.arity != 1 {
+      return Err(de::Error::invalid_length(self.arity - 1, &"a unit variant"));
+    }
+    Ok(())
+  }
+
+  fn newtype_variant_seed<T>(self, seed: T) -> Result<T::Value, SerdeError>
+  where
+    T: DeserializeSeed<'de>,
+  {
+    if self.arity != 2 {
+      return Err(de::Error::invalid_length(
+        self.arity - 1,
+        &"a newtype variant",
+      ));
+    }
+    seed.deserialize(self.de)
+  }
+
+  fn tuple_variant<V>(self, len: usize, visitor: V) -> Result<V::Value, SerdeError>
+  where
+    V: Visitor<'de>,
+  {
+    if self.arity != len + 1 {
+      return Err(de::Error::invalid_length(
+        self.arity - 1,
+        &"a tuple variant",
+      ));
+    }
+    self.de.seq(visitor, len, false)
+  }
+
+  fn struct_variant<V>(
+    self,
+    fields: &'static [&'static str],
+    visitor: V,
+  ) -> Result<V::Value, SerdeError>
+  where
+    V: Visitor<'de>,
+  {
+    match self.de.config.structs {
+      StructMapping::Map if self.arity == 2 => self.de.map(visitor),
+      StructMapping::Tuple | StructMapping::Record if self.arity == fields.len() + 1 => {
+        self.de.seq(visitor, fields.len(), false)
+      }
+      _ => Err(de::Error::invalid_length(
+        self.arity - 1,
+        &"a struct variant",
+      )),
+    }
+  }
+}
@@ -0,0 +1,145 @@
+//! Support for serde, mapping Rust data to and from the external term format.
+//!
+//! The mapping of the serde data model is as follows.
+//!
+//! * Booleans are the atoms `true` and `false`.
+//! * Integers and floats are integers and floats, and characters are integers.
+//! * Strings and byte arrays are binaries.
+//! * `None` is an atom chosen by [`OptionMapping`], and `Some(value)` is just `value`.
+//! * The unit is the empty tuple, and unit structs are atoms named after the struct.
+//! * Newtype structs are their content.
+//! * Sequences are proper lists, tuples and tuple structs are tuples, and maps are maps.
+//! * Structs are mapped according to [`StructMapping`].
+//! * Unit variants are atoms, and the other variants are tuples tagged with the name of the
+//!   variant, such as `{variant, Value}` or `{variant, A, B}`. Struct variants are mapped like
+//!   structs, except that records are tagged with the name of the variant and maps are wrapped in
+//!   `{variant, #{...}}`.
+//!
+//! [`OptionMapping`]: enum.OptionMapping.html
+//! [`StructMapping`]: enum.StructMapping.html
+
+mod de;
+mod ser;
+
+use crate::{Decoder, XBuff};
+use alloc::string::{String, ToString};
+use core::fmt;
+
+pub use self::{de::Deserializer, ser::Serializer};
+
+/// How structs are represented as terms.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum StructMapping {
+  /// A map from the names of the fields, as atoms, to their values.
+  Map,
+  /// A tuple of the values of the fields.
+  Tuple,
+  /// A record, that is a tuple of the values of the fields tagged with the name of the struct as
+  /// an atom. The name can be changed with `#[serde(rename = "...")]`.
+  Record,
+}
+
+/// How `None` is represented as a term.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum OptionMapping {
+  /// The atom `undefined`, as is conventional in Erlang.
+  Undefined,
+  /// The atom `nil`, as is conventional in Elixir.
+  Nil,
+}
+
+impl OptionMapping {
+  fn atom(self) -> &'static str {
+    match self {
+      OptionMapping::Undefined => "undefined",
+      OptionMapping::Nil => "nil",
+    }
+  }
+}
+
+/// The configuration of the [`Serializer`] and [`Deserializer`].
+///
+/// The default configuration maps structs to maps and `None` to `undefined`.
+///
+/// [`Serializer`]: struct.Serializer.html
+/// [`Deserializer`]: struct.Deserializer.html
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct SerdeConfig {
+  pub structs: StructMapping,
+  pub none: OptionMapping,
+}
+
+impl Default for SerdeConfig {
+  fn default() -> Self {
+    SerdeConfig {
+      structs: StructMapping::Map,
+      none: OptionMapping::Undefined,
+    }
+  }
+}
+
+impl SerdeConfig {
+  /// Serializes `value` in a new buffer, preceded by the version magic byte.
+  pub fn to_x_buff<T>(&self, value: &T) -> Result<XBuff, SerdeError>
+  where
+    T: serde::Serialize + ?Sized,
+  {
+    let mut buf = XBuff::with_version()?;
+    value.serialize(&mut Serializer::new(&mut buf, *self))?;
+    Ok(buf)
+  }
+
+  /// Deserializes a value from the single term in `bytes`, optionally preceded by the version
+  /// magic byte.
+  pub fn from_bytes<'de, T>(&self, bytes: &'de [u8]) -> Result<T, SerdeError>
+  where
+    T: serde::Deserialize<'de>,
+  {
+    let mut deserializer = Deserializer::new(Decoder::new(bytes)?, *self);
+    let value = T::deserialize(&mut deserializer)?;
+    deserializer.end()?;
+    Ok(value)
+  }
+}
+
+/// The error type of the [`Serializer`] and [`Deserializer`].
+///
+/// [`Serializer`]: struct.Serializer.html
+/// [`Deserializer`]: struct.Deserializer.html
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SerdeError {
+  /// ei failed to encode or decode a term.
+  Ei(crate::Error),
+  /// The data could not be mapped to or from a term.
+  Message(String),
+}
+
+impl From<crate::Error> for SerdeError {
+  #[inline]
+  fn from(error: crate::Error) -> Self {
+    SerdeError::Ei(error)
+  }
+}
+
+impl fmt::Display for SerdeError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      SerdeError::Ei(error) => error.fmt(f),
+      SerdeError::Message(message) => f.write_str(message),
+    }
+  }
+}
+
+impl serde::ser::StdError for SerdeError {}
+
+impl serde::ser::Error for SerdeError {
+  fn custom<T: fmt::Display>(message: T) -> Self {
+    SerdeError::Message(message.to_string())
+  }
+}
+
+impl serde::de::Error for SerdeError {
+  fn custom<T: fmt::Display>(message: T) -> Self {
+    SerdeError::Message(message.to_string())
+  }
+}
@@ -0,0 +1,443 @@
+use super::{SerdeConfig, SerdeError, StructMapping};
+use crate::{error::check, Error, XBuff};
+use core::convert::TryFrom;
+use libc::c_long;
+use serde::ser::{self, Serialize};
+
+/// A serde serializer that appends terms to an [`XBuff`].
+///
+/// [`XBuff`]: ../struct.XBuff.html
+pub struct Serializer<'a> {
+  buf: &'a mut XBuff,
+  config: SerdeConfig,
+}
+
+impl<'a> Serializer<'a> {
+  /// Creates a serializer that appends terms to `buf`.
+  #[inline]
+  pub fn new(buf: &'a mut XBuff, config: SerdeConfig) -> Self {
+    Serializer { buf, config }
+  }
+
+  fn tuple_header(&mut self, arity: usize) -> Result<(), SerdeError> {
+    let n = c_long::try_from(arity).map_err(|_| Error::Encode)?;
+    check(
+      unsafe { crate::ei_x_encode_tuple_header(self.buf.as_mut_ptr(), n) },
+      Error::Encode,
+    )?;
+    Ok(())
+  }
+
+  /// Starts a list or a map whose size is not known in advance. Its header is patched when the
+  /// compound ends.
+  fn open<'b>(&'b mut self, kind: Kind) -> Result<Compound<'b, 'a>, SerdeError> {
+    let header = self.buf.byte_len();
+    let code = unsafe {
+      match kind {
+        Kind::List => crate::ei_x_encode_list_header(self.buf.as_mut_ptr(), 1),
+        _ => crate::ei_x_encode_map_header(self.buf.as_mut_ptr(), 0),
+      }
+    };
+    check(code, Error::Encode)?;
+    Ok(Compound {
+      ser: self,
+      kind,
+      header,
+      count: 0,
+    })
+  }
+
+  fn fixed<'b>(&'b mut self, kind: Kind) -> Compound<'b, 'a> {
+    Compound {
+      ser: self,
+      kind,
+      header: 0,
+      count: 0,
+    }
+  }
+
+  fn struct_header<'b>(
+    &'b mut self,
+    name: &'static str,
+    len: usize,
+  ) -> Result<Compound<'b, 'a>, SerdeError> {
+    match self.config.structs {
+      StructMapping::Map => self.open(Kind::Struct),
+      StructMapping::Tuple => {
+        self.tuple_header(len)?;
+        Ok(self.fixed(Kind::Tuple))
+      }
+      StructMapping::Record => {
+        self.tuple_header(len + 1)?;
+        self.buf.encoder().atom(name)?;
+        Ok(self.fixed(Kind::Tuple))
+      }
+    }
+  }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+enum Kind {
+  List,
+  Map,
+  Struct,
+  Tuple,
+}
+
+/// The serializer of the elements of sequences, tuples, maps and structs.
+#[doc(hidden)]
+pub struct Compound<'b, 'a> {
+  ser: &'b mut Serializer<'a>,
+  kind: Kind,
+  header: usize,
+  count: u32,
+}
+
+impl Compound<'_, '_> {
+  fn element<T>(&mut self, value: &T) -> Result<(), SerdeError>
+  where
+    T: Serialize + ?Sized,
+  {
+    self.count = self.count.checked_add(1).ok_or(Error::Encode)?;
+    value.serialize(&mut *self.ser)
+  }
+
+  fn patch_count(&mut self) {
+    let count = self.count.to_be_bytes();
+    self.ser.buf.as_mut_bytes()[self.header + 1..self.header + 5].copy_from_slice(&count);
+  }
+
+  fn end(mut self) -> Result<(), SerdeError> {
+    match self.kind {
+      Kind::List if self.count == 0 => {
+        self.ser.buf.truncate(self.header);
+        self.ser.buf.encoder().empty_list()?;
+      }
+      Kind::List => {
+        self.patch_count();
+        check(
+          unsafe { crate::ei_x_encode_empty_list(self.ser.buf.as_mut_ptr()) },
+          Error::Encode,
+        )?;
+      }
+      Kind::Map | Kind::Struct => self.patch_count(),
+      Kind::Tuple => {}
+    }
+    Ok(())
+  }
+}
+
+impl<'b, 'a> ser::Serializer for &'b mut Serializer<'a> {
+  type Ok = ();
+  type Error = SerdeError;
+  type SerializeSeq = Compound<'b, 'a>;
+  type SerializeTuple = Compound<'b, 'a>;
+  type SerializeTupleStruct = Compound<'b, 'a>;
+  type SerializeTupleVariant = Compound<'b, 'a>;
+  type SerializeMap = Compound<'b, 'a>;
+  type SerializeStruct = Compound<'b, 'a>;
+  type SerializeStructVariant = Variant<'b, 'a>;
+
+  fn serialize_bool(self, v: bool) -> Result<(), SerdeError> {
+    self.buf.encoder().boolean(v)?;
+    Ok(())
+  }
+
+  fn serialize_i8(self, v: i8) -> Result<(), SerdeError> {
+    self.serialize_i64(v.into())
+  }
+
+  fn serialize_i16(self, v: i16) -> Result<(), SerdeError> {
+    self.serialize_i64(v.into())
+  }
+
+  fn serialize_i32(self, v: i32) -> Result<(), SerdeError> {
+    self.serialize_i64(v.into())
+  }
+
+  fn serialize_i64(self, v: i64) -> Result<(), SerdeError> {
+    self.buf.encoder().longlong(v)?;
+    Ok(())
+  }
+
+  fn serialize_u8(self, v: u8) -> Result<(), SerdeError> {
+    self.serialize_u64(v.into())
+  }
+
+  fn serialize_u16(self, v: u16) -> Result<(), SerdeError> {
+    self.serialize_u64(v.into())
+  }
+
+  fn serialize_u32(self, v: u32) -> Result<(), SerdeError> {
+    self.serialize_u64(v.into())
+  }
+
+  fn serialize_u64(self, v: u64) -> Result<(), SerdeError> {
+    self.buf.encoder().ulonglong(v)?;
+    Ok(())
+  }
+
+  fn serialize_f32(self, v: f32) -> Result<(), SerdeError> {
+    self.serialize_f64(v.into())
+  }
+
+  fn serialize_f64(self, v: f64) -> Result<(), SerdeError> {
+    self.buf.encoder().double(v)?;
+    Ok(())
+  }
+
+  fn serialize_char(self, v: char) -> Result<(), SerdeError> {
+    self.serialize_u64(u64::from(v))
+  }
+
+  fn serialize_str(self, v: &str) -> Result<(), SerdeError> {
+    self.serialize_bytes(v.as_bytes())
+  }
+
+  fn serialize_bytes(self, v: &[u8]) -> Result<(), SerdeError> {
+    self.buf.encoder().binary(v)?;
+    Ok(())
+  }
+
+  fn serialize_none(self) -> Result<(), SerdeError> {
+    self.buf.encoder().atom(self.config.none.atom())?;
+    Ok(())
+  }
+
+  fn serialize_some<T>(self, value: &T) -> Result<(), SerdeError>
+  where
+    T: Serialize + ?Sized,
+  {
+    value.serialize(self)
+  }
+
+  fn serialize_unit(self) -> Result<(), SerdeError> {
+    self.tuple_header(0)
+  }
+
+  fn serialize_unit_struct(self, name: &'static str) -> Result<(), SerdeError> {
+    self.buf.encoder().atom(name)?;
+    Ok(())
+  }
+
+  fn serialize_unit_variant(
+    self,
+    _name: &'static str,
+    _index: u32,
+    variant: &'static str,
+  ) -> Result<(), SerdeError> {
+    self.buf.encoder().atom(variant)?;
+    Ok(())
+  }
+
+  fn serialize_newtype_struct<T>(self, _name: &'static str, value: &T) -> Result<(), SerdeError>
+  where
+    T: Serialize + ?Sized,
+  {
+    value.serialize(self)
+  }
+
+  fn serialize_newtype_variant<T>(
+    self,
+    _name: &'static str,
+    _index: u32,
+    variant: &'static str,
+    value: &T,
+  ) -> Result<(), SerdeError>
+  where
+    T: Serialize + ?Sized,
+  {
+    self.tuple_header(2)?;
+    self.buf.encoder().atom(variant)?;
+    value.serialize(self)
+  }
+
+  fn serialize_seq(self, _len: Option<usize>) -> Result<Compound<'b, 'a>, SerdeError> {
+    self.open(Kind::List)
+  }
+
+  fn serialize_tuple(self, len: usize) -> Result<Compound<'b, 'a>, SerdeError> {
+    self.tuple_header(len)?;
+    Ok(self.fixed(Kind::Tuple))
+  }
+
+  fn serialize_tuple_struct(
+    self,
+    _name: &'static str,
+    len: usize,
+  ) -> Result<Compound<'b, 'a>, SerdeError> {
+    self.serialize_tuple(len)
+  }
+
+  fn serialize_tuple_variant(
+    self,
+    _name: &'static str,
+    _index: u32,
+    variant: &'static str,
+    len: usize,
+  ) -> Result<Compound<'b, 'a>, SerdeError> {
+    self.tuple_header(len + 1)?;
+    self.buf.encoder().atom(variant)?;
+    Ok(self.fixed(Kind::Tuple))
+  }
+
+  fn serialize_map(self, _len: Option<usize>) -> Result<Compound<'b, 'a>, SerdeError> {
+    self.open(Kind::Map)
+  }
+
+  fn serialize_struct(
+    self,
+    name: &'static str,
+    len: usize,
+  ) -> Result<Compound<'b, 'a>, SerdeError> {
+    self.struct_header(name, len)
+  }
+
+  fn serialize_struct_variant(
+    self,
+    _name: &'static str,
+    _index: u32,
+    variant: &'static str,
+    len: usize,
+  ) -> Result<Variant<'b, 'a>, SerdeError> {
+    self.tuple_header(if self.config.structs == StructMapping::Map {
+      2
+    } else {
+      len + 1
+    })?;
+    self.buf.encoder().atom(variant)?;
+    match self.config.structs {
+      StructMapping::Map => Ok(Variant(self.open(Kind::Struct)?)),
+      _ => Ok(Variant(self.fixed(Kind::Tuple))),
+    }
+  }
+}
+
+impl ser::SerializeSeq for Compound<'_, '_> {
+  type Ok = ();
+  type Error = SerdeError;
+
+  fn serialize_element<T>(&mut self, value: &T) -> Result<(), SerdeError>
+  where
+    T: Serialize + ?Sized,
+  {
+    self.element(value)
+  }
+
+  fn end(self) -> Result<(), SerdeError> {
+    Compound::end(self)
+  }
+}
+
+impl ser::SerializeTuple for Compound<'_, '_> {
+  type Ok = ();
+  type Error = SerdeError;
+
+  fn serialize_element<T>(&mut self, value: &T) -> Result<(), SerdeError>
+  where
+    T: Serialize + ?Sized,
+  {
+    self.element(value)
+  }
+
+  fn end(self) -> Result<(), SerdeError> {
+    Compound::end(self)
+  }
+}
+
+impl ser::SerializeTupleStruct for Compound<'_, '_> {
+  type Ok = ();
+  type Error = SerdeError;
+
+  fn serialize_field<T>(&mut self, value: &T) -> Result<(), SerdeError>
+  where
+    T: Serialize + ?Sized,
+  {
+    self.element(value)
+  }
+
+  fn end(self) -> Result<(), SerdeError> {
+    Compound::end(self)
+  }
+}
+
+impl ser::SerializeTupleVariant for Compound<'_, '_> {
+  type Ok = ();
+  type Error = SerdeError;
+
+  fn serialize_field<T>(&mut self, value: &T) -> Result<(), SerdeError>
+  where
+    T: Serialize + ?Sized,
+  {
+    self.element(value)
+  }
+
+  fn end(self) -> Result<(), SerdeError> {
+    Compound::end(self)
+  }
+}
+
+impl ser::SerializeMap for Compound<'_, '_> {
+  type Ok = ();
+  type Error = SerdeError;
+
+  fn serialize_key<T>(&mut self, key: &T) -> Result<(), SerdeError>
+  where
+    T: Serialize + ?Sized,
+  {
+    self.element(key)
+  }
+
+  fn serialize_value<T>(&mut self, value: &T) -> Result<(), SerdeError>
+  where
+    T: Serialize + ?Sized,
+  {
+    value.serialize(&mut *self.ser)
+  }
+
+  fn end(self) -> Result<(), SerdeError> {
+    Compound::end(self)
+  }
+}
+
+impl ser::SerializeStruct for Compound<'_, '_> {
+  type Ok = ();
+  type Error = SerdeError;
+
+  fn serialize_field<T>(&mut self, key: &'static str, value: &T) -> Result<(), SerdeError>
+  where
+    T: Serialize + ?Sized,
+  {
+    if self.kind == Kind::Struct {
+      self.count = self.count.checked_add(1).ok_or(Error::Encode)?;
+      self.ser.buf.encoder().atom(key)?;
+      value.serialize(&mut *self.ser)
+    } else {
+      self.element(value)
+    }
+  }
+
+  fn end(self) -> Result<(), SerdeError> {
+    Compound::end(self)
+  }
+}
+
+/// The serializer of the fields of struct variants.
+#[doc(hidden)]
+pub struct Variant<'b, 'a>(Compound<'b, 'a>);
+
+impl ser::SerializeStructVariant for Variant<'_, '_> {
+  type Ok = ();
+  type Error = SerdeError;
+
+  fn serialize_field<T>(&mut self, key: &'static str, value: &T) -> Result<(), SerdeError>
+  where
+    T: Serialize + ?Sized,
+  {
+    ser::SerializeStruct::serialize_field(&mut self.0, key, value)
+  }
+
+  fn end(self) -> Result<(), SerdeError> {
+    self.0.end()
+  }
+}
@@ -0,0 +1,309 @@
+//! A small EPMD, to test C nodes without the real daemon.
+
+use crate::{
+  epmd::{
+    ALIVE2_REQ, ALIVE2_RESP, ALIVE2_X_RESP, DUMP_REQ, NAMES_REQ, PORT2_RESP, PORT_PLEASE2_REQ,
+  },
+  error::{io_error, lock},
+  Epmd, Error, NodeInfo, NodeType,
+};
+use core::{
+  fmt::Write as _,
+  net::{Ipv4Addr, SocketAddr},
+};
+use std::{
+  collections::HashMap,
+  env,
+  io::{self, Read, Write},
+  net::{Shutdown, TcpListener, TcpStream},
+  string::{String, ToString},
+  sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc, Mutex, OnceLock,
+  },
+  thread::{self, JoinHandle},
+  vec::Vec,
+};
+
+/// An EPMD serving on a port of the local host from a thread of this process, until it is
+/// dropped.
+///
+/// It answers the requests of [`Epmd`]: nodes stay registered for as long as the connection they
+/// registered on is open, and can be looked up, listed and dumped.
+///
+/// ei finds EPMD through the `ERL_EPMD_PORT` environment variable, which it only reads once, so
+/// the variable must be set to [`port`] before the process first connects to or publishes a node.
+/// [`shared`] does so for the server shared by the tests of a process.
+///
+/// [`Epmd`]: ../struct.Epmd.html
+/// [`port`]: #method.port
+/// [`shared`]: #method.shared
+pub struct EpmdServer {
+  addr: SocketAddr,
+  state: Arc<State>,
+  thread: Option<JoinHandle<()>>,
+}
+
+struct State {
+  port: u16,
+  stopped: AtomicBool,
+  registry: Mutex<Registry>,
+}
+
+#[derive(Default)]
+struct Registry {
+  nodes: HashMap<String, Registered>,
+  registrations: u32,
+}
+
+struct Registered {
+  info: NodeInfo,
+  id: u32,
+  stream: TcpStream,
+}
+
+impl EpmdServer {
+  /// Starts a server on a port chosen by the operating system.
+  pub fn start() -> Result<Self, Error> {
+    Self::bind(0)
+  }
+
+  /// Returns the server shared by the process, started on a port chosen by the operating system
+  /// when it is first called, with `ERL_EPMD_PORT` set to its port.
+  ///
+  /// As ei reads `ERL_EPMD_PORT` only once, all the tests of a process that publish or connect
+  /// nodes must use this server, and call this before they create their first node.
+  ///
+  /// # Panics
+  ///
+  /// Panics if the server cannot be started.
+  pub fn shared() -> &'static EpmdServer {
+    static SHARED: OnceLock<EpmdServer> = OnceLock::new();
+    SHARED.get_or_init(|| {
+      let server = EpmdServer::start().expect("cannot start the shared EPMD");
+      env::set_var("ERL_EPMD_PORT", server.port().to_string());
+      server
+    })
+  }
+
+  /// Starts a server on `port` of the local host, or on a port chosen by the operating system if
+  /// it is `0`.
+  pub fn bind(port: u16) -> Result<Self, Error> {
+    let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, port)).map_err(io_error)?;
+    let addr = listener.local_addr().map_err(io_error)?;
+    let state = Arc::new(State {
+      port: addr.port(),
+      stopped: AtomicBool::new(false),
+      registry: Mutex::default(),
+    });
+    let thread = {
+      let state = Arc::clone(&state);
+      thread::Builder::new()
+        .name("epmd".into())
+        .spawn(move || serve(&listener, &state))
+        .map_err(io_error)?
+    };
+    Ok(Self {
+      addr,
+      state,
+      thread: Some(thread),
+    })
+  }
+
+  /// Returns the port the server listens on.
+  #[inline]
+  pub fn port(&self) -> u16 {
+    self.addr.port()
+  }
+
+  /// Returns the address the server listens on.
+  #[inline]
+  pub fn addr(&self) -> SocketAddr {
+    self.addr
+  }
+
+  /// Returns a client of the server.
+  pub fn client(&self) -> Epmd {
+    Epmd::new(self.addr)
+  }
+
+  /// Returns the registered nodes.
+  pub fn nodes(&self) -> Vec<NodeInfo> {
+    let registry = lock(&self.state.registry);
+    registry
+      .nodes
+      .values()
+      .map(|node| node.info.clone())
+      .collect()
+  }
+}
+
+impl Drop for EpmdServer {
+  fn drop(&mut self) {
+    self.state.stopped.store(true, Ordering::SeqCst);
+    // Wakes the thread up from accepting.
+    let _ = TcpStream::connect(self.addr);
+    if let Some(thread) = self.thread.take() {
+      let _ = thread.join();
+    }
+    for (_, node) in lock(&self.state.registry).nodes.drain() {
+      let _ = node.stream.shutdown(Shutdown::Both);
+    }
+  }
+}
+
+fn serve(listener: &TcpListener, state: &Arc<State>) {
+  for stream in listener.incoming() {
+    if state.stopped.load(Ordering::SeqCst) {
+      break;
+    }
+    if let Ok(stream) = stream {
+      let state = Arc::clone(state);
+      thread::spawn(move || handle(stream, &state));
+    }
+  }
+}
+
+/// Answers the request on `stream`. Malformed and unknown requests are answered by closing the
+/// connection, like EPMD does.
+fn handle(mut stream: TcpStream, state: &State) -> io::Result<()> {
+  let mut len = [0; 2];
+  stream.read_exact(&mut len)?;
+  let mut request = std::vec![0; usize::from(u16::from_be_bytes(len))];
+  stream.read_exact(&mut request)?;
+
+  match request.split_first() {
+    Some((&ALIVE2_REQ, body)) => match parse_alive(body) {
+      Some(info) => register(stream, info, state),
+      None => Ok(()),
+    },
+    Some((&PORT_PLEASE2_REQ, name)) => {
+      let registry = lock(&state.registry);
+      let name = String::from_utf8_lossy(name);
+      let reply = match registry.nodes.get(name.as_ref()) {
+        Some(node) => port2_resp(&node.info),
+        None => std::vec![PORT2_RESP, 1],
+      };
+      drop(registry);
+      stream.write_all(&reply)
+    }
+    Some((&NAMES_REQ, [])) => {
+      let mut text = String::new();
+      for node in lock(&state.registry).nodes.values() {
+        let _ = writeln!(text, "name {} at port {}", node.info.name, node.info.port);
+      }
+      reply_text(&mut stream, state, &text)
+    }
+    Some((&DUMP_REQ, [])) => {
+      let mut text = String::new();
+      for node in lock(&state.registry).nodes.values() {
+        let _ = write!(
+          text,
+          "active name     <{}> at port {}, fd = {}\n\0",
+          node.info.name, node.info.port, node.id
+        );
+      }
+      reply_text(&mut stream, state, &text)
+    }
+    _ => Ok(()),
+  }
+}
+
+/// Registers `info` for as long as `stream` stays open.
+fn register(mut stream: TcpStream, info: NodeInfo, state: &State) -> io::Result<()> {
+  let name = info.name.clone();
+  let modern = info.highest_version >= 6;
+  let mut registry = lock(&state.registry);
+  if registry.nodes.contains_key(&name) {
+    drop(registry);
+    return stream.write_all(&[ALIVE2_RESP, 1, 0, 0]);
+  }
+  registry.registrations = registry.registrations.wrapping_add(1).max(1);
+  let id = registry.registrations;
+  let registered = Registered {
+    info,
+    id,
+    stream: stream.try_clone()?,
+  };
+  registry.nodes.insert(name.clone(), registered);
+  drop(registry);
+
+  // The creation tells the incarnations of a node apart. Older nodes only have two bits for it.
+  let sent = if modern {
+    let creation = id.to_be_bytes();
+    stream.write_all(&[
+      ALIVE2_X_RESP,
+      0,
+      creation[0],
+      creation[1],
+      creation[2],
+      creation[3],
+    ])
+  } else {
+    let creation = (id % 3 + 1) as u8;
+    stream.write_all(&[ALIVE2_RESP, 0, 0, creation])
+  };
+  if sent.is_ok() {
+    let mut buf = [0; 64];
+    while let Ok(1..) = stream.read(&mut buf) {}
+  }
+
+  let mut registry = lock(&state.registry);
+  if registry.nodes.get(&name).is_some_and(|node| node.id == id) {
+    registry.nodes.remove(&name);
+  }
+  sent
+}
+
+/// Parses the body of an `ALIVE2_REQ`.
+fn parse_alive(body: &[u8]) -> Option<NodeInfo> {
+  let (fixed, rest) = split(body, 8)?;
+  let (name, rest) = split_prefixed(rest)?;
+  let (extra, _) = split_prefixed(rest)?;
+  if name.is_empty() {
+    return None;
+  }
+  Some(NodeInfo {
+    name: String::from(core::str::from_utf8(name).ok()?),
+    port: u16::from_be_bytes([fixed[0], fixed[1]]),
+    node_type: NodeType::from(fixed[2]),
+    protocol: fixed[3],
+    highest_version: u16::from_be_bytes([fixed[4], fixed[5]]),
+    lowest_version: u16::from_be_bytes([fixed[6], fixed[7]]),
+    extra: extra.into(),
+  })
+}
+
+fn port2_resp(info: &NodeInfo) -> Vec<u8> {
+  let mut reply = std::vec![PORT2_RESP, 0];
+  reply.extend_from_slice(&info.port.to_be_bytes());
+  reply.push(info.node_type.into());
+  reply.push(info.protocol);
+  reply.extend_from_slice(&info.highest_version.to_be_bytes());
+  reply.extend_from_slice(&info.lowest_version.to_be_bytes());
+  // The lengths fit, since they were parsed from the registration.
+  reply.extend_from_slice(&(info.name.len() as u16).to_be_bytes());
+  reply.extend_from_slice(info.name.as_bytes());
+  reply.extend_from_slice(&(info.extra.len() as u16).to_be_bytes());
+  reply.extend_from_slice(&info.extra);
+  reply
+}
+
+/// Replies with the port of the server followed by `text`, then closes the connection.
+fn reply_text(stream: &mut TcpStream, state: &State, text: &str) -> io::Result<()> {
+  stream.write_all(&u32::from(state.port).to_be_bytes())?;
+  stream.write_all(text.as_bytes())
+}
+
+fn split(bytes: &[u8], at: usize) -> Option<(&[u8], &[u8])> {
+  if bytes.len() < at {
+    return None;
+  }
+  Some(bytes.split_at(at))
+}
+
+/// Splits bytes prefixed by their length on two bytes from the rest.
+fn split_prefixed(bytes: &[u8]) -> Option<(&[u8], &[u8])> {
+  let (len, rest) = split(bytes, 2)?;
+  split(rest, usize::from(u16::from_be_bytes([len[0], len[1]])))
+}
@@ -0,0 +1,176 @@
+//! Erlang nodes started for tests.
+
+use crate::{error::io_error, CNode, Connection, Error};
+use core::{
+  sync::atomic::{AtomicUsize, Ordering},
+  time::Duration,
+};
+use std::{
+  env, format,
+  io::{BufRead, BufReader},
+  process::{self, Child, Command, Stdio},
+  string::String,
+  sync::mpsc::{self, RecvTimeoutError},
+  thread,
+  time::Instant,
+};
+
+/// The code run by the node, which registers a process `echo` that sends `Msg` to `Pid` when it
+/// receives `{Pid, Msg}`, then prints `READY`.
+const ECHO: &str = "register(echo, spawn(fun Loop() -> \
+                    receive {From, Msg} when is_pid(From) -> From ! Msg; _ -> ok end, \
+                    Loop() end)), \
+                    io:put_chars(\"ei_sys_ready\\n\").";
+
+/// The line printed by the node once `echo` is registered.
+const READY: &str = "ei_sys_ready";
+
+/// How long the node may take to start.
+const START_TIMEOUT: Duration = Duration::from_secs(20);
+
+/// An Erlang node running in a child process, named `alive@localhost`, which is killed when
+/// dropped.
+///
+/// The node registers a process `echo`, which sends `Msg` to `Pid` when it receives `{Pid, Msg}`.
+///
+/// Tests using it should check [`available`] first and return early if `erl` is missing.
+///
+/// [`available`]: #method.available
+pub struct ErlNode {
+  child: Child,
+  node_name: String,
+  cookie: String,
+}
+
+impl ErlNode {
+  /// Returns `true` if `erl` is in the `PATH`.
+  pub fn available() -> bool {
+    env::var_os("PATH").is_some_and(|path| {
+      env::split_paths(&path).any(|dir| dir.join("erl").is_file() || dir.join("erl.exe").is_file())
+    })
+  }
+
+  /// Starts a node with a name and cookie unique to this process, and waits for it to register
+  /// with EPMD and to register `echo`.
+  ///
+  /// Fails with [`Error::Timeout`] if the node takes too long to start, and with
+  /// `Error::Io(ECHILD)` if it exits before.
+  ///
+  /// [`Error::Timeout`]: ../enum.Error.html#variant.Timeout
+  pub fn start() -> Result<Self, Error> {
+    let alive_name = unique_name("erl");
+    let node_name = format!("{}@localhost", alive_name);
+    let cookie = format!("ei_sys_test_{}", process::id());
+    let child = Command::new("erl")
+      .args(["-sname", &node_name, "-setcookie", &cookie, "-noshell"])
+      .args(["-eval", ECHO])
+      .stdin(Stdio::null())
+      .stdout(Stdio::piped())
+      .spawn()
+      .map_err(io_error)?;
+    let mut node = Self {
+      child,
+      node_name,
+      cookie,
+    };
+    node.wait_ready()?;
+    Ok(node)
+  }
+
+  /// Waits for the node to print `READY`, which it does once `echo` is registered, after its
+  /// distribution has started and registered with EPMD.
+  fn wait_ready(&mut self) -> Result<(), Error> {
+    let stdout = match self.child.stdout.take() {
+      Some(stdout) => stdout,
+      None => return Err(Error::Io(libc::EBADF)),
+    };
+    let (ready, is_ready) = mpsc::channel();
+    thread::Builder::new()
+      .name("erl stdout".into())
+      .spawn(move || {
+        // Reads until the node exits, so that it never blocks on a full pipe.
+        for line in BufReader::new(stdout).lines() {
+          match line {
+            Ok(line) if line.trim_end() == READY => {
+              let _ = ready.send(());
+            }
+            Ok(_) => {}
+            Err(_) => break,
+          }
+        }
+      })
+      .map_err(io_error)?;
+
+    let start = Instant::now();
+    loop {
+      match is_ready.recv_timeout(Duration::from_millis(50)) {
+        Ok(()) => return Ok(()),
+        // The node closed its output, so it exited.
+        Err(RecvTimeoutError::Disconnected) => return Err(Error::Io(libc::ECHILD)),
+        Err(RecvTimeoutError::Timeout) => {}
+      }
+      if self.child.try_wait().map_err(io_error)?.is_some() {
+        return Err(Error::Io(libc::ECHILD));
+      }
+      if start.elapsed() >= START_TIMEOUT {
+        return Err(Error::Timeout);
+      }
+    }
+  }
+
+  /// Returns the name of the node, `alive@localhost`.
+  #[inline]
+  pub fn node_name(&self) -> &str {
+    &self.node_name
+  }
+
+  /// Returns the cookie of the node.
+  #[inline]
+  pub fn cookie(&self) -> &str {
+    &self.cookie
+  }
+
+  /// Creates a C node with a unique name on `localhost` and the cookie of this node.
+  pub fn cnode(&self) -> Result<CNode, Error> {
+    let alive_name = unique_name("c");
+    CNode::builder(&alive_name, &self.cookie)
+      .host_name("localhost")
+      .build()
+  }
+
+  /// Connects a new C node to this node.
+  pub fn connect(&self) -> Result<Connection, Error> {
+    self
+      .cnode()?
+      .connect_timeout(&self.node_name, START_TIMEOUT)
+  }
+
+  /// Waits for the node to exit, such as after `init:stop()`, and returns `false` if it is still
+  /// running after `timeout`.
+  pub fn wait_timeout(&mut self, timeout: Duration) -> Result<bool, Error> {
+    let start = Instant::now();
+    loop {
+      if self.child.try_wait().map_err(io_error)?.is_some() {
+        return Ok(true);
+      }
+      if start.elapsed() >= timeout {
+        return Ok(false);
+      }
+      thread::sleep(Duration::from_millis(50));
+    }
+  }
+}
+
+impl Drop for ErlNode {
+  fn drop(&mut self) {
+    let _ = self.child.kill();
+    let _ = self.child.wait();
+  }
+}
+
+/// Returns an alive name unique to this process and call.
+fn unique_name(prefix: &str) -> String {
+  static COUNT: AtomicUsize = AtomicUsize::new(0);
+  let count = COUNT.fetch_add(1, Ordering::Relaxed);
+  format!("ei_sys_{}_{}_{}", prefix, process::id(), count)
+}
@@ -0,0 +1,6 @@
+//! Helpers for testing C nodes, enabled by the `test-support` feature.
+
+mod epmd;
+mod erl;
+
+pub use self::{epmd::EpmdServer, erl::ErlNode};
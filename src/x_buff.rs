@@ -0,0 +1,173 @@
+//! An owned, growable buffer of terms.
+
+use crate::error::{check, Error};
+use core::{convert::TryFrom, ffi::CStr, fmt, ptr, slice, str};
+use libc::{c_char, c_int, c_void};
+
+/// An owned [`ei_x_buff`], freed when dropped.
+///
+/// [`ei_x_buff`]: struct.ei_x_buff.html
+pub struct XBuff {
+  raw: crate::ei_x_buff,
+  start: c_int,
+}
+
+// The buffer is uniquely owned and ei does not keep any reference to it.
+unsafe impl Send for XBuff {}
+unsafe impl Sync for XBuff {}
+
+impl XBuff {
+  /// Creates an empty buffer, with an initial capacity of [`ei_x_extra`] bytes.
+  ///
+  /// [`ei_x_extra`]: static.ei_x_extra.html
+  pub fn new() -> Result<Self, Error> {
+    Self::init(crate::ei_x_new)
+  }
+
+  /// Creates a buffer that starts with the version magic byte, as expected of messages sent to
+  /// other nodes.
+  ///
+  /// The version byte is never removed from the buffer, not even by [`clear`].
+  ///
+  /// [`clear`]: #method.clear
+  pub fn with_version() -> Result<Self, Error> {
+    Self::init(crate::ei_x_new_with_version)
+  }
+
+  fn init(new: unsafe extern "C" fn(*mut crate::ei_x_buff) -> c_int) -> Result<Self, Error> {
+    let mut raw = crate::ei_x_buff {
+      buff: ptr::null_mut(),
+      buffsz: 0,
+      index: 0,
+    };
+    check(unsafe { new(&mut raw) }, Error::Alloc)?;
+    let start = raw.index;
+    Ok(XBuff { raw, start })
+  }
+
+  /// Returns the bytes written to the buffer so far.
+  #[inline]
+  pub fn as_bytes(&self) -> &[u8] {
+    unsafe { slice::from_raw_parts(self.raw.buff as *const u8, self.raw.index as usize) }
+  }
+
+  /// Returns the number of bytes written to the buffer so far, including the version byte, which
+  /// is the length of [`as_bytes`].
+  ///
+  /// This is not a number of terms, so a buffer can be [`is_empty`] with a length of 1.
+  ///
+  /// [`as_bytes`]: #method.as_bytes
+  /// [`is_empty`]: #method.is_empty
+  #[inline]
+  pub fn byte_len(&self) -> usize {
+    self.raw.index as usize
+  }
+
+  /// Returns `true` if no term was written to the buffer.
+  #[inline]
+  pub fn is_empty(&self) -> bool {
+    self.raw.index == self.start
+  }
+
+  /// Removes all the terms from the buffer, keeping its capacity.
+  #[inline]
+  pub fn clear(&mut self) {
+    self.raw.index = self.start;
+  }
+
+  /// Returns the bytes written to the buffer so far, to patch headers after the fact.
+  #[cfg(feature = "serde")]
+  #[inline]
+  pub(crate) fn as_mut_bytes(&mut self) -> &mut [u8] {
+    unsafe { slice::from_raw_parts_mut(self.raw.buff as *mut u8, self.raw.index as usize) }
+  }
+
+  /// Shortens the buffer to `len` bytes, which must not be more than its [`byte_len`].
+  ///
+  /// [`byte_len`]: #method.byte_len
+  #[cfg(feature = "serde")]
+  #[inline]
+  pub(crate) fn truncate(&mut self, len: usize) {
+    debug_assert!(len <= self.byte_len());
+    self.raw.index = len as c_int;
+  }
+
+  /// Appends the contents of `other` to this buffer.
+  ///
+  /// Note that this copies the version byte of `other`, if it has one.
+  pub fn append(&mut self, other: &XBuff) -> Result<(), Error> {
+    check(
+      unsafe { crate::ei_x_append(&mut self.raw, &other.raw) },
+      Error::Alloc,
+    )
+  }
+
+  /// Appends raw bytes to this buffer.
+  pub fn extend_from_slice(&mut self, bytes: &[u8]) -> Result<(), Error> {
+    let len = c_int::try_from(bytes.len()).map_err(|_| Error::Alloc)?;
+    check(
+      unsafe { crate::ei_x_append_buf(&mut self.raw, bytes.as_ptr() as *const c_char, len) },
+      Error::Alloc,
+    )
+  }
+
+  /// Returns a pointer to the underlying [`ei_x_buff`], to be passed to the `ei_x_*` functions.
+  ///
+  /// [`ei_x_buff`]: struct.ei_x_buff.html
+  #[inline]
+  pub fn as_ptr(&self) -> *const crate::ei_x_buff {
+    &self.raw
+  }
+
+  /// Returns a mutable pointer to the underlying [`ei_x_buff`], to be passed to the `ei_x_*`
+  /// functions.
+  ///
+  /// The buffer must not be freed through this pointer.
+  ///
+  /// [`ei_x_buff`]: struct.ei_x_buff.html
+  #[inline]
+  pub fn as_mut_ptr(&mut self) -> *mut crate::ei_x_buff {
+    &mut self.raw
+  }
+}
+
+impl Drop for XBuff {
+  fn drop(&mut self) {
+    unsafe {
+      crate::ei_x_free(&mut self.raw);
+    }
+  }
+}
+
+impl fmt::Debug for XBuff {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    f.write_str("XBuff(")?;
+    let mut index = self.start;
+    while index < self.raw.index {
+      if index != self.start {
+        f.write_str(", ")?;
+      }
+      // The buffer may contain arbitrary bytes, which ei would happily read out of bounds.
+      let mut printed: *mut c_char = ptr::null_mut();
+      if crate::decode::term_end(self.as_bytes(), index as usize).is_err()
+        || unsafe { crate::ei_s_print_term(&mut printed, self.raw.buff, &mut index) } < 0
+      {
+        write!(
+          f,
+          "{:?}",
+          self.as_bytes().get(index as usize..).unwrap_or(&[])
+        )?;
+        break;
+      }
+      let result = match str::from_utf8(unsafe { CStr::from_ptr(printed) }.to_bytes()) {
+        Ok(term) => f.write_str(term),
+        Err(_) => f.write_str("<invalid utf-8>"),
+      };
+      unsafe {
+        libc::free(printed as *mut c_void);
+      }
+      result?;
+    }
+    f.write_str(")")
+  }
+}
@@ -0,0 +1,73 @@
+#![cfg(all(feature = "tokio", feature = "test-support", unix))]
+
+use ei_sys::{test_support::EpmdServer, AsyncListener, CNode, Connection, TermRef, XBuff};
+use std::{future::Future, thread, time::Duration};
+use tokio::time::timeout;
+
+const COOKIE: &str = "cookie";
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+fn node(alive_name: &str) -> CNode {
+  EpmdServer::shared();
+  CNode::builder(alive_name, COOKIE)
+    .host_name("localhost")
+    .build()
+    .unwrap()
+}
+
+/// Runs `future` on a new single-threaded runtime, since the macros of tokio are not enabled.
+fn block_on<F: Future>(future: F) -> F::Output {
+  tokio::runtime::Builder::new_current_thread()
+    .enable_all()
+    .build()
+    .unwrap()
+    .block_on(future)
+}
+
+/// Connects `node` to the node named `to` from another thread.
+fn connect(node: CNode, to: &str) -> thread::JoinHandle<Connection> {
+  let to = to.to_owned();
+  thread::spawn(move || node.connect(&to).unwrap())
+}
+
+#[test]
+fn accept_connections() {
+  let server = node("async_server");
+  let client = node("async_client");
+  block_on(async {
+    let listener = AsyncListener::new(server.listen(0).unwrap());
+    let connector = connect(client, server.node_name());
+    let (mut connection, peer) = timeout(TIMEOUT, listener.accept()).await.unwrap().unwrap();
+    assert_eq!(peer.node_name(), "async_client@localhost");
+    assert_eq!(connection.get_ref().peer_name(), "async_client@localhost");
+
+    let mut client = connector.join().unwrap();
+    let mut buf = XBuff::with_version().unwrap();
+    buf.encoder().atom("hello").unwrap();
+    client.reg_send("server", &buf).unwrap();
+    let (_, payload) = timeout(TIMEOUT, connection.receive())
+      .await
+      .unwrap()
+      .unwrap();
+    let payload = TermRef::from_x_buff(&payload).unwrap();
+    assert_eq!(payload.as_atom().unwrap(), "hello");
+  });
+}
+
+#[test]
+fn keep_connections_accepted_after_a_cancelled_accept() {
+  let server = node("cancel_server");
+  let client = node("cancel_client");
+  block_on(async {
+    let listener = AsyncListener::new(server.listen(0).unwrap());
+    assert!(timeout(Duration::from_millis(50), listener.accept())
+      .await
+      .is_err());
+
+    // The blocking thread of the cancelled call is still accepting, and accepts this connection.
+    let connector = connect(client, server.node_name());
+    let (_, peer) = timeout(TIMEOUT, listener.accept()).await.unwrap().unwrap();
+    assert_eq!(peer.node_name(), "cancel_client@localhost");
+    connector.join().unwrap();
+  });
+}
@@ -0,0 +1,63 @@
+#![cfg(all(feature = "std", unix))]
+
+use ei_sys::{Cookie, CookieLookup, CookieSource, Error};
+use std::{env, fs, os::unix::fs::PermissionsExt, path::Path, process};
+
+fn write_cookie(path: &Path, cookie: &str, mode: u32) {
+  fs::create_dir_all(path.parent().unwrap()).unwrap();
+  fs::write(path, cookie).unwrap();
+  fs::set_permissions(path, fs::Permissions::from_mode(mode)).unwrap();
+}
+
+// The lookup reads the environment, so the cases run in order in a single test.
+#[test]
+fn lookup_order() {
+  let root = env::temp_dir().join(format!("ei_sys_cookie_{}", process::id()));
+  let home = root.join("home");
+  let config = root.join("config");
+  fs::create_dir_all(&home).unwrap();
+  env::set_var("HOME", &home);
+  env::set_var("XDG_CONFIG_HOME", &config);
+  env::remove_var("ERLANG_COOKIE");
+
+  assert_eq!(Cookie::find().unwrap_err(), Error::Io(libc::ENOENT));
+
+  let xdg_file = config.join("erlang/.erlang.cookie");
+  write_cookie(&xdg_file, "xdg\n", 0o400);
+  let cookie = Cookie::find().unwrap();
+  assert_eq!(cookie.as_str(), "xdg");
+  assert_eq!(cookie.source(), &CookieSource::File(xdg_file));
+
+  let home_file = home.join(".erlang.cookie");
+  write_cookie(&home_file, "home", 0o644);
+  assert_eq!(Cookie::find().unwrap_err(), Error::Io(libc::EACCES));
+  let cookie = CookieLookup::new().check_permissions(false).find().unwrap();
+  assert_eq!(cookie.as_str(), "home");
+  fs::set_permissions(&home_file, fs::Permissions::from_mode(0o400)).unwrap();
+  assert_eq!(Cookie::find().unwrap().as_str(), "home");
+
+  env::set_var("ERLANG_COOKIE", "env");
+  let cookie = Cookie::find().unwrap();
+  assert_eq!(cookie.as_str(), "env");
+  assert_eq!(cookie.source(), &CookieSource::Env("ERLANG_COOKIE".into()));
+  assert_eq!(
+    CookieLookup::new().env_var(None).find().unwrap().as_str(),
+    "home"
+  );
+
+  let cookie = CookieLookup::new()
+    .explicit(Some("explicit"))
+    .find()
+    .unwrap();
+  assert_eq!(cookie.source(), &CookieSource::Explicit);
+  let too_long = "a".repeat(ei_sys::EI_MAX_COOKIE_SIZE + 1);
+  assert_eq!(
+    CookieLookup::new()
+      .explicit(Some(&too_long))
+      .find()
+      .unwrap_err(),
+    Error::InvalidArgument
+  );
+
+  fs::remove_dir_all(&root).unwrap();
+}
@@ -0,0 +1,110 @@
+use ei_sys::{Decoder, ExtTag, TermRef};
+
+/// Encodes a fun of the module `m` that claims `num_free` free variables, followed by the bytes of
+/// `free_vars`, and whose size is off by `size_delta`.
+fn fun(num_free: u32, free_vars: &[u8], size_delta: i32) -> Vec<u8> {
+  let mut fields = vec![1];
+  fields.extend_from_slice(&[0x5a; 16]);
+  fields.extend_from_slice(&0u32.to_be_bytes());
+  fields.extend_from_slice(&num_free.to_be_bytes());
+  fields.extend_from_slice(&[b'w', 1, b'm']);
+  fields.extend_from_slice(&[b'a', 0]);
+  fields.extend_from_slice(&[b'b', 0, 0, 0, 42]);
+  fields.extend_from_slice(&[b'X', b'w', 3, b'n', b'@', b'h']);
+  fields.extend_from_slice(&[0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0, 1]);
+  fields.extend_from_slice(free_vars);
+
+  let size = (4 + fields.len() as i32 + size_delta) as u32;
+  let mut bytes = vec![b'p'];
+  bytes.extend_from_slice(&size.to_be_bytes());
+  bytes.extend(fields);
+  bytes
+}
+
+fn decode_all(bytes: &[u8]) -> bool {
+  match Decoder::new(bytes) {
+    Ok(mut decoder) => {
+      while !decoder.is_empty() {
+        decoder.skip().unwrap();
+      }
+      true
+    }
+    Err(_) => false,
+  }
+}
+
+#[test]
+fn accepts_funs() {
+  let bytes = fun(0, &[], 0);
+  let term = TermRef::new(&bytes).unwrap();
+  assert_eq!(term.tag(), ExtTag::NewFun);
+  assert!(decode_all(&bytes));
+
+  let bytes = fun(2, &[b'a', 7, b'j'], 0);
+  assert!(decode_all(&bytes));
+
+  // A fun in a free variable, in a list, followed by another term.
+  let mut list = vec![b'l', 0, 0, 0, 1];
+  list.extend(fun(1, &fun(1, &[b'a', 1], 0), 0));
+  list.extend_from_slice(&[b'j', b'a', 2]);
+  assert!(decode_all(&list));
+}
+
+#[test]
+fn rejects_funs_whose_size_does_not_match() {
+  assert!(!decode_all(&[b'p', 0, 0, 0, 4]));
+  assert!(!decode_all(&[b'p', 0, 0, 0, 0]));
+  assert!(!decode_all(&fun(0, &[], -1)));
+  assert!(!decode_all(&fun(0, &[], 1)));
+  // The free variable ends before the size says.
+  let mut bytes = fun(1, &[b'a', 7], 1);
+  bytes.push(b'j');
+  assert!(!decode_all(&bytes));
+  // The free variable ends after the size says, on a term that looks complete.
+  let mut bytes = fun(1, &[b'a', 7], -2);
+  bytes.extend_from_slice(&[b'a', 7]);
+  assert!(!decode_all(&bytes));
+}
+
+#[test]
+fn rejects_funs_with_malformed_fields() {
+  // More free variables than the fun holds.
+  assert!(!decode_all(&fun(2, &[b'a', 7], 0)));
+  assert!(!decode_all(&fun(u32::MAX, &[b'a', 7], 0)));
+  // Fewer free variables than the fun holds.
+  assert!(!decode_all(&fun(0, &[b'a', 7], 0)));
+  // A module that is not an atom.
+  let mut bytes = fun(0, &[], 0);
+  bytes[30] = b'k';
+  assert!(!decode_all(&bytes));
+  // A pid whose node runs past the end of the fun.
+  let mut bytes = fun(0, &[], 0);
+  bytes[42] = 200;
+  assert!(!decode_all(&bytes));
+}
+
+#[test]
+fn rejects_truncated_funs() {
+  let bytes = fun(2, &[b'a', 7, b'k', 0, 1, b'x'], 0);
+  assert!(decode_all(&bytes));
+  for len in 1..bytes.len() {
+    assert!(!decode_all(&bytes[..len]), "{}", len);
+  }
+}
+
+#[test]
+fn rejects_funs_nested_too_deep() {
+  let mut bytes = fun(0, &[], 0);
+  for _ in 0..63 {
+    bytes = fun(1, &bytes, 0);
+  }
+  assert!(decode_all(&bytes));
+  assert!(!decode_all(&fun(1, &bytes, 0)));
+}
+
+#[test]
+fn rejects_arities_that_overflow() {
+  assert!(!decode_all(&[b'l', 0xff, 0xff, 0xff, 0xff, b'j']));
+  assert!(!decode_all(&[b't', 0xff, 0xff, 0xff, 0xff]));
+  assert!(!decode_all(&[b'u', 0xff, 0xff, 0xff, 0xff]));
+}
@@ -0,0 +1,205 @@
+#![cfg(all(feature = "derive", feature = "alloc"))]
+
+use ei_sys::{Decoder, Error, FromTerm, Term, ToTerm, XBuff};
+use std::fmt;
+
+#[derive(Debug, PartialEq, ToTerm, FromTerm)]
+struct Point {
+  x: i64,
+  #[term(rename = "why")]
+  y: i64,
+}
+
+#[derive(Debug, PartialEq, ToTerm, FromTerm)]
+#[term(record = "person")]
+struct Person {
+  name: String,
+  age: u32,
+}
+
+#[derive(Debug, PartialEq, ToTerm, FromTerm)]
+#[term(elixir_struct = "Elixir.User")]
+struct User {
+  id: u32,
+  admin: bool,
+}
+
+#[derive(Debug, PartialEq, ToTerm, FromTerm)]
+struct Meters(f64);
+
+#[derive(Debug, PartialEq, ToTerm, FromTerm)]
+struct Pair(u8, bool);
+
+#[derive(Debug, PartialEq, ToTerm, FromTerm)]
+struct HTTPServer;
+
+#[derive(Debug, PartialEq, ToTerm, FromTerm)]
+#[term(atom)]
+enum Level {
+  Info,
+  NotFound,
+  #[term(rename = "ERROR")]
+  Error,
+}
+
+#[derive(Debug, PartialEq, ToTerm, FromTerm)]
+enum Message {
+  Ping,
+  Echo(String),
+  Move(i64, i64),
+  Resize { width: u32, height: u32 },
+}
+
+fn atom(name: &str) -> Term {
+  Term::Atom(name.to_owned())
+}
+
+fn encode<T: ToTerm>(value: &T) -> XBuff {
+  let mut buf = XBuff::with_version().unwrap();
+  value.to_term(&mut buf.encoder()).unwrap();
+  buf
+}
+
+fn decode<T: FromTerm>(bytes: &[u8]) -> Result<T, Error> {
+  let mut decoder = Decoder::new(bytes)?;
+  let value = T::from_term(&mut decoder)?;
+  assert!(decoder.is_empty());
+  Ok(value)
+}
+
+/// Checks that `value` is encoded as `expected`, and that it decodes back to `value`.
+fn round_trip<T>(value: &T, expected: Term)
+where
+  T: ToTerm + FromTerm + PartialEq + fmt::Debug,
+{
+  let buf = encode(value);
+  assert_eq!(Term::from_x_buff(&buf).unwrap(), expected);
+  assert_eq!(decode::<T>(buf.as_bytes()).unwrap(), *value);
+}
+
+#[test]
+fn derives_structs() {
+  round_trip(
+    &Point { x: 1, y: -2 },
+    Term::Map(vec![
+      (atom("x"), Term::Integer(1)),
+      (atom("why"), Term::Integer(-2)),
+    ]),
+  );
+  round_trip(&Meters(1.5), Term::Float(1.5));
+  round_trip(
+    &Pair(7, true),
+    Term::Tuple(vec![Term::Integer(7), atom("true")]),
+  );
+  round_trip(&HTTPServer, atom("http_server"));
+}
+
+#[test]
+fn derives_records() {
+  round_trip(
+    &Person {
+      name: "Joe".to_owned(),
+      age: 42,
+    },
+    Term::Tuple(vec![
+      atom("person"),
+      Term::Binary(b"Joe".to_vec()),
+      Term::Integer(42),
+    ]),
+  );
+
+  let other = Term::Tuple(vec![
+    atom("other"),
+    Term::Binary(b"Joe".to_vec()),
+    Term::Integer(42),
+  ]);
+  assert!(decode::<Person>(encode(&other).as_bytes()).is_err());
+  let short = Term::Tuple(vec![atom("person"), Term::Binary(b"Joe".to_vec())]);
+  assert!(decode::<Person>(encode(&short).as_bytes()).is_err());
+}
+
+#[test]
+fn derives_elixir_structs() {
+  round_trip(
+    &User {
+      id: 3,
+      admin: false,
+    },
+    Term::Map(vec![
+      (atom("__struct__"), atom("Elixir.User")),
+      (atom("id"), Term::Integer(3)),
+      (atom("admin"), atom("false")),
+    ]),
+  );
+
+  // The keys may come in any order, and unknown keys are ignored.
+  let reordered = Term::Map(vec![
+    (atom("admin"), atom("true")),
+    (atom("extra"), Term::Integer(0)),
+    (atom("id"), Term::Integer(4)),
+    (atom("__struct__"), atom("Elixir.User")),
+  ]);
+  assert_eq!(
+    decode::<User>(encode(&reordered).as_bytes()).unwrap(),
+    User { id: 4, admin: true }
+  );
+
+  // So are keys that are not atoms.
+  let mixed = Term::Map(vec![
+    (Term::Integer(1), atom("id")),
+    (atom("id"), Term::Integer(4)),
+    (Term::Binary(b"admin".to_vec()), Term::Integer(0)),
+    (atom("admin"), atom("true")),
+    (atom("__struct__"), atom("Elixir.User")),
+  ]);
+  assert_eq!(
+    decode::<User>(encode(&mixed).as_bytes()).unwrap(),
+    User { id: 4, admin: true }
+  );
+
+  let plain = Term::Map(vec![
+    (atom("id"), Term::Integer(4)),
+    (atom("admin"), atom("true")),
+  ]);
+  assert!(decode::<User>(encode(&plain).as_bytes()).is_err());
+  let other = Term::Map(vec![
+    (atom("__struct__"), atom("Elixir.Admin")),
+    (atom("id"), Term::Integer(4)),
+    (atom("admin"), atom("true")),
+  ]);
+  assert!(decode::<User>(encode(&other).as_bytes()).is_err());
+}
+
+#[test]
+fn derives_atom_enums() {
+  round_trip(&Level::Info, atom("info"));
+  round_trip(&Level::NotFound, atom("not_found"));
+  round_trip(&Level::Error, atom("ERROR"));
+  assert!(decode::<Level>(encode(&atom("error")).as_bytes()).is_err());
+  assert!(decode::<Level>(encode(&Term::Integer(1)).as_bytes()).is_err());
+}
+
+#[test]
+fn derives_enum_variants() {
+  round_trip(&Message::Ping, atom("ping"));
+  round_trip(
+    &Message::Echo("hi".to_owned()),
+    Term::Tuple(vec![atom("echo"), Term::Binary(b"hi".to_vec())]),
+  );
+  round_trip(
+    &Message::Move(-1, 1),
+    Term::Tuple(vec![atom("move"), Term::Integer(-1), Term::Integer(1)]),
+  );
+  round_trip(
+    &Message::Resize {
+      width: 2,
+      height: 3,
+    },
+    Term::Tuple(vec![atom("resize"), Term::Integer(2), Term::Integer(3)]),
+  );
+
+  let wrong_arity = Term::Tuple(vec![atom("move"), Term::Integer(-1)]);
+  assert!(decode::<Message>(encode(&wrong_arity).as_bytes()).is_err());
+  let unknown = Term::Tuple(vec![atom("jump"), Term::Integer(1)]);
+  assert!(decode::<Message>(encode(&unknown).as_bytes()).is_err());
+}
@@ -0,0 +1,60 @@
+use ei_sys::{Error, XBuff};
+
+#[test]
+fn failed_elements_are_not_counted() {
+  let mut buf = XBuff::new().unwrap();
+  buf
+    .encoder()
+    .tuple(2, |e| {
+      e.long(0)?;
+      assert_eq!(
+        e.tuple(1, |_| Err(Error::Encode)).err(),
+        Some(Error::Encode)
+      );
+      assert_eq!(e.double(f64::NAN).err(), Some(Error::Encode));
+      e.long(1)?;
+      Ok(())
+    })
+    .unwrap();
+}
+
+#[test]
+fn encoder_is_usable_after_an_error() {
+  let mut buf = XBuff::new().unwrap();
+  let mut encoder = buf.encoder();
+  assert!(encoder.list(0..2, |_, _| Err(Error::Encode)).is_err());
+  encoder.tuple(1, |e| e.atom("ok").map(drop)).unwrap();
+}
+
+#[cfg(debug_assertions)]
+#[test]
+#[should_panic(expected = "different number of elements than its arity")]
+fn too_few_elements_panic() {
+  let mut buf = XBuff::new().unwrap();
+  let _ = buf.encoder().tuple(2, |e| e.long(0).map(drop));
+}
+
+#[cfg(debug_assertions)]
+#[test]
+#[should_panic(expected = "different number of elements than its arity")]
+fn too_many_elements_panic() {
+  let mut buf = XBuff::new().unwrap();
+  let _ = buf.encoder().map_with(1, |e| {
+    e.atom("key")?.atom("value")?.atom("extra")?;
+    Ok(())
+  });
+}
+
+#[test]
+fn version_byte_is_counted_in_bytes_only() {
+  let mut buf = XBuff::with_version().unwrap();
+  assert!(buf.is_empty());
+  assert_eq!(buf.byte_len(), 1);
+  assert_eq!(buf.byte_len(), buf.as_bytes().len());
+
+  buf.encoder().atom("ok").unwrap();
+  assert!(!buf.is_empty());
+  buf.clear();
+  assert!(buf.is_empty());
+  assert_eq!(buf.as_bytes(), [131]);
+}
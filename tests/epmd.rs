@@ -0,0 +1,62 @@
+#![cfg(feature = "test-support")]
+
+use ei_sys::{test_support::EpmdServer, Error, NodeInfo, NodeType};
+use std::{thread, time::Duration};
+
+#[test]
+fn register_and_look_up() {
+  let server = EpmdServer::start().unwrap();
+  let epmd = server.client();
+  assert_eq!(epmd.port_please("foo").unwrap(), None);
+
+  let info = NodeInfo::new("foo", 4242);
+  let registration = epmd.register(&info).unwrap();
+  assert_ne!(registration.creation(), 0);
+  assert_eq!(epmd.port_please("foo").unwrap(), Some(info.clone()));
+  assert_eq!(epmd.names().unwrap(), vec![("foo".to_owned(), 4242)]);
+
+  let dump = epmd.dump().unwrap();
+  assert_eq!(dump.len(), 1);
+  assert_eq!(dump[0].name, "foo");
+  assert_eq!(dump[0].port, 4242);
+  assert!(dump[0].active);
+}
+
+#[test]
+fn refuse_taken_names() {
+  let server = EpmdServer::start().unwrap();
+  let epmd = server.client();
+  let _registration = epmd.register(&NodeInfo::new("foo", 4242)).unwrap();
+  assert_eq!(
+    epmd.register(&NodeInfo::new("foo", 4243)).unwrap_err(),
+    Error::InvalidArgument
+  );
+}
+
+#[test]
+fn unregister_on_close() {
+  let server = EpmdServer::start().unwrap();
+  let epmd = server.client();
+  let mut info = NodeInfo::new("bar", 4242);
+  info.node_type = NodeType::Normal;
+  let registration = epmd.register(&info).unwrap();
+  assert_eq!(server.nodes(), vec![info]);
+
+  drop(registration);
+  for _ in 0..100 {
+    if epmd.port_please("bar").unwrap().is_none() {
+      return;
+    }
+    thread::sleep(Duration::from_millis(10));
+  }
+  panic!("bar is still registered");
+}
+
+#[test]
+fn bind_to_a_given_port() {
+  // A port that was free a moment ago, rather than a hard-coded one that may be taken.
+  let port = EpmdServer::start().unwrap().port();
+  let server = EpmdServer::bind(port).unwrap();
+  assert_eq!(server.port(), port);
+  assert_eq!(server.client().port_please("foo").unwrap(), None);
+}
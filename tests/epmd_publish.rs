@@ -0,0 +1,65 @@
+#![cfg(all(feature = "test-support", unix))]
+
+use ei_sys::{test_support::EpmdServer, CNode, NodeInfo};
+use std::{
+  thread,
+  time::{Duration, Instant},
+};
+
+const COOKIE: &str = "cookie";
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+fn node(alive_name: &str) -> CNode {
+  EpmdServer::shared();
+  CNode::builder(alive_name, COOKIE)
+    .host_name("localhost")
+    .build()
+    .unwrap()
+}
+
+fn published(alive_name: &str) -> Option<NodeInfo> {
+  EpmdServer::shared()
+    .nodes()
+    .into_iter()
+    .find(|info| info.name == alive_name)
+}
+
+#[test]
+fn publish_connect_and_unpublish() {
+  let server = node("published");
+  let mut listener = server.listen(0).unwrap();
+  let info = published("published").unwrap();
+  assert_eq!(info.port, listener.port());
+  assert_eq!(
+    EpmdServer::shared()
+      .client()
+      .port_please("published")
+      .unwrap(),
+    Some(info)
+  );
+
+  // The client asks the server for the port of the node, then connects to it.
+  let client = node("publish_client");
+  let name = server.node_name().to_owned();
+  let connector = thread::spawn(move || client.connect(&name).unwrap());
+  let (connection, peer) = listener.accept_timeout(TIMEOUT).unwrap();
+  assert_eq!(peer.node_name(), "publish_client@localhost");
+  assert_eq!(connection.peer_name(), "publish_client@localhost");
+  let connection = connector.join().unwrap();
+  assert_eq!(connection.peer_name(), "published@localhost");
+
+  // The server notices that the connection the node was published on is closed.
+  drop(listener);
+  let start = Instant::now();
+  while published("published").is_some() {
+    assert!(start.elapsed() < TIMEOUT, "still published");
+    thread::sleep(Duration::from_millis(10));
+  }
+  assert_eq!(
+    EpmdServer::shared()
+      .client()
+      .port_please("published")
+      .unwrap(),
+    None
+  );
+}
@@ -0,0 +1,124 @@
+#![cfg(feature = "test-support")]
+
+use ei_sys::{
+  test_support::ErlNode, Connection, Decoder, FromTerm, Received, RpcError, Term, ToTerm, XBuff,
+};
+use std::time::Duration;
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Starts an Erlang node, or returns `None` if `erl` is missing so that the test is skipped.
+fn start() -> Option<ErlNode> {
+  if !ErlNode::available() {
+    eprintln!("skipping: erl is not in the PATH");
+    return None;
+  }
+  Some(ErlNode::start().unwrap())
+}
+
+fn encode<T: ToTerm>(value: T) -> XBuff {
+  let mut buf = XBuff::with_version().unwrap();
+  value.to_term(&mut buf.encoder()).unwrap();
+  buf
+}
+
+/// Receives the next message on `connection` and decodes it.
+fn receive<T: FromTerm>(connection: &mut Connection) -> T {
+  loop {
+    if let Received::Message { payload, .. } = connection.receive_timeout(TIMEOUT).unwrap() {
+      let mut decoder = Decoder::from_x_buff(&payload).unwrap();
+      return T::from_term(&mut decoder).unwrap();
+    }
+  }
+}
+
+#[test]
+fn connect() {
+  let erl = match start() {
+    Some(erl) => erl,
+    None => return,
+  };
+  let connection = erl.connect().unwrap();
+  assert_eq!(connection.peer_name(), erl.node_name());
+}
+
+#[test]
+fn send() {
+  let erl = match start() {
+    Some(erl) => erl,
+    None => return,
+  };
+  let mut connection = erl.connect().unwrap();
+  let echo: ei_sys::erlang_pid = connection
+    .rpc("erlang", "whereis", (Term::Atom("echo".into()),), TIMEOUT)
+    .unwrap();
+  let pid = connection.node().pid().clone();
+  connection.send(&echo, &encode((&pid, 42))).unwrap();
+  assert_eq!(receive::<i64>(&mut connection), 42);
+}
+
+#[test]
+fn reg_send() {
+  let erl = match start() {
+    Some(erl) => erl,
+    None => return,
+  };
+  let mut connection = erl.connect().unwrap();
+  let pid = connection.node().pid().clone();
+  let message = encode((&pid, "hello"));
+  connection.reg_send("echo", &message).unwrap();
+  assert_eq!(receive::<String>(&mut connection), "hello");
+}
+
+#[test]
+fn rpc() {
+  let erl = match start() {
+    Some(erl) => erl,
+    None => return,
+  };
+  let mut connection = erl.connect().unwrap();
+  let abs: i64 = connection.rpc("erlang", "abs", (-5,), TIMEOUT).unwrap();
+  assert_eq!(abs, 5);
+  let seq: Vec<i64> = connection.rpc("lists", "seq", (1, 3), TIMEOUT).unwrap();
+  assert_eq!(seq, [1, 2, 3]);
+  let undefined: Result<Term, _> = connection.rpc("no_such_module", "f", (), TIMEOUT);
+  assert!(matches!(undefined, Err(RpcError::BadRpc(_))));
+}
+
+#[test]
+fn publish_and_accept() {
+  let erl = match start() {
+    Some(erl) => erl,
+    None => return,
+  };
+  let node = erl.cnode().unwrap();
+  let mut listener = node.listen(0).unwrap();
+
+  // Have the Erlang node connect back by sending to a name on the C node.
+  let mut connection = erl.connect().unwrap();
+  let to = Term::Tuple(vec![
+    Term::Atom("any".into()),
+    Term::Atom(node.node_name().into()),
+  ]);
+  let _: Term = connection
+    .rpc("erlang", "send", (to, Term::Atom("hello".into())), TIMEOUT)
+    .unwrap();
+
+  let (mut accepted, peer) = listener.accept_timeout(TIMEOUT).unwrap();
+  assert_eq!(peer.node_name(), erl.node_name());
+  assert_eq!(accepted.peer_name(), erl.node_name());
+  assert_eq!(receive::<Term>(&mut accepted), Term::Atom("hello".into()));
+}
+
+#[test]
+fn shutdown() {
+  let mut erl = match start() {
+    Some(erl) => erl,
+    None => return,
+  };
+  let mut connection = erl.connect().unwrap();
+  let stopped: Term = connection.rpc("init", "stop", (), TIMEOUT).unwrap();
+  assert_eq!(stopped, Term::Atom("ok".into()));
+  assert!(erl.wait_timeout(TIMEOUT).unwrap());
+  assert!(connection.receive_timeout(TIMEOUT).is_err());
+}
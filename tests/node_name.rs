@@ -0,0 +1,55 @@
+use ei_sys::{Error, NameKind, NodeName, EI_MAXALIVELEN, EI_MAXHOSTNAMELEN};
+
+#[test]
+fn parses_short_and_long_names() {
+  let name: NodeName = "node@host".parse().unwrap();
+  assert_eq!(name.alive(), "node");
+  assert_eq!(name.host(), "host");
+  assert_eq!(name.as_str(), "node@host");
+  assert_eq!(name.as_c_str().to_bytes(), b"node@host");
+  assert_eq!(name.kind(), NameKind::Short);
+
+  let name = NodeName::parse("node@host.example.com").unwrap();
+  assert_eq!(name.host_c_str().to_bytes(), b"host.example.com");
+  assert_eq!(name.kind(), NameKind::Long);
+  assert_eq!(
+    NodeName::parse("node@127.0.0.1").unwrap().kind(),
+    NameKind::Long
+  );
+}
+
+#[test]
+fn rejects_invalid_names() {
+  let alive = "a".repeat(EI_MAXALIVELEN + 1);
+  let host = "h".repeat(EI_MAXHOSTNAMELEN + 1);
+  for name in [
+    "node",
+    "@host",
+    "node@",
+    "no de@host",
+    "node@ho@st",
+    "node@host\0",
+    &format!("{}@host", alive),
+    &format!("node@{}", host),
+  ] {
+    assert_eq!(
+      NodeName::parse(name),
+      Err(Error::InvalidArgument),
+      "{}",
+      name
+    );
+  }
+  assert!(NodeName::new(&alive[1..], &host[1..]).is_ok());
+}
+
+#[cfg(unix)]
+#[test]
+fn defaults_to_the_local_host() {
+  let name = NodeName::short("node").unwrap();
+  assert_eq!(name.alive(), "node");
+  assert_eq!(name.kind(), NameKind::Short);
+  // The local host may not have a fully qualified name.
+  if let Ok(name) = NodeName::long("node") {
+    assert_eq!(name.kind(), NameKind::Long);
+  }
+}
@@ -0,0 +1,114 @@
+#![cfg(all(feature = "test-support", unix))]
+
+use ei_sys::{test_support::EpmdServer, CNode, Connection, NodePool, Received, TermRef, XBuff};
+use std::{thread, time::Duration};
+
+const COOKIE: &str = "cookie";
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+fn node(alive_name: &str) -> CNode {
+  EpmdServer::shared();
+  CNode::builder(alive_name, COOKIE)
+    .host_name("localhost")
+    .build()
+    .unwrap()
+}
+
+fn atom(name: &str) -> XBuff {
+  let mut buf = XBuff::with_version().unwrap();
+  buf.encoder().atom(name).unwrap();
+  buf
+}
+
+/// Receives the next message on `connection`, and returns it if it is an atom, or `None` if it is
+/// a tick.
+fn receive(connection: &mut Connection) -> Option<String> {
+  match connection.receive_timeout(TIMEOUT).unwrap() {
+    Received::Message { payload, .. } => {
+      let payload = TermRef::from_x_buff(&payload).unwrap();
+      Some(payload.as_atom().unwrap().to_owned())
+    }
+    Received::Tick => None,
+  }
+}
+
+#[test]
+fn reuse_connections() {
+  let server = node("pool_reuse_server");
+  let mut listener = server.listen(0).unwrap();
+  let pool = NodePool::builder(&node("pool_reuse_client")).build();
+  let name = server.node_name();
+
+  let accepted = thread::spawn(move || listener.accept_timeout(TIMEOUT).unwrap().0);
+  pool.reg_send(name, "server", &atom("one")).unwrap();
+  let mut connection = accepted.join().unwrap();
+  pool.reg_send(name, "server", &atom("two")).unwrap();
+  assert_eq!(pool.connected_nodes(), [name]);
+
+  assert_eq!(receive(&mut connection).as_deref(), Some("one"));
+  assert_eq!(receive(&mut connection).as_deref(), Some("two"));
+}
+
+#[test]
+fn reopen_lost_connections() {
+  let server = node("pool_lost_server");
+  let mut listener = server.listen(0).unwrap();
+  let pool = NodePool::builder(&node("pool_lost_client")).build();
+  let name = server.node_name();
+
+  let accepted = thread::spawn(move || (listener.accept_timeout(TIMEOUT).unwrap().0, listener));
+  pool.reg_send(name, "server", &atom("one")).unwrap();
+  let (connection, mut listener) = accepted.join().unwrap();
+  drop(connection);
+
+  let accepted = thread::spawn(move || listener.accept_timeout(TIMEOUT).unwrap().0);
+  pool.reg_send(name, "server", &atom("two")).unwrap();
+  let mut connection = accepted.join().unwrap();
+  assert_eq!(receive(&mut connection).as_deref(), Some("two"));
+}
+
+#[test]
+fn answer_ticks_when_used() {
+  let server = node("pool_ticks_server");
+  let mut listener = server.listen(0).unwrap();
+  let ticktime = Duration::from_millis(200);
+  let pool = NodePool::builder(&node("pool_ticks_client"))
+    .net_ticktime(ticktime)
+    .build();
+  let name = server.node_name();
+
+  let accepted = thread::spawn(move || listener.accept_timeout(TIMEOUT).unwrap().0);
+  pool.reg_send(name, "server", &atom("one")).unwrap();
+  let mut connection = accepted.join().unwrap();
+  assert_eq!(receive(&mut connection).as_deref(), Some("one"));
+
+  // The other node ticks, and the pool answers before the next message.
+  connection.set_net_ticktime(Some(Duration::from_millis(40)));
+  thread::sleep(Duration::from_millis(15));
+  connection.heartbeat().unwrap();
+  connection.set_net_ticktime(None);
+  thread::sleep(ticktime / 2);
+  pool.reg_send(name, "server", &atom("two")).unwrap();
+  assert_eq!(receive(&mut connection), None);
+  assert_eq!(receive(&mut connection).as_deref(), Some("two"));
+}
+
+#[test]
+fn close_idle_connections() {
+  let server = node("pool_idle_server");
+  let mut listener = server.listen(0).unwrap();
+  let pool = NodePool::builder(&node("pool_idle_client"))
+    .idle_timeout(Duration::from_millis(50))
+    .build();
+  let name = server.node_name();
+
+  let accepted = thread::spawn(move || listener.accept_timeout(TIMEOUT).unwrap().0);
+  pool.reg_send(name, "server", &atom("one")).unwrap();
+  let _connection = accepted.join().unwrap();
+  assert_eq!(pool.close_idle(), 0);
+  assert_eq!(pool.connected_nodes(), [name]);
+
+  thread::sleep(Duration::from_millis(60));
+  assert_eq!(pool.close_idle(), 1);
+  assert!(pool.connected_nodes().is_empty());
+}
@@ -0,0 +1,193 @@
+#![cfg(all(feature = "std", unix))]
+
+use ei_sys::{erlang_pid, CNode, Connection, RpcError, Term, TermRef, ToTerm, XBuff};
+use std::{
+  io::{Read, Write},
+  os::unix::{io::IntoRawFd, net::UnixStream},
+  time::Duration,
+};
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Returns a connection whose other end is a socket that plays the other node.
+fn connect() -> (Connection, UnixStream) {
+  let node = CNode::builder("rpc", "cookie")
+    .host_name("localhost")
+    .build()
+    .unwrap();
+  let (ours, theirs) = UnixStream::pair().unwrap();
+  let connection = unsafe { Connection::from_raw_fd(node, ours.into_raw_fd()) };
+  (connection, theirs)
+}
+
+fn encode<T: ToTerm>(value: T) -> XBuff {
+  let mut buf = XBuff::with_version().unwrap();
+  value.to_term(&mut buf.encoder()).unwrap();
+  buf
+}
+
+/// Reads the next call sent to rex and returns the pid that the reply must be sent to.
+fn read_call(peer: &mut UnixStream) -> erlang_pid {
+  let mut len = [0; 4];
+  peer.read_exact(&mut len).unwrap();
+  let mut frame = vec![0; u32::from_be_bytes(len) as usize];
+  peer.read_exact(&mut frame).unwrap();
+  assert_eq!(frame[0], b'p');
+
+  // The control message and the payload each start with the version byte.
+  let (control, payload) = (2..frame.len())
+    .filter(|&i| frame[i] == 131)
+    .find_map(|i| {
+      Some((
+        TermRef::new(&frame[1..i]).ok()?,
+        TermRef::new(&frame[i..]).ok()?,
+      ))
+    })
+    .unwrap();
+  let mut control = control.tuple().unwrap();
+  assert_eq!(control.next().unwrap().as_long().unwrap(), 6);
+  assert_eq!(control.nth(2).unwrap().as_atom().unwrap(), "rex");
+  payload.tuple().unwrap().next().unwrap().as_pid().unwrap()
+}
+
+/// Sends `{rex, result}` to `to`, as rex replies to calls.
+fn send_reply(peer: &mut UnixStream, to: &erlang_pid, result: &XBuff) {
+  let mut control = XBuff::with_version().unwrap();
+  control
+    .encoder()
+    .tuple(3, |e| e.long(2)?.atom("")?.pid(to).map(drop))
+    .unwrap();
+  // The result is already encoded, so the tuple around it is written by hand.
+  let mut payload = XBuff::with_version().unwrap();
+  payload.extend_from_slice(b"h\x02w\x03rex").unwrap();
+  payload.extend_from_slice(&result.as_bytes()[1..]).unwrap();
+
+  let len = 1 + control.byte_len() + payload.byte_len();
+  peer.write_all(&(len as u32).to_be_bytes()).unwrap();
+  peer.write_all(b"p").unwrap();
+  peer.write_all(control.as_bytes()).unwrap();
+  peer.write_all(payload.as_bytes()).unwrap();
+}
+
+#[test]
+fn rpc_from_matches_replies_to_calls() {
+  let (mut connection, mut peer) = connect();
+  let first = connection.rpc_to("erlang", "node", ()).unwrap();
+  let second = connection.rpc_to("lists", "seq", (1, 3)).unwrap();
+  assert_ne!(first, second);
+
+  let first_pid = read_call(&mut peer);
+  let second_pid = read_call(&mut peer);
+  send_reply(&mut peer, &second_pid, &encode(2i64));
+  send_reply(&mut peer, &first_pid, &encode(1i64));
+
+  let reply = connection.rpc_from(TIMEOUT).unwrap();
+  assert_eq!(reply.id(), second);
+  assert_eq!(reply.decode::<i64>().unwrap(), 2);
+  let reply = connection.rpc_from(TIMEOUT).unwrap();
+  assert_eq!(reply.id(), first);
+  assert_eq!(reply.decode::<i64>().unwrap(), 1);
+  assert!(matches!(
+    connection.rpc_from(Duration::from_millis(10)),
+    Err(RpcError::Timeout)
+  ));
+}
+
+#[test]
+fn rpc_keeps_the_replies_to_other_calls() {
+  let (mut connection, mut peer) = connect();
+  let other = connection.rpc_to("erlang", "node", ()).unwrap();
+  let rex = std::thread::spawn(move || {
+    let other_pid = read_call(&mut peer);
+    let pid = read_call(&mut peer);
+    send_reply(&mut peer, &other_pid, &encode(1i64));
+    send_reply(&mut peer, &pid, &encode(2i64));
+    peer
+  });
+
+  assert_eq!(
+    connection
+      .rpc::<_, i64>("erlang", "node", (), TIMEOUT)
+      .unwrap(),
+    2
+  );
+  let _peer = rex.join().unwrap();
+  let reply = connection.rpc_from(TIMEOUT).unwrap();
+  assert_eq!(reply.id(), other);
+  assert_eq!(reply.decode::<i64>().unwrap(), 1);
+}
+
+#[cfg(feature = "tokio")]
+#[test]
+fn async_rpc_keeps_the_replies_to_other_calls() {
+  let (connection, mut peer) = connect();
+  let runtime = tokio::runtime::Builder::new_current_thread()
+    .enable_all()
+    .build()
+    .unwrap();
+  runtime.block_on(async {
+    let mut connection = ei_sys::AsyncConnection::new(connection).unwrap();
+    let other = connection.get_mut().rpc_to("erlang", "node", ()).unwrap();
+    let rex = std::thread::spawn(move || {
+      let other_pid = read_call(&mut peer);
+      let pid = read_call(&mut peer);
+      send_reply(&mut peer, &other_pid, &encode(1i64));
+      send_reply(&mut peer, &pid, &encode(2i64));
+      peer
+    });
+
+    assert_eq!(
+      connection
+        .rpc::<_, i64>("erlang", "node", (), TIMEOUT)
+        .await
+        .unwrap(),
+      2
+    );
+    let _peer = rex.join().unwrap();
+    let reply = connection.get_mut().rpc_from(TIMEOUT).unwrap();
+    assert_eq!(reply.id(), other);
+    assert_eq!(reply.decode::<i64>().unwrap(), 1);
+  });
+}
+
+#[test]
+fn rpc_decodes_badrpc() {
+  let (mut connection, mut peer) = connect();
+  let id = connection.rpc_to("erlang", "error", ()).unwrap();
+  let pid = read_call(&mut peer);
+  let mut reason = XBuff::with_version().unwrap();
+  reason
+    .encoder()
+    .tuple(2, |e| {
+      e.atom("badrpc")?
+        .tuple(2, |e| e.atom("EXIT")?.atom("boom").map(drop))
+        .map(drop)
+    })
+    .unwrap();
+  send_reply(&mut peer, &pid, &reason);
+
+  let reply = connection.rpc_from(TIMEOUT).unwrap();
+  assert_eq!(reply.id(), id);
+  match reply.decode::<i64>() {
+    Err(RpcError::BadRpc(reason)) => {
+      let reason = TermRef::from_x_buff(&reason).unwrap();
+      let mut elements = reason.tuple().unwrap();
+      assert_eq!(elements.next().unwrap().as_atom().unwrap(), "EXIT");
+      assert_eq!(elements.next().unwrap().as_atom().unwrap(), "boom");
+    }
+    other => panic!("{:?}", other),
+  }
+
+  // A result that merely looks like badrpc is still a result.
+  let id = connection.rpc_to("erlang", "self", ()).unwrap();
+  let pid = read_call(&mut peer);
+  let result = Term::Tuple(vec![
+    Term::Atom("badrpc".to_owned()),
+    Term::Integer(1),
+    Term::Integer(2),
+  ]);
+  send_reply(&mut peer, &pid, &encode(&result));
+  let reply = connection.rpc_from(TIMEOUT).unwrap();
+  assert_eq!(reply.id(), id);
+  assert_eq!(reply.decode::<Term>().unwrap(), result);
+}
@@ -0,0 +1,178 @@
+#![cfg(feature = "alloc")]
+
+use ei_sys::{BitString, Pid, Port, Reference, Term};
+use proptest::{collection::vec, prelude::*};
+
+const NODE: &str = "node@localhost";
+
+/// Returns the encoding of `term`, without the version magic byte.
+fn encode(term: &Term) -> Vec<u8> {
+  term.to_x_buff().unwrap().as_bytes()[1..].to_vec()
+}
+
+fn closure() -> impl Strategy<Value = Term> {
+  (
+    any::<u8>(),
+    any::<[u8; 16]>(),
+    any::<u32>(),
+    "[a-z_]{1,16}",
+    (any::<i32>(), any::<i32>(), pid()),
+    vec(plain(), 0..4),
+  )
+    .prop_map(
+      |(arity, md5, index, module, (old_index, old_uniq, pid), free_vars)| {
+        let mut fields = vec![arity];
+        fields.extend_from_slice(&md5);
+        fields.extend_from_slice(&index.to_be_bytes());
+        fields.extend_from_slice(&(free_vars.len() as u32).to_be_bytes());
+        fields.extend(encode(&Term::Atom(module)));
+        fields.extend(encode(&Term::Integer(old_index.into())));
+        fields.extend(encode(&Term::Integer(old_uniq.into())));
+        fields.extend(encode(&pid));
+        for free_var in &free_vars {
+          fields.extend(encode(free_var));
+        }
+
+        let mut bytes = vec![b'p'];
+        bytes.extend_from_slice(&(4 + fields.len() as u32).to_be_bytes());
+        bytes.extend(fields);
+        Term::Closure(bytes)
+      },
+    )
+}
+
+fn pid() -> impl Strategy<Value = Term> {
+  (0..0x8000u32, 0..0x2000u32, 0..4u32).prop_map(|(num, serial, creation)| {
+    Term::Pid(Pid {
+      node: NODE.to_owned(),
+      num,
+      serial,
+      creation,
+    })
+  })
+}
+
+fn leaf() -> impl Strategy<Value = Term> {
+  prop_oneof![4 => plain(), 1 => closure()]
+}
+
+/// Returns the terms that are neither compound nor funs.
+fn plain() -> impl Strategy<Value = Term> {
+  prop_oneof![
+    any::<i64>().prop_map(Term::Integer),
+    (any::<bool>(), vec(any::<u8>(), 8..40), 1..=255u8).prop_map(
+      |(negative, mut magnitude, top)| {
+        magnitude.push(top);
+        Term::from_big(negative, &magnitude)
+      }
+    ),
+    any::<f64>()
+      .prop_filter("finite", |f| f.is_finite())
+      .prop_map(Term::Float),
+    "[a-zA-Z0-9_@.é]{0,64}".prop_map(Term::Atom),
+    vec(any::<u8>(), 0..64).prop_map(Term::Binary),
+    (vec(any::<u8>(), 1..16), 1..8u8)
+      .prop_map(|(bytes, bits)| Term::BitString(BitString { bytes, bits })),
+    vec(any::<u8>(), 1..64).prop_map(Term::String),
+    pid(),
+    (0..0x1000_0000u32, 0..4u32).prop_map(|(id, creation)| {
+      Term::Port(Port {
+        node: NODE.to_owned(),
+        id,
+        creation,
+      })
+    }),
+    (0..0x4_0000u32, vec(any::<u32>(), 0..3), 0..4u32).prop_map(|(first, rest, creation)| {
+      let mut ids = vec![first];
+      ids.extend(rest);
+      Term::Ref(Reference {
+        node: NODE.to_owned(),
+        ids,
+        creation,
+      })
+    }),
+    ("[a-z_]{1,16}", "[a-z_]{1,16}", any::<u8>()).prop_map(|(module, function, arity)| {
+      Term::Export {
+        module,
+        function,
+        arity,
+      }
+    }),
+  ]
+}
+
+fn term() -> impl Strategy<Value = Term> {
+  leaf().prop_recursive(4, 64, 8, |inner| {
+    prop_oneof![
+      vec(inner.clone(), 0..8).prop_map(Term::List),
+      // A tail that is a list would make a proper list, or a longer improper one.
+      (
+        vec(inner.clone(), 1..8),
+        leaf().prop_filter("not a list", |tail| !matches!(tail, Term::String(_)))
+      )
+        .prop_map(|(elements, tail)| Term::ImproperList(elements, Box::new(tail))),
+      vec(inner.clone(), 0..8).prop_map(Term::Tuple),
+      vec((inner.clone(), inner), 0..8).prop_map(Term::Map),
+    ]
+  })
+}
+
+proptest! {
+  #[test]
+  fn decode_inverts_encode(t in term()) {
+    let buf = t.to_x_buff().unwrap();
+    prop_assert_eq!(Term::from_x_buff(&buf).unwrap(), t);
+  }
+
+  #[test]
+  fn encode_inverts_decode(t in term()) {
+    let buf = t.to_x_buff().unwrap();
+    let decoded = Term::from_bytes(buf.as_bytes()).unwrap();
+    let reencoded = decoded.to_x_buff().unwrap();
+    prop_assert_eq!(reencoded.as_bytes(), buf.as_bytes());
+  }
+
+  #[test]
+  fn decode_rejects_truncated_input(t in term(), cut in any::<prop::sample::Index>()) {
+    let buf = t.to_x_buff().unwrap();
+    let bytes = buf.as_bytes();
+    let len = 1 + cut.index(bytes.len() - 1);
+    prop_assume!(len < bytes.len());
+    prop_assert!(Term::from_bytes(&bytes[..len]).is_err());
+  }
+}
+
+/// Returns `depth` nested 1-tuples around the empty list.
+fn nested_tuples(depth: usize) -> Vec<u8> {
+  let mut bytes = vec![131];
+  for _ in 0..depth {
+    bytes.extend_from_slice(&[104, 1]);
+  }
+  bytes.push(106);
+  bytes
+}
+
+#[test]
+fn decode_rejects_deep_nesting() {
+  let mut term = Term::List(vec![]);
+  for _ in 0..256 {
+    term = Term::Tuple(vec![term]);
+  }
+  assert_eq!(Term::from_bytes(&nested_tuples(256)).unwrap(), term);
+  assert_eq!(term.to_x_buff().unwrap().as_bytes(), nested_tuples(256));
+  assert_eq!(
+    Term::from_bytes(&nested_tuples(257)),
+    Err(ei_sys::Error::Decode)
+  );
+  assert_eq!(
+    Term::from_bytes(&nested_tuples(100_000)),
+    Err(ei_sys::Error::Decode)
+  );
+
+  // Lists and maps count too.
+  let mut bytes = vec![131];
+  for _ in 0..300 {
+    bytes.extend_from_slice(&[116, 0, 0, 0, 1, 106, 108, 0, 0, 0, 1]);
+  }
+  assert_eq!(Term::from_bytes(&bytes), Err(ei_sys::Error::Decode));
+}
@@ -0,0 +1,201 @@
+#![cfg(feature = "alloc")]
+
+use ei_sys::{Decoder, ExtTag, Pid, Term, TermRef, MAXATOMLEN_UTF8};
+use proptest::{collection::vec, prelude::*};
+use std::convert::TryFrom;
+
+/// Checks every accessor of `view` that applies to `term` against it, recursively.
+fn check(view: TermRef, term: &Term) {
+  assert_eq!(view.to_term().unwrap(), *term);
+  match term {
+    Term::Integer(value) => {
+      assert_eq!(view.as_longlong().unwrap(), *value);
+      assert_eq!(view.as_ulonglong().ok(), u64::try_from(*value).ok());
+    }
+    Term::Float(value) => assert_eq!(view.as_double().unwrap(), *value),
+    Term::Atom(name) => {
+      assert!(view.tag().is_atom());
+      assert_eq!(view.as_atom().unwrap(), name);
+      let boolean = match name.as_str() {
+        "true" => Some(true),
+        "false" => Some(false),
+        _ => None,
+      };
+      assert_eq!(view.as_boolean().ok(), boolean);
+    }
+    Term::Binary(bytes) => assert_eq!(view.as_binary().unwrap(), &bytes[..]),
+    Term::String(bytes) => assert_eq!(view.as_string().unwrap(), &bytes[..]),
+    Term::List(elements) => {
+      let mut list = view.list().unwrap();
+      assert_eq!(list.len(), elements.len());
+      for element in elements {
+        check(list.next().unwrap(), element);
+      }
+      assert_eq!(list.len(), 0);
+      if elements.is_empty() {
+        assert_eq!(view.as_string().unwrap(), b"");
+        assert!(list.tail().is_none());
+      } else {
+        assert_eq!(list.tail().unwrap().as_bytes(), [b'j']);
+      }
+    }
+    Term::ImproperList(elements, tail) => {
+      let list = view.list().unwrap();
+      assert_eq!(list.len(), elements.len());
+      for (element, expected) in list.clone().zip(elements) {
+        check(element, expected);
+      }
+      check(list.tail().unwrap(), tail);
+    }
+    Term::Tuple(elements) => {
+      let tuple = view.tuple().unwrap();
+      assert_eq!(tuple.len(), elements.len());
+      for (element, expected) in tuple.clone().zip(elements) {
+        check(element, expected);
+      }
+      assert!(tuple.tail().is_none());
+    }
+    Term::Map(entries) => {
+      let map = view.map().unwrap();
+      assert_eq!(map.len(), entries.len());
+      for ((key, value), (expected_key, expected_value)) in map.zip(entries) {
+        check(key, expected_key);
+        check(value, expected_value);
+      }
+    }
+    Term::Pid(pid) => {
+      assert_eq!(Pid::from_raw(&view.as_pid().unwrap()).unwrap(), *pid);
+      assert!(view.as_port().is_err());
+    }
+    _ => {}
+  }
+}
+
+fn term() -> impl Strategy<Value = Term> {
+  let leaf = prop_oneof![
+    any::<i64>().prop_map(Term::Integer),
+    any::<f64>()
+      .prop_filter("finite", |f| f.is_finite())
+      .prop_map(Term::Float),
+    "[a-z_é]{0,8}|true|false".prop_map(Term::Atom),
+    vec(any::<u8>(), 0..16).prop_map(Term::Binary),
+    vec(any::<u8>(), 1..16).prop_map(Term::String),
+    (0..0x8000u32, 0..0x2000u32).prop_map(|(num, serial)| {
+      Term::Pid(Pid {
+        node: "node@host".into(),
+        num,
+        serial,
+        creation: 1,
+      })
+    }),
+  ];
+  leaf.prop_recursive(3, 32, 6, |inner| {
+    prop_oneof![
+      vec(inner.clone(), 0..6).prop_map(Term::List),
+      (vec(inner.clone(), 1..6), any::<i64>())
+        .prop_map(|(elements, tail)| Term::ImproperList(elements, Box::new(Term::Integer(tail)))),
+      vec(inner.clone(), 0..6).prop_map(Term::Tuple),
+      vec((inner.clone(), inner), 0..6).prop_map(Term::Map),
+    ]
+  })
+}
+
+proptest! {
+  #[test]
+  fn accessors_agree_with_term(t in term()) {
+    let buf = t.to_x_buff().unwrap();
+    check(TermRef::from_x_buff(&buf).unwrap(), &t);
+  }
+
+  #[test]
+  fn rejects_truncated_input(t in term(), cut in any::<prop::sample::Index>()) {
+    let buf = t.to_x_buff().unwrap();
+    let bytes = buf.as_bytes();
+    let len = 1 + cut.index(bytes.len() - 1);
+    prop_assume!(len < bytes.len());
+    prop_assert!(TermRef::new(&bytes[..len]).is_err());
+  }
+}
+
+#[test]
+fn reads_every_atom_encoding() {
+  let encodings: [(&[u8], ExtTag); 4] = [
+    (b"d\x00\x03abc", ExtTag::Atom),
+    (b"s\x03abc", ExtTag::SmallAtom),
+    (b"v\x00\x03abc", ExtTag::AtomUtf8),
+    (b"w\x03abc", ExtTag::SmallAtomUtf8),
+  ];
+  for (bytes, tag) in encodings {
+    assert_eq!(bytes[0], tag as u8);
+    let view = TermRef::new(bytes).unwrap();
+    // ei reports every atom as `Atom`.
+    assert_eq!(view.tag(), ExtTag::Atom);
+    assert_eq!(view.as_atom().unwrap(), "abc");
+    let mut buf = [0; MAXATOMLEN_UTF8];
+    assert_eq!(view.decoder().decode_atom(&mut buf).unwrap(), "abc");
+    assert_eq!(view.to_term().unwrap(), Term::Atom("abc".into()));
+  }
+
+  // Views are equal by encoding only.
+  let latin1 = TermRef::new(b"d\x00\x03abc").unwrap();
+  let utf8 = TermRef::new(b"w\x03abc").unwrap();
+  assert_ne!(latin1, utf8);
+  assert_eq!(latin1.to_term().unwrap(), utf8.to_term().unwrap());
+
+  for bytes in [&b"v\x00\x02\xc3\xa9"[..], b"w\x02\xc3\xa9"] {
+    let view = TermRef::new(bytes).unwrap();
+    assert_eq!(view.as_atom().unwrap(), "é");
+    assert_eq!(view.to_term().unwrap(), Term::Atom("é".into()));
+  }
+
+  // Latin-1 atoms can only be borrowed when they are ASCII, but can still be decoded.
+  for bytes in [&b"d\x00\x01\xe9"[..], b"s\x01\xe9"] {
+    let view = TermRef::new(bytes).unwrap();
+    assert!(view.as_atom().is_err());
+    let mut buf = [0; MAXATOMLEN_UTF8];
+    assert_eq!(view.decoder().decode_atom(&mut buf).unwrap(), "é");
+    assert_eq!(view.to_term().unwrap(), Term::Atom("é".into()));
+  }
+
+  let empty = TermRef::new(b"w\x00").unwrap();
+  assert_eq!(empty.as_atom().unwrap(), "");
+}
+
+#[test]
+fn reads_strings_and_binaries() {
+  let string = TermRef::new(b"\x83k\x00\x03abc").unwrap();
+  assert_eq!(string.as_bytes(), b"k\x00\x03abc");
+  assert_eq!(string.as_string().unwrap(), b"abc");
+  assert!(string.as_binary().is_err());
+  assert!(string.list().is_err());
+
+  let nil = TermRef::new(b"j").unwrap();
+  assert_eq!(nil.as_string().unwrap(), b"");
+  assert_eq!(nil.list().unwrap().len(), 0);
+
+  // A list of bytes that is not encoded as a string.
+  let list = TermRef::new(b"l\x00\x00\x00\x01a\x61j").unwrap();
+  assert!(list.as_string().is_err());
+  let mut decoder = list.decoder();
+  let mut buf = [0; 2];
+  assert_eq!(decoder.decode_string(&mut buf).unwrap(), b"a");
+
+  let binary = TermRef::new(b"m\x00\x00\x00\x02xy").unwrap();
+  assert_eq!(binary.as_binary().unwrap(), b"xy");
+  assert!(binary.as_string().is_err());
+  assert!(binary.as_atom().is_err());
+}
+
+#[test]
+fn rejects_trailing_and_truncated_terms() {
+  assert!(TermRef::new(b"").is_err());
+  assert!(TermRef::new(b"\x83").is_err());
+  assert!(TermRef::new(b"a\x01a\x02").is_err());
+  assert!(TermRef::new(b"w\x03ab").is_err());
+  assert!(TermRef::new(b"d\x00\x03ab").is_err());
+  assert!(TermRef::new(b"k\x00\x03ab").is_err());
+  assert!(TermRef::new(b"h\x02a\x01").is_err());
+  assert!(TermRef::new(b"t\x00\x00\x00\x01a\x01").is_err());
+  assert!(TermRef::new(b"l\x00\x00\x00\x01a\x01").is_err());
+  assert!(Decoder::new(b"h\x02a\x01").is_err());
+}
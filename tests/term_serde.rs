@@ -0,0 +1,281 @@
+#![cfg(feature = "serde")]
+
+use ei_sys::{OptionMapping, SerdeConfig, SerdeError, StructMapping, Term};
+use serde::{
+  de::{self, DeserializeOwned, MapAccess, Visitor},
+  Deserialize, Deserializer, Serialize,
+};
+use std::{collections::BTreeMap, fmt};
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename = "point")]
+struct Point {
+  x: i32,
+  label: Option<String>,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct Meters(f64);
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct Marker;
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct Pair(u8, bool);
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+enum Shape {
+  Empty,
+  Circle(u32),
+  Segment(i64, i64),
+  Rect { width: u32, height: u32 },
+}
+
+const CONFIGS: [SerdeConfig; 6] = [
+  config(StructMapping::Map, OptionMapping::Undefined),
+  config(StructMapping::Map, OptionMapping::Nil),
+  config(StructMapping::Tuple, OptionMapping::Undefined),
+  config(StructMapping::Tuple, OptionMapping::Nil),
+  config(StructMapping::Record, OptionMapping::Undefined),
+  config(StructMapping::Record, OptionMapping::Nil),
+];
+
+const fn config(structs: StructMapping, none: OptionMapping) -> SerdeConfig {
+  SerdeConfig { structs, none }
+}
+
+fn atom(name: &str) -> Term {
+  Term::Atom(name.to_owned())
+}
+
+/// Serializes `value` with `config`, checks that it is encoded as `expected`, and that it
+/// deserializes back to `value`.
+fn round_trip<T>(config: SerdeConfig, value: &T, expected: Term)
+where
+  T: Serialize + DeserializeOwned + PartialEq + fmt::Debug,
+{
+  let buf = config.to_x_buff(value).unwrap();
+  assert_eq!(Term::from_x_buff(&buf).unwrap(), expected, "{:?}", config);
+  assert_eq!(
+    config.from_bytes::<T>(buf.as_bytes()).unwrap(),
+    *value,
+    "{:?}",
+    config
+  );
+}
+
+#[test]
+fn round_trips_primitives() {
+  for config in CONFIGS {
+    round_trip(config, &true, atom("true"));
+    round_trip(config, &-7i8, Term::Integer(-7));
+    round_trip(config, &u64::from(u32::MAX), Term::Integer(u32::MAX.into()));
+    round_trip(config, &1.5f64, Term::Float(1.5));
+    round_trip(config, &'é', Term::Integer(0xe9));
+    round_trip(config, &"hi".to_owned(), Term::Binary(b"hi".to_vec()));
+    round_trip(config, &(), Term::Tuple(vec![]));
+    round_trip(
+      config,
+      &(1u8, "a".to_owned()),
+      Term::Tuple(vec![Term::Integer(1), Term::Binary(b"a".to_vec())]),
+    );
+    round_trip(
+      config,
+      &vec![1u32, 2],
+      Term::List(vec![Term::Integer(1), Term::Integer(2)]),
+    );
+    round_trip(config, &Vec::<u32>::new(), Term::List(vec![]));
+    let mut map = BTreeMap::new();
+    map.insert(1u8, false);
+    round_trip(
+      config,
+      &map,
+      Term::Map(vec![(Term::Integer(1), atom("false"))]),
+    );
+  }
+}
+
+#[test]
+fn round_trips_options() {
+  for config in CONFIGS {
+    let none = match config.none {
+      OptionMapping::Undefined => atom("undefined"),
+      OptionMapping::Nil => atom("nil"),
+    };
+    round_trip(config, &None::<u8>, none);
+    round_trip(config, &Some(3u8), Term::Integer(3));
+  }
+}
+
+#[test]
+fn round_trips_structs() {
+  for config in CONFIGS {
+    round_trip(config, &Meters(2.0), Term::Float(2.0));
+    round_trip(config, &Marker, atom("Marker"));
+    round_trip(
+      config,
+      &Pair(1, true),
+      Term::Tuple(vec![Term::Integer(1), atom("true")]),
+    );
+
+    for label in [None, Some("p".to_owned())] {
+      let point = Point {
+        x: -1,
+        label: label.clone(),
+      };
+      let label = match label {
+        Some(label) => Term::Binary(label.into_bytes()),
+        None => atom(match config.none {
+          OptionMapping::Undefined => "undefined",
+          OptionMapping::Nil => "nil",
+        }),
+      };
+      let expected = match config.structs {
+        StructMapping::Map => {
+          Term::Map(vec![(atom("x"), Term::Integer(-1)), (atom("label"), label)])
+        }
+        StructMapping::Tuple => Term::Tuple(vec![Term::Integer(-1), label]),
+        StructMapping::Record => Term::Tuple(vec![atom("point"), Term::Integer(-1), label]),
+      };
+      round_trip(config, &point, expected);
+    }
+  }
+}
+
+#[test]
+fn round_trips_enums() {
+  for config in CONFIGS {
+    round_trip(config, &Shape::Empty, atom("Empty"));
+    round_trip(
+      config,
+      &Shape::Circle(4),
+      Term::Tuple(vec![atom("Circle"), Term::Integer(4)]),
+    );
+    round_trip(
+      config,
+      &Shape::Segment(-1, 1),
+      Term::Tuple(vec![atom("Segment"), Term::Integer(-1), Term::Integer(1)]),
+    );
+    let rect = Shape::Rect {
+      width: 2,
+      height: 3,
+    };
+    let expected = match config.structs {
+      StructMapping::Map => Term::Tuple(vec![
+        atom("Rect"),
+        Term::Map(vec![
+          (atom("width"), Term::Integer(2)),
+          (atom("height"), Term::Integer(3)),
+        ]),
+      ]),
+      StructMapping::Tuple | StructMapping::Record => {
+        Term::Tuple(vec![atom("Rect"), Term::Integer(2), Term::Integer(3)])
+      }
+    };
+    round_trip(config, &rect, expected);
+  }
+}
+
+#[test]
+fn rejects_mismatched_terms() {
+  let config = SerdeConfig::default();
+  let encode = |term: &Term| term.to_x_buff().unwrap();
+
+  // A struct missing a field, and a record with the wrong name.
+  let buf = encode(&Term::Map(vec![(atom("label"), atom("undefined"))]));
+  assert!(config.from_bytes::<Point>(buf.as_bytes()).is_err());
+  let record = config_with(StructMapping::Record);
+  let buf = encode(&Term::Tuple(vec![
+    atom("other"),
+    Term::Integer(1),
+    atom("undefined"),
+  ]));
+  assert!(record.from_bytes::<Point>(buf.as_bytes()).is_err());
+
+  // Variants of the wrong arity, and an unknown variant.
+  let buf = encode(&Term::Tuple(vec![atom("Circle")]));
+  assert!(config.from_bytes::<Shape>(buf.as_bytes()).is_err());
+  let buf = encode(&Term::Tuple(vec![atom("Segment"), Term::Integer(1)]));
+  assert!(config.from_bytes::<Shape>(buf.as_bytes()).is_err());
+  let buf = encode(&Term::Tuple(vec![atom("Empty"), Term::Integer(1)]));
+  assert!(config.from_bytes::<Shape>(buf.as_bytes()).is_err());
+  let buf = encode(&atom("Square"));
+  assert!(config.from_bytes::<Shape>(buf.as_bytes()).is_err());
+
+  // A list with more elements than the tuple, and trailing terms.
+  let buf = encode(&Term::List(vec![Term::Integer(1), Term::Integer(2)]));
+  assert!(config.from_bytes::<[u8; 1]>(buf.as_bytes()).is_err());
+  let mut bytes = encode(&Term::Integer(1)).as_bytes().to_vec();
+  bytes.extend_from_slice(&[b'a', 2]);
+  assert!(config.from_bytes::<u8>(&bytes).is_err());
+}
+
+fn config_with(structs: StructMapping) -> SerdeConfig {
+  SerdeConfig {
+    structs,
+    ..SerdeConfig::default()
+  }
+}
+
+/// A value whose visitor asks for more map values than there are entries.
+#[derive(Debug)]
+struct GreedyValues;
+
+impl<'de> Deserialize<'de> for GreedyValues {
+  fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+    struct GreedyVisitor;
+
+    impl<'de> Visitor<'de> for GreedyVisitor {
+      type Value = GreedyValues;
+
+      fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a map")
+      }
+
+      fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<GreedyValues, A::Error> {
+        loop {
+          map.next_value::<de::IgnoredAny>()?;
+        }
+      }
+    }
+
+    deserializer.deserialize_map(GreedyVisitor)
+  }
+}
+
+#[test]
+fn rejects_values_without_keys() {
+  let buf = Term::Map(vec![(Term::Integer(1), Term::Integer(2))])
+    .to_x_buff()
+    .unwrap();
+  assert!(matches!(
+    SerdeConfig::default().from_bytes::<GreedyValues>(buf.as_bytes()),
+    Err(SerdeError::Message(_))
+  ));
+}
+
+/// Returns the bytes of `depth` nested lists of one element, around the empty list.
+fn nested_lists(depth: usize) -> Vec<u8> {
+  let mut bytes = vec![131];
+  for _ in 0..depth {
+    bytes.extend_from_slice(&[108, 0, 0, 0, 1]);
+  }
+  bytes.extend(std::iter::repeat_n(106, depth + 1));
+  bytes
+}
+
+/// Lists of lists, of any depth.
+#[derive(Debug, Deserialize)]
+struct Nested(#[allow(dead_code)] Vec<Nested>);
+
+#[test]
+fn rejects_deep_nesting() {
+  let config = SerdeConfig::default();
+  assert!(config.from_bytes::<Nested>(&nested_lists(100)).is_ok());
+  for depth in [129, 100_000] {
+    assert!(matches!(
+      config.from_bytes::<Nested>(&nested_lists(depth)),
+      Err(SerdeError::Message(_))
+    ));
+  }
+}
@@ -0,0 +1,169 @@
+#![cfg(all(feature = "std", unix))]
+
+use ei_sys::{erlang_pid, CNode, Connection, Error, Received, TermRef, XBuff};
+use std::{
+  io::{Read, Write},
+  os::unix::{io::IntoRawFd, net::UnixStream},
+  thread,
+  time::{Duration, Instant},
+};
+
+const TICKTIME: Duration = Duration::from_millis(200);
+
+/// Returns a connection with ticks enabled, whose other end is a socket that plays the other node.
+fn connect() -> (Connection, UnixStream) {
+  let node = CNode::builder("ticks", "cookie")
+    .host_name("localhost")
+    .build()
+    .unwrap();
+  let (ours, theirs) = UnixStream::pair().unwrap();
+  let mut connection = unsafe { Connection::from_raw_fd(node, ours.into_raw_fd()) };
+  connection.set_net_ticktime(Some(TICKTIME));
+  (connection, theirs)
+}
+
+/// Returns a frame holding a `SEND` to `to` of the atom `ok`, as Erlang nodes send messages.
+fn message(to: &erlang_pid) -> Vec<u8> {
+  let mut payload = XBuff::with_version().unwrap();
+  payload.encoder().atom("ok").unwrap();
+  frame(to, &payload)
+}
+
+/// Returns a frame holding a `SEND` to `to` of `payload`.
+fn frame(to: &erlang_pid, payload: &XBuff) -> Vec<u8> {
+  let mut control = XBuff::with_version().unwrap();
+  control
+    .encoder()
+    .tuple(3, |e| e.long(2)?.atom("")?.pid(to).map(drop))
+    .unwrap();
+
+  let len = 1 + control.byte_len() + payload.byte_len();
+  let mut frame = (len as u32).to_be_bytes().to_vec();
+  frame.push(b'p');
+  frame.extend_from_slice(control.as_bytes());
+  frame.extend_from_slice(payload.as_bytes());
+  frame
+}
+
+#[test]
+fn silent_peers_time_out_and_receive_ticks() {
+  let (mut connection, mut peer) = connect();
+  let start = Instant::now();
+  assert!(matches!(connection.receive(), Err(Error::NetTickTimeout)));
+  assert!(start.elapsed() >= TICKTIME);
+
+  peer
+    .set_read_timeout(Some(Duration::from_millis(10)))
+    .unwrap();
+  let mut sent = Vec::new();
+  let mut buf = [0; 64];
+  while let Ok(n) = peer.read(&mut buf) {
+    if n == 0 {
+      break;
+    }
+    sent.extend_from_slice(&buf[..n]);
+  }
+  assert!(sent.len() >= 4);
+  assert!(sent.len() % 4 == 0 && sent.iter().all(|&b| b == 0));
+}
+
+#[test]
+fn ticks_are_not_returned() {
+  let (mut connection, mut peer) = connect();
+  let frame = message(connection.node().pid());
+  let writer = thread::spawn(move || {
+    for _ in 0..3 {
+      peer.write_all(&[0; 4]).unwrap();
+      thread::sleep(TICKTIME / 2);
+    }
+    peer.write_all(&frame).unwrap();
+    peer
+  });
+
+  match connection.receive_timeout(TICKTIME * 10).unwrap() {
+    Received::Message { msg, .. } => assert_eq!(msg.msgtype, 2),
+    Received::Tick => panic!("received a tick"),
+  }
+  writer.join().unwrap();
+}
+
+#[test]
+fn messages_split_across_slices_are_received_whole() {
+  let (mut connection, mut peer) = connect();
+  let frame = message(connection.node().pid());
+  let writer = thread::spawn(move || {
+    // The rest arrives after a few slices of a quarter of the tick time.
+    let (head, tail) = frame.split_at(6);
+    peer.write_all(head).unwrap();
+    thread::sleep(TICKTIME / 2);
+    peer.write_all(tail).unwrap();
+    peer
+  });
+
+  match connection.receive_timeout(TICKTIME * 10).unwrap() {
+    Received::Message { msg, .. } => assert_eq!(msg.msgtype, 2),
+    Received::Tick => panic!("received a tick"),
+  }
+  writer.join().unwrap();
+}
+
+#[test]
+fn unfinished_messages_time_out() {
+  let (mut connection, mut peer) = connect();
+  let frame = message(connection.node().pid());
+  peer.write_all(&frame[..6]).unwrap();
+  assert!(matches!(
+    connection.receive_timeout(TICKTIME * 10),
+    Err(Error::NetTickTimeout)
+  ));
+}
+
+#[test]
+fn deadlines_are_kept_in_the_middle_of_messages() {
+  let (mut connection, mut peer) = connect();
+  let frame = message(connection.node().pid());
+  peer.write_all(&frame[..6]).unwrap();
+  let start = Instant::now();
+  assert!(matches!(
+    connection.receive_timeout(TICKTIME / 4),
+    Err(Error::Timeout)
+  ));
+  assert!(start.elapsed() < TICKTIME);
+
+  // What was received of the message is kept.
+  peer.write_all(&frame[6..]).unwrap();
+  match connection.receive_timeout(TICKTIME).unwrap() {
+    Received::Message { msg, .. } => assert_eq!(msg.msgtype, 2),
+    Received::Tick => panic!("received a tick"),
+  }
+}
+
+#[test]
+fn messages_larger_than_the_socket_buffer_are_received() {
+  let (mut connection, mut peer) = connect();
+  connection.set_net_ticktime(None);
+  let bytes = vec![7; 4 << 20];
+  let mut payload = XBuff::with_version().unwrap();
+  payload.encoder().binary(&bytes).unwrap();
+  let frame = frame(connection.node().pid(), &payload);
+  let writer = thread::spawn(move || {
+    peer.write_all(&frame).unwrap();
+    peer
+  });
+
+  let start = Instant::now();
+  let payload = loop {
+    match connection.try_receive() {
+      Ok(Received::Message { payload, .. }) => break payload,
+      Ok(Received::Tick) => panic!("received a tick"),
+      Err(Error::WouldBlock) => {
+        assert!(start.elapsed() < TICKTIME * 50, "never received whole");
+        thread::sleep(Duration::from_millis(1));
+      }
+      Err(error) => panic!("{}", error),
+    }
+  };
+  let payload = TermRef::from_x_buff(&payload).unwrap();
+  assert_eq!(payload.as_binary().unwrap(), &bytes[..]);
+  writer.join().unwrap();
+}